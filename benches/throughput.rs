@@ -0,0 +1,105 @@
+//! Submission throughput and dispatch latency under concurrent load.
+//!
+//! `cargo bench --bench throughput` submits `BENCH_JOBS` jobs (default 5000)
+//! from `BENCH_CLIENTS` concurrent clients (default 64) spread over eight
+//! tenants, then waits for all of them to finish. It reports submissions per
+//! second and the time from `submitted_at` to `started_at` per job.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+use time::OffsetDateTime;
+
+use common::{Harness, config, status_name, tenant};
+
+const TENANTS: usize = 8;
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+#[tokio::main]
+async fn main() {
+    let jobs = env_or("BENCH_JOBS", 5000);
+    let clients = env_or("BENCH_CLIENTS", 64);
+
+    let tenants: Vec<Value> = (0..TENANTS)
+        .map(|i| tenant(&format!("tenant{}", i), &[], 8))
+        .collect();
+    let harness = Arc::new(Harness::start(
+        &config(jobs, &[8; TENANTS], "127.0.0.1:1"),
+        json!({ "tenants": tenants }),
+    ));
+    harness.install_example("simple-compute");
+    harness.wait_ready().await;
+
+    let started = Instant::now();
+    let mut submitters = tokio::task::JoinSet::new();
+    for client in 0..clients {
+        let harness = harness.clone();
+        let count = jobs / clients + usize::from(client < jobs % clients);
+        submitters.spawn(async move {
+            let tenant_id = format!("tenant{}", client % TENANTS);
+            let mut ids = Vec::with_capacity(count);
+            for _ in 0..count {
+                ids.push(harness.accepted(&tenant_id, "simple-compute", &[]).await);
+            }
+            ids
+        });
+    }
+    let mut ids = Vec::with_capacity(jobs);
+    while let Some(submitted) = submitters.join_next().await {
+        ids.extend(submitted.unwrap());
+    }
+    let submit_time = started.elapsed();
+
+    let listed = loop {
+        let (_, body) = harness.get("/jobs/list").await;
+        let listed = body["jobs"].as_array().unwrap().clone();
+        if listed
+            .iter()
+            .all(|job| !matches!(status_name(job), "queued" | "running"))
+        {
+            break listed;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    let total_time = started.elapsed();
+
+    let mut latencies: Vec<Duration> = listed
+        .iter()
+        .filter_map(|job| {
+            let submitted: OffsetDateTime =
+                serde_json::from_value(job["submitted_at"].clone()).ok()?;
+            let started: OffsetDateTime = serde_json::from_value(job["started_at"].clone()).ok()?;
+            (started - submitted).try_into().ok()
+        })
+        .collect();
+    latencies.sort();
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+
+    println!("jobs:            {} from {} clients", ids.len(), clients);
+    println!(
+        "submissions:     {:.0}/s ({:.2?})",
+        ids.len() as f64 / submit_time.as_secs_f64(),
+        submit_time
+    );
+    println!(
+        "completed:       {:.0}/s ({:.2?})",
+        ids.len() as f64 / total_time.as_secs_f64(),
+        total_time
+    );
+    println!(
+        "dispatch latency p50 {:.2?}, p99 {:.2?}, max {:.2?}",
+        percentile(50),
+        percentile(99),
+        percentile(100)
+    );
+}
@@ -0,0 +1,237 @@
+// Polls the controller's JSON API and renders the dashboard.
+
+const REFRESH_MS = 5000;
+const MAX_FAILURES = 20;
+const MAX_HISTORY = 500;
+const TENANT_COLORS = ["#2b6cd9", "#d9822b", "#2e7d32", "#8e44ad", "#c0392b", "#16a085", "#7f8c8d", "#b7950b"];
+
+let jobs = [];
+let openJob = null;
+
+// `time` serializes `OffsetDateTime` as
+// [year, ordinal day, hour, minute, second, nanosecond, offset h, m, s].
+function toDate(value) {
+  if (!value) return null;
+  const [year, ordinal, hour, minute, second, nanos, offH, offM, offS] = value;
+  const utc = Date.UTC(year, 0, ordinal, hour, minute, second, Math.floor(nanos / 1e6));
+  return new Date(utc - ((offH * 60 + offM) * 60 + offS) * 1000);
+}
+
+function formatSeconds(seconds) {
+  if (seconds < 1) return `${Math.round(seconds * 1000)} ms`;
+  if (seconds < 60) return `${seconds.toFixed(1)} s`;
+  const minutes = Math.floor(seconds / 60);
+  if (minutes < 60) return `${minutes}m ${Math.floor(seconds % 60)}s`;
+  return `${Math.floor(minutes / 60)}h ${minutes % 60}m`;
+}
+
+function formatTime(date) {
+  return date ? date.toLocaleTimeString() : "";
+}
+
+// Statuses are "queued", "running", "cancelled", "expired" or {"finished": ...} / {"failed": ...}.
+function statusName(status) {
+  return typeof status === "string" ? status : Object.keys(status)[0];
+}
+
+function failureReason(status) {
+  return typeof status === "object" && status.failed !== undefined ? status.failed : "";
+}
+
+function el(tag, attrs = {}, ...children) {
+  const node = document.createElement(tag);
+  for (const [key, value] of Object.entries(attrs)) {
+    if (key === "class") node.className = value;
+    else if (key.startsWith("on")) node.addEventListener(key.slice(2), value);
+    else node.setAttribute(key, value);
+  }
+  for (const child of children) {
+    node.append(child instanceof Node ? child : document.createTextNode(String(child)));
+  }
+  return node;
+}
+
+function jobRow(job, ...cells) {
+  return el("tr", { class: "job", onclick: () => showJob(job.job_id) },
+    el("td", {}, el("code", {}, job.job_id.slice(0, 8))),
+    ...cells.map((cell) => (cell instanceof Node && cell.tagName === "TD" ? cell : el("td", {}, cell))));
+}
+
+async function getJson(path) {
+  const response = await fetch(path);
+  if (!response.ok) throw new Error(`${path}: ${response.status}`);
+  return response.json();
+}
+
+function renderQueue(stats) {
+  const svg = document.getElementById("queue-chart");
+  svg.replaceChildren();
+  const samples = stats.samples;
+  if (samples.length === 0) return;
+
+  const width = 600;
+  const height = 160;
+  const peak = Math.max(1, ...samples.map((s) => Math.max(s.queued, s.running)));
+  const step = samples.length > 1 ? width / (samples.length - 1) : width;
+  const ns = "http://www.w3.org/2000/svg";
+
+  for (const series of ["queued", "running"]) {
+    const points = samples
+      .map((s, i) => `${(i * step).toFixed(1)},${(height - 4 - (s[series] / peak) * (height - 8)).toFixed(1)}`)
+      .join(" ");
+    const line = document.createElementNS(ns, "polyline");
+    line.setAttribute("points", points);
+    line.setAttribute("class", series);
+    line.setAttribute("fill", "none");
+    line.setAttribute("stroke-width", "2");
+    line.setAttribute("vector-effect", "non-scaling-stroke");
+    svg.append(line);
+  }
+
+  const last = samples[samples.length - 1];
+  const minutes = Math.round((samples.length * stats.interval_secs) / 60);
+  document.getElementById("queue-now").textContent =
+    ` · now ${last.queued} queued, ${last.running} running · peak ${peak} over the last ${minutes} min`;
+}
+
+function renderSlots(usage) {
+  const tenants = [...new Set(usage.reservations.map((r) => r.tenant_id))].sort();
+  const color = (tenant) => TENANT_COLORS[tenants.indexOf(tenant) % TENANT_COLORS.length];
+
+  const devices = document.getElementById("devices");
+  devices.replaceChildren(...usage.devices.map((device) => {
+    const slices = device.capacity.compute_slices;
+    const byTenant = new Map();
+    for (const reservation of usage.reservations) {
+      for (const placement of reservation.placements) {
+        if (placement.device_id !== device.device_id) continue;
+        byTenant.set(reservation.tenant_id,
+          (byTenant.get(reservation.tenant_id) || 0) + placement.shape.compute_slices);
+      }
+    }
+
+    const bar = el("div", { class: "bar" });
+    for (const [tenant, used] of byTenant) {
+      bar.append(el("div", {
+        title: `${tenant}: ${used} slices`,
+        style: `width: ${(used / slices) * 100}%; background: ${color(tenant)}`,
+      }));
+    }
+
+    return el("div", { class: "device" },
+      el("div", {},
+        el("strong", {}, device.device_id),
+        ` ${device.used.compute_slices}/${slices} slices, ${device.used.memory_gb}/${device.capacity.memory_gb} GB`),
+      bar);
+  }));
+
+  const legend = document.getElementById("tenant-legend");
+  legend.replaceChildren(...tenants.flatMap((tenant) => [
+    el("span", { class: "swatch", style: `background: ${color(tenant)}` }),
+    ` ${tenant}`,
+  ]));
+  if (tenants.length === 0) legend.textContent = "No reservations";
+}
+
+function renderRunning(now) {
+  const running = jobs.filter((job) => statusName(job.status) === "running");
+  document.getElementById("running").replaceChildren(...running.map((job) => {
+    const started = toDate(job.started_at);
+    const elapsed = started ? formatSeconds((now - started) / 1000) : "";
+    return jobRow(job, job.tenant_id, job.module_id, job.backend, elapsed);
+  }));
+}
+
+function renderFailures() {
+  const failures = jobs
+    .filter((job) => statusName(job.status) === "failed")
+    .sort((a, b) => toDate(b.finished_at) - toDate(a.finished_at))
+    .slice(0, MAX_FAILURES);
+  document.getElementById("failures").replaceChildren(...failures.map((job) =>
+    jobRow(job, job.tenant_id, job.module_id, formatTime(toDate(job.finished_at)),
+      el("td", { class: "reason" }, failureReason(job.status)))));
+}
+
+function renderHistory() {
+  const terms = document.getElementById("search").value.toLowerCase().split(/\s+/).filter(Boolean);
+  const matches = jobs
+    .filter((job) => {
+      const text = [job.job_id, job.tenant_id, job.module_id, job.backend, statusName(job.status)]
+        .join(" ").toLowerCase();
+      return terms.every((term) => text.includes(term));
+    })
+    .slice()
+    .reverse()
+    .slice(0, MAX_HISTORY);
+
+  document.getElementById("history").replaceChildren(...matches.map((job) => {
+    const status = statusName(job.status);
+    const started = toDate(job.started_at);
+    const finished = toDate(job.finished_at);
+    const duration = started && finished ? formatSeconds((finished - started) / 1000) : "";
+    return jobRow(job, job.tenant_id, job.module_id, job.backend,
+      el("td", { class: `status-${status}` }, status),
+      formatTime(toDate(job.submitted_at)), duration);
+  }));
+}
+
+function outputText(job) {
+  if (!job.result) {
+    const reason = failureReason(job.status);
+    return reason ? `failed: ${reason}` : `(${statusName(job.status)})`;
+  }
+  let text = new TextDecoder().decode(new Uint8Array(job.result.output));
+  if (job.result.tensors) {
+    text += `\n\ntensors: ${JSON.stringify(job.result.tensors, null, 2)}`;
+  }
+  return text;
+}
+
+async function showJob(jobId) {
+  openJob = jobId;
+  const detail = document.getElementById("detail");
+  detail.hidden = false;
+  document.getElementById("detail-id").textContent = jobId;
+  document.getElementById("detail-json").href = `/jobs/${jobId}`;
+  document.getElementById("detail-logs-link").href = `/jobs/${jobId}/logs`;
+
+  try {
+    const [job, logs] = await Promise.all([getJson(`/jobs/${jobId}`), getJson(`/jobs/${jobId}/logs`)]);
+    if (openJob !== jobId) return;
+    document.getElementById("detail-output").textContent = outputText(job);
+    document.getElementById("detail-logs").textContent = logs.logs.join("\n") || "(no logs)";
+  } catch (error) {
+    document.getElementById("detail-output").textContent = error.message;
+    document.getElementById("detail-logs").textContent = "";
+  }
+  detail.scrollIntoView({ behavior: "smooth" });
+}
+
+async function refresh() {
+  try {
+    const [list, usage, stats] = await Promise.all([
+      getJson("/jobs/list"),
+      getJson("/gpu"),
+      getJson("/stats/queue"),
+    ]);
+    jobs = list.jobs;
+    const now = new Date();
+    renderQueue(stats);
+    renderSlots(usage);
+    renderRunning(now);
+    renderFailures();
+    renderHistory();
+    document.getElementById("updated").textContent = `updated ${formatTime(now)}`;
+  } catch (error) {
+    document.getElementById("updated").textContent = `refresh failed: ${error.message}`;
+  }
+}
+
+document.getElementById("search").addEventListener("input", renderHistory);
+document.getElementById("detail-close").addEventListener("click", () => {
+  openJob = null;
+  document.getElementById("detail").hidden = true;
+});
+
+refresh();
+setInterval(refresh, REFRESH_MS);
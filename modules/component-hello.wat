@@ -0,0 +1,73 @@
+(component
+  ;; World imports (require the "logging" and "gpu.compute" capabilities)
+  (import "gpu-sandbox:guest/logging@0.1.0" (instance $logging
+    (export "log" (func (param "message" string) (result (result (error string)))))))
+  (import "gpu-sandbox:guest/compute@0.1.0" (instance $compute
+    (export "compute" (func (param "value" s32) (result s32)))))
+
+  ;; Memory and a bump allocator for the canonical ABI
+  (core module $Libc
+    (memory (export "memory") 1)
+    (global $heap (mut i32) (i32.const 1024))
+    (func (export "realloc") (param i32 i32) (param $align i32) (param $size i32) (result i32)
+      (local $ptr i32)
+      (local.set $ptr
+        (i32.and
+          (i32.add (global.get $heap) (i32.sub (local.get $align) (i32.const 1)))
+          (i32.sub (i32.const 0) (local.get $align))))
+      (global.set $heap (i32.add (local.get $ptr) (local.get $size)))
+      (local.get $ptr)))
+  (core instance $libc (instantiate $Libc))
+  (alias core export $libc "memory" (core memory $memory))
+  (alias core export $libc "realloc" (core func $realloc))
+
+  (core func $log (canon lower (func $logging "log")
+    (memory $memory) (realloc $realloc)))
+  (core func $compute (canon lower (func $compute "compute")))
+
+  (core module $Main
+    (import "libc" "memory" (memory 1))
+    (import "host" "log" (func $log (param i32 i32 i32)))
+    (import "host" "compute" (func $compute (param i32) (result i32)))
+
+    (data (i32.const 16) "Hello from a component!")     ;; 23 bytes
+    (data (i32.const 48) "unexpected compute result")   ;; 25 bytes
+
+    ;; run(input: job-input) -> result<job-output, string>
+    ;; Params are the four (ptr, len) strings of job-input; the result is
+    ;; written at offset 80 as discriminant + (ptr, len).
+    (func (export "run") (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+      (call $log (i32.const 16) (i32.const 23) (i32.const 64))
+
+      (if (i32.ne (call $compute (i32.const 21)) (i32.const 42))
+        (then
+          (i32.store8 (i32.const 80) (i32.const 1))
+          (i32.store (i32.const 84) (i32.const 48))
+          (i32.store (i32.const 88) (i32.const 25))
+          (return (i32.const 80))))
+
+      ;; Echo the payload back as the output
+      (i32.store8 (i32.const 80) (i32.const 0))
+      (i32.store (i32.const 84) (local.get 6))
+      (i32.store (i32.const 88) (local.get 7))
+      (i32.const 80)))
+
+  (core instance $main (instantiate $Main
+    (with "libc" (instance $libc))
+    (with "host" (instance
+      (export "log" (func $log))
+      (export "compute" (func $compute))))))
+
+  (type $job-input' (record
+    (field "job-id" string)
+    (field "tenant-id" string)
+    (field "module-id" string)
+    (field "payload" string)))
+  (type $job-output' (record (field "data" (list u8))))
+  (export $job-input "job-input" (type $job-input'))
+  (export $job-output "job-output" (type $job-output'))
+
+  (func (export "run") (param "input" $job-input) (result (result $job-output (error string)))
+    (canon lift (core func $main "run")
+      (memory $memory) (realloc $realloc)))
+)
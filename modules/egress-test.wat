@@ -0,0 +1,26 @@
+(module
+  ;; Import the http_post host function (requires "network.egress" capability)
+  ;; (url_ptr, url_len, headers_ptr, headers_len, body_ptr, body_len, resp_ptr, resp_cap) -> status
+  (import "env" "http_post"
+    (func $http_post (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  ;; URL, headers and body laid out in the data section
+  (data (i32.const 0) "http://127.0.0.1:8080/ingest")
+  (data (i32.const 64) "Content-Type: text/plain")
+  (data (i32.const 128) "Hello from WASM!")
+
+  ;; Export a 'run' function returning the HTTP status (or a negative error)
+  (func $run (export "run") (result i32)
+    i32.const 0    ;; url_ptr
+    i32.const 28   ;; url_len
+    i32.const 64   ;; headers_ptr
+    i32.const 24   ;; headers_len
+    i32.const 128  ;; body_ptr
+    i32.const 16   ;; body_len
+    i32.const 256  ;; resp_ptr: u32 body length, then body
+    i32.const 1024 ;; resp_cap
+    call $http_post
+  )
+)
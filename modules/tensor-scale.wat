@@ -0,0 +1,68 @@
+;; Doubles the f32 input tensor "x" and outputs it as "y". Returns the number
+;; of elements, or the negative status of a failed tensor call.
+(module
+  (import "env" "tensor_info" (func $tensor_info (param i32 i32 i32) (result i32)))
+  (import "env" "tensor_output"
+    (func $tensor_output (param i32 i32 i32 i32 i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+
+  (data (i32.const 0) "x")
+  (data (i32.const 8) "y")
+
+  ;; Bump allocator handing out 16-byte aligned blocks; the host stages input
+  ;; tensors through it before `run`
+  (func $alloc (export "tensor_alloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local $end i32)
+    (local.set $ptr (global.get $heap))
+    (local.set $end
+      (i32.and (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 15))
+               (i32.const -16)))
+    (block $done
+      (loop $grow
+        (br_if $done
+          (i32.le_u (local.get $end) (i32.shl (memory.size) (i32.const 16))))
+        (if (i32.eq (memory.grow (i32.const 1)) (i32.const -1))
+          (then (return (i32.const -1))))
+        (br $grow)))
+    (global.set $heap (local.get $end))
+    (local.get $ptr))
+
+  (func (export "run") (result i32)
+    (local $status i32)
+    (local $src i32)
+    (local $len i32)
+    (local $dst i32)
+    (local $i i32)
+
+    ;; Descriptor at 64: data ptr, byte length, dtype, rank, dims[8]
+    (local.set $status (call $tensor_info (i32.const 0) (i32.const 1) (i32.const 64)))
+    (if (local.get $status) (then (return (local.get $status))))
+    (if (i32.load (i32.const 72)) (then (return (i32.const -10))))
+
+    ;; Read the input in place
+    (local.set $src (i32.load (i32.const 64)))
+    (local.set $len (i32.load (i32.const 68)))
+    (local.set $dst (call $alloc (local.get $len)))
+
+    (block $done
+      (loop $scale
+        (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+        (f32.store
+          (i32.add (local.get $dst) (local.get $i))
+          (f32.mul (f32.load (i32.add (local.get $src) (local.get $i))) (f32.const 2)))
+        (local.set $i (i32.add (local.get $i) (i32.const 4)))
+        (br $scale)))
+
+    ;; Same dtype and shape as the input
+    (local.set $status
+      (call $tensor_output
+        (i32.const 8) (i32.const 1)
+        (i32.const 0)
+        (i32.const 80) (i32.load (i32.const 76))
+        (local.get $dst) (local.get $len)))
+    (if (local.get $status) (then (return (local.get $status))))
+
+    (i32.shr_u (local.get $len) (i32.const 2))))
@@ -0,0 +1,42 @@
+(module
+  ;; Device API imports (require the "gpu.compute" capability)
+  (import "env" "gpu_alloc" (func $alloc (param i32) (result i32)))
+  (import "env" "gpu_write" (func $write (param i32 i32 i32) (result i32)))
+  (import "env" "gpu_read" (func $read (param i32 i32 i32) (result i32)))
+  (import "env" "gpu_launch" (func $launch (param i32 i32 i32 i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  ;; Input vectors
+  (data (i32.const 0) "\00\00\80\3f\00\00\00\40\00\00\40\40\00\00\80\40")   ;; [1, 2, 3, 4]
+  (data (i32.const 16) "\00\00\20\41\00\00\a0\41\00\00\f0\41\00\00\20\42")  ;; [10, 20, 30, 40]
+  (data (i32.const 64) "vadd")
+  (data (i32.const 72) "reduce_sum")
+
+  ;; Handles are stored at 128.. for gpu_launch
+  (func $run (export "run") (result i32)
+    (local $a i32) (local $b i32) (local $sum i32) (local $total i32)
+    (local.set $a (call $alloc (i32.const 4)))
+    (local.set $b (call $alloc (i32.const 4)))
+    (local.set $sum (call $alloc (i32.const 4)))
+    (local.set $total (call $alloc (i32.const 1)))
+
+    (drop (call $write (local.get $a) (i32.const 0) (i32.const 4)))
+    (drop (call $write (local.get $b) (i32.const 16) (i32.const 4)))
+
+    ;; sum = a + b
+    (i32.store (i32.const 128) (local.get $a))
+    (i32.store (i32.const 132) (local.get $b))
+    (i32.store (i32.const 136) (local.get $sum))
+    (drop (call $launch (i32.const 64) (i32.const 4) (i32.const 128) (i32.const 3) (i32.const 0) (i32.const 0)))
+
+    ;; total = reduce_sum(sum)
+    (i32.store (i32.const 128) (local.get $sum))
+    (i32.store (i32.const 132) (local.get $total))
+    (drop (call $launch (i32.const 72) (i32.const 10) (i32.const 128) (i32.const 2) (i32.const 0) (i32.const 0)))
+
+    ;; Copy the total back and return it: 110
+    (drop (call $read (local.get $total) (i32.const 256) (i32.const 1)))
+    (i32.trunc_f32_s (f32.load (i32.const 256)))
+  )
+)
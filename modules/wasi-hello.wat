@@ -0,0 +1,46 @@
+(module
+  ;; WASI preview1 imports (require the "wasi" capability)
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit"
+    (func $proc_exit (param i32)))
+
+  (memory (export "memory") 1)
+
+  ;; Strings
+  (data (i32.const 64) "Hello from WASI!\n")      ;; 17 bytes
+  (data (i32.const 96) "scratch ok\n")            ;; 11 bytes
+  (data (i32.const 112) "no scratch dir\n")       ;; 15 bytes
+  (data (i32.const 128) "out.txt")                ;; 7 bytes
+
+  ;; Writes len bytes at ptr to fd through the iovec at offset 0
+  (func $write (param $fd i32) (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 0) (local.get $ptr))
+    (i32.store (i32.const 4) (local.get $len))
+    (drop (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8)))
+  )
+
+  ;; WASI command entry point
+  (func $start (export "_start")
+    ;; stdout becomes the job output
+    (call $write (i32.const 1) (i32.const 64) (i32.const 17))
+
+    ;; Create out.txt in the preopened scratch dir (fd 3, needs "wasi.fs")
+    (if (i32.eqz
+          (call $path_open
+            (i32.const 3) (i32.const 0)
+            (i32.const 128) (i32.const 7)
+            (i32.const 1)                 ;; O_CREAT
+            (i64.const 0x1fffffff) (i64.const 0x1fffffff)
+            (i32.const 0) (i32.const 16)))
+      (then
+        (call $write (i32.load (i32.const 16)) (i32.const 64) (i32.const 17))
+        (call $write (i32.const 2) (i32.const 96) (i32.const 11)))
+      (else
+        (call $write (i32.const 2) (i32.const 112) (i32.const 15))))
+
+    (call $proc_exit (i32.const 0))
+  )
+)
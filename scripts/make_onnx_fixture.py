@@ -0,0 +1,88 @@
+#!/usr/bin/env python3
+"""Writes a tiny ONNX model without needing the onnx package.
+
+    y = Relu(x @ W + b), x: float[1, 2], W: [[1, -1], [2, 1]], b: [0, 1]
+
+Usage: scripts/make_onnx_fixture.py tiny-dense.onnx
+Upload: curl -H "Authorization: Bearer $ADMIN_TOKEN" \
+    --data-binary @tiny-dense.onnx localhost:3000/models/tiny-dense
+"""
+import struct
+import sys
+
+
+def varint(n):
+    out = b""
+    while True:
+        byte = n & 0x7F
+        n >>= 7
+        if n:
+            out += bytes([byte | 0x80])
+        else:
+            return out + bytes([byte])
+
+
+def field(number, wire_type, payload):
+    key = varint(number << 3 | wire_type)
+    if wire_type == 0:
+        return key + varint(payload)
+    return key + varint(len(payload)) + payload
+
+
+def string(number, text):
+    return field(number, 2, text.encode())
+
+
+def message(number, *parts):
+    return field(number, 2, b"".join(parts))
+
+
+def tensor(name, dims, values):
+    return b"".join(
+        [field(1, 0, d) for d in dims]
+        + [field(2, 0, 1)]  # FLOAT
+        + [field(4, 2, struct.pack(f"<{len(values)}f", *values))]
+        + [string(8, name)]
+    )
+
+
+def value_info(number, name, dims):
+    shape = b"".join(message(1, field(1, 0, d)) for d in dims)
+    tensor_type = field(1, 0, 1) + message(2, shape)
+    return message(number, string(1, name), message(2, message(1, tensor_type)))
+
+
+def node(inputs, output, op_type):
+    return message(
+        1,
+        *[string(1, i) for i in inputs],
+        string(2, output),
+        string(3, f"{op_type.lower()}_0"),
+        string(4, op_type),
+    )
+
+
+graph = b"".join(
+    [
+        node(["x", "W"], "xw", "MatMul"),
+        node(["xw", "b"], "z", "Add"),
+        node(["z"], "y", "Relu"),
+        string(2, "tiny-dense"),
+        message(5, tensor("W", [2, 2], [1.0, -1.0, 2.0, 1.0])),
+        message(5, tensor("b", [2], [0.0, 1.0])),
+        value_info(11, "x", [1, 2]),
+        value_info(12, "y", [1, 2]),
+    ]
+)
+
+model = b"".join(
+    [
+        field(1, 0, 7),  # ir_version
+        string(2, "gpu-sandbox-controller"),
+        message(7, graph),
+        message(8, string(1, ""), field(2, 0, 13)),  # opset 13
+    ]
+)
+
+with open(sys.argv[1] if len(sys.argv) > 1 else "tiny-dense.onnx", "wb") as f:
+    f.write(model)
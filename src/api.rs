@@ -1,36 +1,473 @@
-use std::collections::VecDeque;
+use std::collections::BTreeMap;
+use std::sync::PoisonError;
 
 use crate::domain::{
-    Job, JobErrorResponse, JobListItem, JobListResponse, JobStatus, SubmitJobRequest,
-    SubmitJobResponse,
+    BatchItemResult, BatchMode, CancelFlag, Job, JobErrorResponse, JobListItem, JobListResponse,
+    JobLogsResponse, JobStatus, ListJobsQuery, ModuleUploadResponse, Stage, SubmitBatchRequest,
+    SubmitBatchResponse, SubmitJobRequest, SubmitJobResponse, TenantSummary,
 };
 
+use crate::backend::{BackendError, DEFAULT_BACKEND, ResourceModel};
+use crate::deadline;
+use crate::dispatcher;
+use crate::gpu_manager::{GpuManager, ResourceShape};
+use crate::health::{self, HealthReport};
+use crate::job_store::JobStoreError;
+use crate::model_registry::ModelError;
+use crate::onnx::{self, OnnxError};
+use crate::pipeline;
+use crate::quota::ScopeQuota;
+use crate::sandbox;
+use crate::schedule::{self, Schedule, ScheduleListResponse, SubmitScheduleRequest};
 use crate::state::AppState;
+use crate::stats::{QueueStatsResponse, SAMPLE_INTERVAL};
 use crate::tenant::TenantStatus;
-use axum::{Json, extract::Path, extract::State, http::StatusCode, response::IntoResponse};
-use time::Duration;
+use crate::workflow::{
+    self, SubmitWorkflowRequest, SubmitWorkflowResponse, Workflow, WorkflowStatusResponse,
+    WorkflowStep, WorkflowStepStatus,
+};
+use axum::body::Bytes;
+use axum::http::{HeaderMap, header};
+use axum::{
+    Json, extract::Path, extract::Query, extract::State, http::StatusCode, response::IntoResponse,
+};
+use subtle::ConstantTimeEq;
 use time::OffsetDateTime;
+use tokio::sync::mpsc::error::TrySendError;
 use uuid::Uuid;
 
 pub async fn submit_job(
     State(state): State<AppState>,
     Json(req): Json<SubmitJobRequest>,
 ) -> impl IntoResponse {
+    match submit(&state, req).await {
+        Ok(job_id) => (StatusCode::ACCEPTED, Json(SubmitJobResponse { job_id })).into_response(),
+        Err((status, error)) => (status, Json(error)).into_response(),
+    }
+}
+
+/// Checks and queues one job, as `POST /jobs` does.
+pub(crate) async fn submit(state: &AppState, req: SubmitJobRequest) -> Result<Uuid, Rejection> {
+    accepting_jobs(state)?;
+    let (job, quotas) = prepare_job(state, req).await?;
+    enqueue(state, job, &quotas)
+}
+
+/// Submits several jobs at once. Each job gets the checks of `submit_job`. In
+/// `all_or_nothing` mode nothing is queued unless every job is accepted,
+/// including its rate-limit window and queue space; in `best_effort` mode
+/// each job is queued or rejected on its own.
+pub async fn submit_batch(
+    State(state): State<AppState>,
+    Json(req): Json<SubmitBatchRequest>,
+) -> impl IntoResponse {
+    if let Err((status, error)) = accepting_jobs(&state) {
+        return (status, Json(error)).into_response();
+    }
+
+    if req.jobs.is_empty() || req.jobs.len() > state.max_batch_size {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(JobErrorResponse {
+                error: "invalid_batch".to_string(),
+                message: format!(
+                    "A batch takes 1 to {} jobs, got {}",
+                    state.max_batch_size,
+                    req.jobs.len()
+                ),
+            }),
+        )
+            .into_response();
+    }
+
+    let mut prepared = Vec::with_capacity(req.jobs.len());
+    for job in req.jobs {
+        prepared.push(prepare_job(&state, job).await);
+    }
+
+    let results: Vec<Result<Uuid, Rejection>> = match req.mode {
+        BatchMode::BestEffort => prepared
+            .into_iter()
+            .map(|prepared| prepared.and_then(|(job, quotas)| enqueue(&state, job, &quotas)))
+            .collect(),
+        BatchMode::AllOrNothing => enqueue_all(&state, prepared),
+    };
+
+    let accepted = results.iter().filter(|r| r.is_ok()).count();
+    // A batch with nothing queued answers like the job that sank it would
+    let rejected = results
+        .iter()
+        .filter_map(|r| r.as_ref().err())
+        .find(|(_, error)| error.error != "batch_aborted");
+    let status = match rejected {
+        Some((status, _)) if accepted == 0 => *status,
+        _ => StatusCode::ACCEPTED,
+    };
+    let jobs: Vec<BatchItemResult> = results
+        .into_iter()
+        .map(|result| match result {
+            Ok(job_id) => BatchItemResult {
+                status: StatusCode::ACCEPTED.as_u16(),
+                job_id: Some(job_id),
+                error: None,
+            },
+            Err((status, error)) => BatchItemResult {
+                status: status.as_u16(),
+                job_id: None,
+                error: Some(error),
+            },
+        })
+        .collect();
+
+    (
+        status,
+        Json(SubmitBatchResponse {
+            accepted,
+            rejected: jobs.len() - accepted,
+            jobs,
+        }),
+    )
+        .into_response()
+}
+
+/// Queues every prepared job or none of them. When one is rejected, the
+/// others are reported as aborted.
+fn enqueue_all(
+    state: &AppState,
+    prepared: Vec<Result<(Job, Vec<ScopeQuota>), Rejection>>,
+) -> Vec<Result<Uuid, Rejection>> {
+    if prepared.iter().any(|p| p.is_err()) {
+        return prepared
+            .into_iter()
+            .map(|p| Err(p.err().unwrap_or_else(batch_aborted)))
+            .collect();
+    }
+    let prepared: Vec<(Job, Vec<ScopeQuota>)> = prepared.into_iter().flatten().collect();
+
+    for (i, (job, quotas)) in prepared.iter().enumerate() {
+        if let Err(rejection) = admit(state, job, quotas) {
+            for (job, quotas) in &prepared[..i] {
+                withdraw(state, job.job_id, quotas);
+            }
+            let mut results: Vec<_> = prepared.iter().map(|_| Err(batch_aborted())).collect();
+            results[i] = Err(rejection);
+            return results;
+        }
+    }
+
+    // Queue space for the whole batch, so it can't be cut off half-way
+    let permits = match state.queue.try_reserve_many(prepared.len()) {
+        Ok(permits) => permits,
+        Err(e) => {
+            for (job, quotas) in &prepared {
+                withdraw(state, job.job_id, quotas);
+            }
+            return prepared.iter().map(|_| Err(queue_rejection(e))).collect();
+        }
+    };
+
+    permits
+        .zip(prepared)
+        .map(|(permit, (job, _))| {
+            let job_id = job.job_id;
+            permit.send(job);
+            Ok(job_id)
+        })
+        .collect()
+}
+
+fn batch_aborted() -> Rejection {
+    (
+        StatusCode::CONFLICT,
+        JobErrorResponse {
+            error: "batch_aborted".to_string(),
+            message: "Not queued because another job in the batch was rejected".to_string(),
+        },
+    )
+}
+
+/// Submits a DAG of jobs. Steps are checked like a batch and queued all or
+/// nothing; each step depends on the jobs of the steps it runs `after`.
+pub async fn submit_workflow(
+    State(state): State<AppState>,
+    Json(req): Json<SubmitWorkflowRequest>,
+) -> impl IntoResponse {
+    if let Err((status, error)) = accepting_jobs(&state) {
+        return (status, Json(error)).into_response();
+    }
+
+    let order = if req.steps.len() > state.max_batch_size {
+        Err(format!(
+            "A workflow takes at most {} steps, got {}",
+            state.max_batch_size,
+            req.steps.len()
+        ))
+    } else {
+        workflow::order(&req.steps).map_err(|e| e.to_string())
+    };
+    let order = match order {
+        Ok(order) => order,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(JobErrorResponse {
+                    error: "invalid_workflow".to_string(),
+                    message,
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    let workflow_id = Uuid::new_v4();
+    let tenant_id = req.steps[0].job.tenant_id.clone();
+    let mut steps: Vec<Option<WorkflowStep>> = req.steps.into_iter().map(Some).collect();
+    let mut job_ids = BTreeMap::new();
+    let mut names = Vec::with_capacity(order.len());
+    let mut prepared = Vec::with_capacity(order.len());
+
+    // In dependency order, so parents are known (and queued) before children
+    for i in order {
+        let Some(step) = steps[i].take() else {
+            continue;
+        };
+        let mut result = prepare_job(&state, step.job).await;
+        if let Ok((job, _)) = &mut result {
+            job.workflow_id = Some(workflow_id);
+            job.depends_on
+                .extend(step.after.iter().filter_map(|name| job_ids.get(name)));
+            job_ids.insert(step.name.clone(), job.job_id);
+        }
+        names.push(step.name);
+        prepared.push(result);
+    }
+
+    let results = enqueue_all(&state, prepared);
+    if let Some((name, (status, mut error))) = names
+        .into_iter()
+        .zip(results)
+        .filter_map(|(name, result)| Some((name, result.err()?)))
+        .find(|(_, (_, error))| error.error != "batch_aborted")
+    {
+        error.message = format!("Step {}: {}", name, error.message);
+        return (status, Json(error)).into_response();
+    }
+
+    state.workflows.write().await.insert(
+        workflow_id,
+        Workflow {
+            workflow_id,
+            tenant_id,
+            submitted_at: OffsetDateTime::now_utc(),
+            steps: job_ids.clone(),
+        },
+    );
+    (
+        StatusCode::ACCEPTED,
+        Json(SubmitWorkflowResponse {
+            workflow_id,
+            steps: job_ids,
+        }),
+    )
+        .into_response()
+}
+
+fn accepting_jobs(state: &AppState) -> Result<(), Rejection> {
+    if state.is_shutting_down() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            JobErrorResponse {
+                error: "shutting_down".to_string(),
+                message: "The controller is shutting down and not accepting jobs".to_string(),
+            },
+        ));
+    }
+    Ok(())
+}
+
+/// Why a job spec was turned down: the status and body of the rejection.
+pub(crate) type Rejection = (StatusCode, JobErrorResponse);
+
+/// Validates a job spec against the tenant, its capabilities, the backend and
+/// the quota chain, and builds the queued job with the quotas it's charged
+/// against.
+async fn prepare_job(
+    state: &AppState,
+    req: SubmitJobRequest,
+) -> Result<(Job, Vec<ScopeQuota>), Rejection> {
     let job_id = Uuid::new_v4();
     let submitted_at = OffsetDateTime::now_utc();
 
-    let job = Job {
+    let gang_size = req.gang_size.unwrap_or(1);
+    if gang_size == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JobErrorResponse {
+                error: "invalid_resources".to_string(),
+                message: "gang_size must be at least 1".to_string(),
+            },
+        ));
+    }
+
+    if let Some(deadline) = req.deadline {
+        let earliest = req.not_before.unwrap_or(submitted_at).max(submitted_at);
+        if deadline <= earliest {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                JobErrorResponse {
+                    error: "invalid_deadline".to_string(),
+                    message: format!("Deadline {} is not after {}", deadline, earliest),
+                },
+            ));
+        }
+    }
+
+    if req.stages.len() >= pipeline::MAX_STAGES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JobErrorResponse {
+                error: "invalid_stages".to_string(),
+                message: format!(
+                    "A pipeline runs at most {} stages, got {}",
+                    pipeline::MAX_STAGES,
+                    req.stages.len() + 1
+                ),
+            },
+        ));
+    }
+    // Stage and parent outputs are handed over in the payload
+    let takes_outputs = !req.stages.is_empty() || req.pass_outputs;
+    if takes_outputs && !(req.payload.is_object() || req.payload.is_null()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JobErrorResponse {
+                error: "invalid_payload".to_string(),
+                message: "Pipeline and pass_outputs jobs need an object or null payload"
+                    .to_string(),
+            },
+        ));
+    }
+
+    // `module_id` is the first stage of a pipeline
+    let stages = if req.stages.is_empty() {
+        Vec::new()
+    } else {
+        std::iter::once(req.module_id.clone())
+            .chain(req.stages)
+            .map(Stage::new)
+            .collect()
+    };
+
+    let backend_name = req
+        .backend
+        .clone()
+        .unwrap_or_else(|| DEFAULT_BACKEND.to_string());
+    let Some(backend) = state.backends.get(&backend_name) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JobErrorResponse {
+                error: "unknown_backend".to_string(),
+                message: format!("Backend {} not known", backend_name),
+            },
+        ));
+    };
+
+    let resolved = match backend.resource_model() {
+        ResourceModel::GpuPartition => {
+            let gpu_manager = GpuManager::lock(&state.gpu_manager);
+            // Each partition takes at least one slice
+            let total_slices = gpu_manager.total_capacity().compute_slices;
+            if gang_size > total_slices {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    JobErrorResponse {
+                        error: "unsatisfiable_resources".to_string(),
+                        message: format!(
+                            "gang_size {} exceeds the {} compute slices of all devices",
+                            gang_size, total_slices
+                        ),
+                    },
+                ));
+            }
+            gpu_manager
+                .resolve(req.resources.as_ref())
+                .map(|(profile, shape)| {
+                    let fits = gpu_manager.can_ever_fit(&shape, gang_size);
+                    (profile, shape, fits)
+                })
+        }
+        ResourceModel::HostCpu => {
+            if req.resources.is_some() || gang_size > 1 {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    JobErrorResponse {
+                        error: "invalid_resources".to_string(),
+                        message: format!(
+                            "Backend {} runs on the host CPU and takes no GPU resources",
+                            backend_name
+                        ),
+                    },
+                ));
+            }
+            Ok((None, ResourceShape::default(), true))
+        }
+    };
+
+    let (profile, resources) = match resolved {
+        Ok((profile, shape, true)) => (profile, shape),
+        Ok((_, shape, false)) => {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                JobErrorResponse {
+                    error: "unsatisfiable_resources".to_string(),
+                    message: format!(
+                        "The GPU devices can't host {} partition(s) of {:?}",
+                        gang_size, shape
+                    ),
+                },
+            ));
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                JobErrorResponse {
+                    error: "invalid_resources".to_string(),
+                    message: e.to_string(),
+                },
+            ));
+        }
+    };
+
+    let mut job = Job {
         job_id,
         tenant_id: req.tenant_id,
+        project_id: req.project_id,
         module_id: req.module_id,
         payload: req.payload,
+        tensors: req.tensors,
+        output_encoding: req.output_encoding,
         capabilities: req.capabilities,
+        backend: backend_name,
+        profile,
+        resources,
+        gang_size,
+        quota_scopes: Vec::new(),
+        depends_on: req.depends_on,
+        on_parent_failure: req.on_parent_failure,
+        pass_outputs: req.pass_outputs,
+        workflow_id: None,
+        stages,
+        input: Vec::new(),
+        not_before: req.not_before,
+        deadline: req.deadline,
+        placements: Vec::new(),
         submitted_at,
         started_at: None,
         finished_at: None,
         duration: None,
         status: JobStatus::Queued,
         result: None,
+        cancel: CancelFlag::default(),
     };
 
     let tenant = {
@@ -39,166 +476,810 @@ pub async fn submit_job(
     };
 
     let Some(t) = tenant else {
-        return (
+        return Err((
             StatusCode::NOT_FOUND,
-            Json(JobErrorResponse {
+            JobErrorResponse {
                 error: "unknown_tenant".to_string(),
                 message: format!("Tenant ID {} not known", job.tenant_id),
-            }),
-        )
-            .into_response();
+            },
+        ));
     };
 
     if !matches!(t.status, TenantStatus::Active) {
-        return (
+        return Err((
             StatusCode::UNAUTHORIZED,
-            Json(JobErrorResponse {
+            JobErrorResponse {
                 error: "unauthorized_tenant".to_string(),
                 message: format!("Tenant ID {} not authorized", job.tenant_id),
-            }),
-        )
-            .into_response();
+            },
+        ));
+    }
+
+    // Parents must already be known; other tenants' jobs look the same as
+    // missing ones
+    if let Some(parent) = job.depends_on.iter().find(|parent| {
+        state
+            .jobs
+            .read(**parent, |p| p.tenant_id != job.tenant_id)
+            .unwrap_or(true)
+    }) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JobErrorResponse {
+                error: "unknown_dependency".to_string(),
+                message: format!("Job {} depends on unknown job {}", job.job_id, parent),
+            },
+        ));
+    }
+
+    if !t.allowed_backends.contains(&job.backend) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            JobErrorResponse {
+                error: "unpermitted_backend".to_string(),
+                message: format!(
+                    "Tenant ID {} may not use backend {}",
+                    job.tenant_id, job.backend
+                ),
+            },
+        ));
     }
 
-    if job
+    let unknown_capabilities: Vec<&String> = job
         .capabilities
         .iter()
-        .any(|c| !t.allowed_capabilities.contains(c))
-    {
+        .filter(|c| !state.capabilities.contains(c))
+        .collect();
+
+    if !unknown_capabilities.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JobErrorResponse {
+                error: "unknown_capabilities".to_string(),
+                message: format!(
+                    "Unknown capabilities requested: {:?} ",
+                    unknown_capabilities
+                ),
+            },
+        ));
+    }
+
+    let unsupported_capabilities: Vec<&String> = job
+        .capabilities
+        .iter()
+        .filter(|c| !backend.supports_capability(c))
+        .collect();
+
+    if !unsupported_capabilities.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JobErrorResponse {
+                error: "unsupported_capabilities".to_string(),
+                message: format!(
+                    "Backend {} does not support capabilities: {:?} ",
+                    job.backend, unsupported_capabilities
+                ),
+            },
+        ));
+    }
+
+    if job.capabilities.iter().any(|c| t.grant(c).is_none()) {
         let unpermitted_capabilities: Vec<&String> = job
             .capabilities
             .iter()
-            .filter(|c| !t.allowed_capabilities.contains(*c))
+            .filter(|c| t.grant(c).is_none())
             .collect();
 
-        return (
+        return Err((
             StatusCode::FORBIDDEN,
-            Json(JobErrorResponse {
+            JobErrorResponse {
                 error: "unpermitted_capabilities".to_string(),
                 message: format!(
                     "Unpermitted capabilities requested: {:?} ",
                     unpermitted_capabilities
                 ),
-            }),
-        )
-            .into_response();
+            },
+        ));
     }
 
-    // Rate limit: tenant.rate_limit is "#jobs / minute"
-    let now = OffsetDateTime::now_utc();
-    let window = Duration::minutes(1);
+    // Loading a signature can mean reading a model from disk
+    let signature = {
+        let backend = backend.clone();
+        let module_id = job.module_id.clone();
+        tokio::task::spawn_blocking(move || backend.signature(&module_id)).await
+    };
+    let signature = match signature {
+        Ok(Ok(signature)) => signature,
+        Ok(Err(BackendError::Onnx(OnnxError::Model(e)))) => return Err(model_rejection(e)),
+        Ok(Err(e)) => {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                JobErrorResponse {
+                    error: "invalid_signature".to_string(),
+                    message: e.to_string(),
+                },
+            ));
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JobErrorResponse {
+                    error: "invalid_signature".to_string(),
+                    message: e.to_string(),
+                },
+            ));
+        }
+    };
 
-    // Lock order: tenant_usage -> inner (avoid accidental deadlocks later)
-    let mut tenant_usage_map = state.tenant_usage.write().await;
-    if t.rate_limit > 0 {
-        let usage: &mut VecDeque<OffsetDateTime> = tenant_usage_map
-            .entry(job.tenant_id.clone())
-            .or_insert_with(VecDeque::new);
+    // Inputs passed on from parents are only known once they finish
+    let signature = signature.filter(|_| !job.pass_outputs);
+    if let Some(Err(e)) = signature.map(|s| s.check_inputs(&job.tensors)) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            JobErrorResponse {
+                error: "invalid_tensors".to_string(),
+                message: e.to_string(),
+            },
+        ));
+    }
 
-        while usage.front().is_some_and(|ts| now - *ts > window) {
-            usage.pop_front();
+    let quotas = match state.quota_chain(&t, job.project_id.as_deref()).await {
+        Ok(quotas) => quotas,
+        Err(e) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                JobErrorResponse {
+                    error: "invalid_project".to_string(),
+                    message: e.to_string(),
+                },
+            ));
         }
+    };
+    job.quota_scopes = quotas.iter().map(|q| q.scope.clone()).collect();
 
-        if usage.len() >= t.rate_limit {
-            return (
-                StatusCode::TOO_MANY_REQUESTS,
-                Json(JobErrorResponse {
-                    error: "rate_limit_exceeded".to_string(),
+    // Can't overflow: the devices can hold it
+    let total = job.resources.times(gang_size).unwrap_or_default();
+    if let Some(quota) = quotas
+        .iter()
+        .find(|q| q.limits.gpu_limit.is_some_and(|l| !total.fits_within(&l)))
+    {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            JobErrorResponse {
+                error: "exceeds_quota".to_string(),
+                message: format!(
+                    "Requested {:?} exceeds the GPU limit of {}",
+                    total, quota.scope
+                ),
+            },
+        ));
+    }
+
+    if let Some(deadline) = job.deadline {
+        let finish = deadline::earliest_finish(state, &job, submitted_at);
+        if finish.is_none_or(|finish| finish > deadline) {
+            let expected = finish.map_or("never".to_string(), |finish| finish.to_string());
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                JobErrorResponse {
+                    error: "infeasible_deadline".to_string(),
                     message: format!(
-                        "Rate limit exceeded for tenant {}: max {} jobs per minute",
-                        job.tenant_id, t.rate_limit
+                        "Job is expected to finish at {} at the earliest, after its deadline {}",
+                        expected, deadline
                     ),
+                },
+            ));
+        }
+    }
+
+    Ok((job, quotas))
+}
+
+/// Charges a prepared job to its rate windows and adds it to the job table.
+fn admit(state: &AppState, job: &Job, quotas: &[ScopeQuota]) -> Result<(), Rejection> {
+    if let Err(e) = state
+        .rate_limiter
+        .acquire(quotas, OffsetDateTime::now_utc())
+    {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            JobErrorResponse {
+                error: "rate_limit_exceeded".to_string(),
+                message: e.to_string(),
+            },
+        ));
+    }
+
+    // The job is in the table before the dispatcher can see it, so its status
+    // updates always find it
+    let max_queued: Vec<(&str, usize)> = quotas
+        .iter()
+        .filter_map(|q| Some((q.scope.as_str(), q.limits.max_queued?)))
+        .collect();
+    if let Err(e) = state.jobs.insert(job.clone(), &max_queued) {
+        state.rate_limiter.release(quotas);
+        return Err(match e {
+            JobStoreError::QueueFull(_) => queue_full(),
+            JobStoreError::TooManyQueued { .. } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                JobErrorResponse {
+                    error: "queued_limit_exceeded".to_string(),
+                    message: e.to_string(),
+                },
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Undoes `admit` for a job that couldn't be handed to the dispatcher.
+fn withdraw(state: &AppState, job_id: Uuid, quotas: &[ScopeQuota]) {
+    state.jobs.remove(job_id);
+    state.rate_limiter.release(quotas);
+}
+
+fn queue_full() -> Rejection {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        JobErrorResponse {
+            error: "queue_full".to_string(),
+            message: "Job queue full please kwewe later".to_string(),
+        },
+    )
+}
+
+fn queue_rejection<T>(e: TrySendError<T>) -> Rejection {
+    match e {
+        TrySendError::Full(_) => queue_full(),
+        TrySendError::Closed(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JobErrorResponse {
+                error: "service_closed".to_string(),
+                message: "Sorry, we are closed for business".to_string(),
+            },
+        ),
+    }
+}
+
+/// Admits a prepared job and hands it to the dispatcher.
+fn enqueue(state: &AppState, job: Job, quotas: &[ScopeQuota]) -> Result<Uuid, Rejection> {
+    let job_id = job.job_id;
+    admit(state, &job, quotas)?;
+    if let Err(e) = state.queue.try_send(job) {
+        withdraw(state, job_id, quotas);
+        return Err(queue_rejection(e));
+    }
+    Ok(job_id)
+}
+
+pub async fn get_job(State(state): State<AppState>, Path(job_id): Path<Uuid>) -> impl IntoResponse {
+    match state.jobs.get(job_id) {
+        Some(job) => (StatusCode::OK, Json(job)).into_response(),
+        None => job_not_found(job_id),
+    }
+}
+
+/// Registers a schedule. Its job template gets the checks of `POST /jobs` now,
+/// and again at every run.
+pub async fn create_schedule(
+    State(state): State<AppState>,
+    Json(req): Json<SubmitScheduleRequest>,
+) -> impl IntoResponse {
+    let schedule = match Schedule::new(req) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(JobErrorResponse {
+                    error: "invalid_schedule".to_string(),
+                    message: e.to_string(),
                 }),
             )
                 .into_response();
         }
+    };
+    if let Err((status, error)) = prepare_job(&state, schedule.template().clone()).await {
+        return (status, Json(error)).into_response();
+    }
+
+    state
+        .schedules
+        .write()
+        .await
+        .insert(schedule.schedule_id, schedule.clone());
+    (StatusCode::CREATED, Json(schedule)).into_response()
+}
 
-        // Reserve a slot in the window; we roll this back if queueing fails.
-        usage.push_back(now);
+pub async fn list_schedules(
+    State(state): State<AppState>,
+    Query(query): Query<ListJobsQuery>,
+) -> impl IntoResponse {
+    let schedules = schedule::list(&*state.schedules.read().await, query.tenant_id.as_deref());
+    Json(ScheduleListResponse { schedules })
+}
+
+pub async fn get_schedule(
+    State(state): State<AppState>,
+    Path(schedule_id): Path<Uuid>,
+) -> impl IntoResponse {
+    match state.schedules.read().await.get(&schedule_id) {
+        Some(schedule) => Json(schedule).into_response(),
+        None => schedule_not_found(schedule_id),
     }
+}
 
-    let mut inner = state.inner.write().await;
+pub async fn delete_schedule(
+    State(state): State<AppState>,
+    Path(schedule_id): Path<Uuid>,
+) -> impl IntoResponse {
+    match state.schedules.write().await.remove(&schedule_id) {
+        Some(schedule) => Json(schedule).into_response(),
+        None => schedule_not_found(schedule_id),
+    }
+}
 
-    let job_for_queue = job.clone();
+pub async fn pause_schedule(
+    State(state): State<AppState>,
+    Path(schedule_id): Path<Uuid>,
+) -> impl IntoResponse {
+    match state.schedules.write().await.get_mut(&schedule_id) {
+        Some(schedule) => {
+            schedule.pause();
+            Json(&*schedule).into_response()
+        }
+        None => schedule_not_found(schedule_id),
+    }
+}
 
-    match inner.queue.try_send(job_for_queue) {
-        Ok(()) => {
-            inner.jobs.insert(job_id, job);
+pub async fn resume_schedule(
+    State(state): State<AppState>,
+    Path(schedule_id): Path<Uuid>,
+) -> impl IntoResponse {
+    match state.schedules.write().await.get_mut(&schedule_id) {
+        Some(schedule) => {
+            schedule.resume();
+            Json(&*schedule).into_response()
         }
-        Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
-            // Roll back rate limit reservation if we made one.
-            if t.rate_limit > 0 {
-                if let Some(usage) = tenant_usage_map.get_mut(&job.tenant_id) {
-                    let _ = usage.pop_back();
-                    if usage.is_empty() {
-                        tenant_usage_map.remove(&job.tenant_id);
-                    }
-                }
-            }
+        None => schedule_not_found(schedule_id),
+    }
+}
+
+fn schedule_not_found(schedule_id: Uuid) -> axum::response::Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(JobErrorResponse {
+            error: "schedule_not_found".to_string(),
+            message: format!("Schedule with id {} not found", schedule_id),
+        }),
+    )
+        .into_response()
+}
+
+pub async fn get_workflow(
+    State(state): State<AppState>,
+    Path(workflow_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let Some(workflow) = state.workflows.read().await.get(&workflow_id).cloned() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(JobErrorResponse {
+                error: "workflow_not_found".to_string(),
+                message: format!("Workflow with id {} not found", workflow_id),
+            }),
+        )
+            .into_response();
+    };
+
+    let steps: BTreeMap<String, WorkflowStepStatus> = workflow
+        .steps
+        .into_iter()
+        .filter_map(|(name, job_id)| {
+            let status = state.jobs.read(job_id, |job| job.status.clone())?;
+            Some((name, WorkflowStepStatus { job_id, status }))
+        })
+        .collect();
+    let status = workflow::aggregate_status(steps.values().map(|step| &step.status));
+
+    Json(WorkflowStatusResponse {
+        workflow_id,
+        tenant_id: workflow.tenant_id,
+        status,
+        submitted_at: workflow.submitted_at,
+        steps,
+    })
+    .into_response()
+}
+
+pub async fn list_jobs(
+    State(state): State<AppState>,
+    Query(query): Query<ListJobsQuery>,
+) -> impl IntoResponse {
+    let mut jobs: Vec<JobListItem> = state.jobs.collect(|job| {
+        let wanted = query.tenant_id.as_ref().is_none_or(|t| *t == job.tenant_id)
+            && query.status.as_ref().is_none_or(|s| s == job.status.name());
+        wanted.then(|| JobListItem {
+            job_id: job.job_id,
+            tenant_id: job.tenant_id.clone(),
+            module_id: job.module_id.clone(),
+            backend: job.backend.clone(),
+            status: job.status.clone(),
+            submitted_at: job.submitted_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+        })
+    });
+
+    jobs.sort_by_key(|job| job.submitted_at);
+
+    Json(JobListResponse { jobs })
+}
+
+/// Cancels a queued or running job. A running job is stopped at its next
+/// interruption point and holds its slot until then.
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+) -> impl IntoResponse {
+    match dispatcher::cancel(&state, job_id) {
+        None => return job_not_found(job_id),
+        Some(Err(status)) => {
             return (
-                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::CONFLICT,
                 Json(JobErrorResponse {
-                    error: "queue_full".to_string(),
-                    message: "Job queue full please kwewe later".to_string(),
+                    error: "job_not_cancellable".to_string(),
+                    message: format!(
+                        "Job {} is {}, only queued or running jobs can be cancelled",
+                        job_id, status
+                    ),
                 }),
             )
                 .into_response();
         }
-        Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {
-            // Roll back rate limit reservation if we made one.
-            if t.rate_limit > 0 {
-                if let Some(usage) = tenant_usage_map.get_mut(&job.tenant_id) {
-                    let _ = usage.pop_back();
-                    if usage.is_empty() {
-                        tenant_usage_map.remove(&job.tenant_id);
-                    }
-                }
-            }
+        Some(Ok(())) => {}
+    }
+
+    match state.jobs.get(job_id) {
+        Some(job) => (StatusCode::OK, Json(job)).into_response(),
+        None => job_not_found(job_id),
+    }
+}
+
+pub async fn job_logs(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let logs = state.jobs.read(job_id, |job| JobLogsResponse {
+        job_id,
+        status: job.status.clone(),
+        logs: job
+            .result
+            .as_ref()
+            .map(|r| r.logs.clone())
+            .unwrap_or_default(),
+    });
+
+    match logs {
+        Some(logs) => Json(logs).into_response(),
+        None => job_not_found(job_id),
+    }
+}
+
+fn job_not_found(job_id: Uuid) -> axum::response::Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(JobErrorResponse {
+            error: "job_not_found".to_string(),
+            message: format!("Job with id {} not found", job_id),
+        }),
+    )
+        .into_response()
+}
+
+pub async fn gpu_usage(State(state): State<AppState>) -> impl IntoResponse {
+    let gpu_manager = GpuManager::lock(&state.gpu_manager);
+    Json(gpu_manager.usage())
+}
+
+pub async fn list_reservations(State(state): State<AppState>) -> impl IntoResponse {
+    let gpu_manager = GpuManager::lock(&state.gpu_manager);
+    Json(gpu_manager.reservations())
+}
+
+pub async fn livez(State(state): State<AppState>) -> impl IntoResponse {
+    health_response(health::liveness(&state))
+}
+
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    health_response(health::readiness(&state).await)
+}
+
+fn health_response(report: HealthReport) -> axum::response::Response {
+    let status = if report.ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report)).into_response()
+}
+
+pub async fn queue_stats(State(state): State<AppState>) -> impl IntoResponse {
+    let history = state
+        .queue_history
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    Json(QueueStatsResponse {
+        interval_secs: SAMPLE_INTERVAL.as_secs(),
+        samples: history.samples(),
+    })
+}
+
+pub async fn list_capabilities(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.capabilities.list())
+}
+
+pub async fn list_backends(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.backends.list())
+}
+
+/// Stores the request body as the next version of an ONNX model. Admin only,
+/// since every tenant's jobs run the latest version.
+pub async fn upload_model(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    if let Some(response) = admin_rejection(&state, &headers) {
+        return response;
+    }
+
+    let models = state.models.clone();
+    let uploaded = tokio::task::spawn_blocking(move || {
+        onnx::check_model(&body)?;
+        models.upload(&name, &body)
+    })
+    .await;
+
+    match uploaded {
+        Ok(Ok(version)) => (StatusCode::CREATED, Json(version)).into_response(),
+        Ok(Err(e)) => model_error(e),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(JobErrorResponse {
+                error: "model_storage_failed".to_string(),
+                message: e.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn list_models(State(state): State<AppState>) -> impl IntoResponse {
+    match state.models.list() {
+        Ok(models) => (StatusCode::OK, Json(models)).into_response(),
+        Err(e) => model_error(e),
+    }
+}
+
+pub async fn list_model_versions(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match state.models.versions(&name) {
+        Ok(versions) => (StatusCode::OK, Json(versions)).into_response(),
+        Err(e) => model_error(e),
+    }
+}
+
+fn model_error(e: ModelError) -> axum::response::Response {
+    let (status, error) = model_rejection(e);
+    (status, Json(error)).into_response()
+}
+
+fn model_rejection(e: ModelError) -> Rejection {
+    let (status, error) = match e {
+        ModelError::InvalidName(_) => (StatusCode::BAD_REQUEST, "invalid_model_name"),
+        ModelError::NotFound(_) => (StatusCode::NOT_FOUND, "model_not_found"),
+        ModelError::Invalid(_) => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_model"),
+        ModelError::Storage(_) => (StatusCode::INTERNAL_SERVER_ERROR, "model_storage_failed"),
+    };
+    (
+        status,
+        JobErrorResponse {
+            error: error.to_string(),
+            message: e.to_string(),
+        },
+    )
+}
+
+/// Stores the request body as `modules/{module_id}.wasm`, replacing any
+/// module of that name. It must compile as a core module or component.
+/// Admin only, since any tenant may run the module.
+pub async fn upload_module(
+    State(state): State<AppState>,
+    Path(module_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    if let Some(response) = admin_rejection(&state, &headers) {
+        return response;
+    }
+
+    let valid_name = !module_id.is_empty()
+        && module_id.len() <= 64
+        && module_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(JobErrorResponse {
+                error: "invalid_module_name".to_string(),
+                message: format!(
+                    "Invalid module name {}: use up to 64 letters, digits, '-' or '_'",
+                    module_id
+                ),
+            }),
+        )
+            .into_response();
+    }
+
+    let checked = {
+        let body = body.clone();
+        tokio::task::spawn_blocking(move || sandbox::check_module(&body)).await
+    };
+    let component = match checked {
+        Ok(Ok(component)) => component,
+        Ok(Err(e)) => {
             return (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::UNPROCESSABLE_ENTITY,
                 Json(JobErrorResponse {
-                    error: "service_closed".to_string(),
-                    message: "Sorry, we are closed for business".to_string(),
+                    error: "invalid_module".to_string(),
+                    message: e.to_string(),
                 }),
             )
                 .into_response();
         }
+        Err(e) => return module_storage_failed(e.to_string()),
+    };
+
+    let path = state.modules_dir.join(format!("{}.wasm", module_id));
+    if let Err(e) = tokio::fs::write(path, &body).await {
+        return module_storage_failed(e.to_string());
     }
 
-    (StatusCode::ACCEPTED, Json(SubmitJobResponse { job_id })).into_response()
+    (
+        StatusCode::CREATED,
+        Json(ModuleUploadResponse {
+            module_id,
+            size_bytes: body.len(),
+            component,
+        }),
+    )
+        .into_response()
 }
 
-pub async fn get_job(State(state): State<AppState>, Path(job_id): Path<Uuid>) -> impl IntoResponse {
-    let inner = state.inner.read().await;
+fn module_storage_failed(message: String) -> axum::response::Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(JobErrorResponse {
+            error: "module_storage_failed".to_string(),
+            message,
+        }),
+    )
+        .into_response()
+}
 
-    match inner.jobs.get(&job_id).cloned() {
-        Some(job) => (StatusCode::OK, Json(job)).into_response(),
-        None => (
-            StatusCode::NOT_FOUND,
-            Json(JobErrorResponse {
-                error: "job_not_found".to_string(),
-                message: format!("Job with id {} not found", job_id),
-            }),
+/// Admin routes need `Authorization: Bearer <admin_token>` from `config.toml`.
+/// Returns the response to reject the request with, if any.
+fn admin_rejection(state: &AppState, headers: &HeaderMap) -> Option<axum::response::Response> {
+    let Some(token) = &state.admin_token else {
+        return Some(
+            (
+                StatusCode::FORBIDDEN,
+                Json(JobErrorResponse {
+                    error: "admin_disabled".to_string(),
+                    message: "No admin_token is configured".to_string(),
+                }),
+            )
+                .into_response(),
+        );
+    };
+
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    // Constant time, so response timing doesn't leak how much of a guess matched
+    let matches = presented.is_some_and(|p| bool::from(p.as_bytes().ct_eq(token.as_bytes())));
+    if matches {
+        None
+    } else {
+        Some(
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(JobErrorResponse {
+                    error: "unauthorized".to_string(),
+                    message: "Missing or wrong admin token".to_string(),
+                }),
+            )
+                .into_response(),
         )
-            .into_response(),
     }
 }
 
-pub async fn list_jobs(State(state): State<AppState>) -> impl IntoResponse {
-    let inner = state.inner.read().await;
+pub async fn list_tenants(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Some(response) = admin_rejection(&state, &headers) {
+        return response;
+    }
 
-    let mut jobs: Vec<JobListItem> = inner
-        .jobs
-        .values()
-        .map(|job| JobListItem {
-            job_id: job.job_id,
-            tenant_id: job.tenant_id.clone(),
-            status: job.status.clone(),
-            submitted_at: job.submitted_at,
-        })
-        .collect();
+    let tenants = state.tenants.read().await;
+    let mut summaries: Vec<TenantSummary> = tenants.values().map(TenantSummary::from).collect();
+    summaries.sort_by(|a, b| a.tenant_id.cmp(&b.tenant_id));
+    Json(summaries).into_response()
+}
 
-    jobs.sort_by_key(|job| job.submitted_at);
+pub async fn get_tenant(
+    State(state): State<AppState>,
+    Path(tenant_id): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Some(response) = admin_rejection(&state, &headers) {
+        return response;
+    }
 
-    Json(JobListResponse { jobs })
+    let tenants = state.tenants.read().await;
+    match tenants.get(&tenant_id) {
+        Some(tenant) => Json(TenantSummary::from(tenant)).into_response(),
+        None => tenant_not_found(&tenant_id),
+    }
+}
+
+pub async fn suspend_tenant(
+    State(state): State<AppState>,
+    Path(tenant_id): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    set_tenant_status(state, tenant_id, headers, TenantStatus::Suspended).await
+}
+
+pub async fn activate_tenant(
+    State(state): State<AppState>,
+    Path(tenant_id): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    set_tenant_status(state, tenant_id, headers, TenantStatus::Active).await
+}
+
+/// Changes take effect for new submissions and for queued jobs when they are
+/// dispatched. They live in memory only; `tenants.json` is not rewritten.
+async fn set_tenant_status(
+    state: AppState,
+    tenant_id: String,
+    headers: HeaderMap,
+    status: TenantStatus,
+) -> axum::response::Response {
+    if let Some(response) = admin_rejection(&state, &headers) {
+        return response;
+    }
+
+    let mut tenants = state.tenants.write().await;
+    match tenants.get_mut(&tenant_id) {
+        Some(tenant) => {
+            tenant.status = status;
+            Json(TenantSummary::from(&*tenant)).into_response()
+        }
+        None => tenant_not_found(&tenant_id),
+    }
+}
+
+fn tenant_not_found(tenant_id: &str) -> axum::response::Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(JobErrorResponse {
+            error: "unknown_tenant".to_string(),
+            message: format!("Tenant ID {} not known", tenant_id),
+        }),
+    )
+        .into_response()
 }
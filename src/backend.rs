@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::capability::ResolvedCapability;
+use crate::domain::Job;
+use crate::mlp::{MlpBackend, MlpError};
+use crate::model_registry::ModelRegistry;
+use crate::onnx::{OnnxBackend, OnnxError};
+use crate::sandbox::{ExecutionResult, SandboxError, SandboxExecutor};
+use crate::tenant::Tenant;
+use crate::tensor::{Signature, TensorError};
+
+/// Backend jobs run on when `SubmitJobRequest::backend` is left out.
+pub const DEFAULT_BACKEND: &str = "wasm";
+
+pub type BackendFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<ExecutionResult, BackendError>> + Send + 'a>>;
+
+/// What a backend consumes while a job runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceModel {
+    /// Runs in GPU partition(s) reserved through `GpuManager`
+    GpuPartition,
+    /// Runs on the host CPU and takes no GPU reservation
+    HostCpu,
+}
+
+/// Something a job can execute on. The dispatcher only sees this trait;
+/// adding a backend means implementing it and registering it in
+/// `BackendRegistry::new`.
+pub trait ExecutionBackend: Send + Sync {
+    fn description(&self) -> &'static str;
+
+    fn resource_model(&self) -> ResourceModel;
+
+    /// Whether jobs on this backend may request `capability`.
+    fn supports_capability(&self, capability: &str) -> bool;
+
+    /// The input tensors `module_id` declares, if any. Checked on submit.
+    fn signature(&self, _module_id: &str) -> Result<Option<Signature>, BackendError> {
+        Ok(None)
+    }
+
+    /// Whether the backend can run jobs right now. Checked by `GET /readyz`.
+    fn health_check(&self) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    fn execute<'a>(
+        &'a self,
+        job: &'a Job,
+        capabilities: &'a [ResolvedCapability],
+    ) -> BackendFuture<'a>;
+}
+
+impl ExecutionBackend for SandboxExecutor {
+    fn description(&self) -> &'static str {
+        "WebAssembly modules and components in the wasmtime sandbox"
+    }
+
+    fn resource_model(&self) -> ResourceModel {
+        ResourceModel::GpuPartition
+    }
+
+    fn supports_capability(&self, _capability: &str) -> bool {
+        true
+    }
+
+    fn signature(&self, module_id: &str) -> Result<Option<Signature>, BackendError> {
+        Ok(Signature::load(
+            self.module_file(module_id, "signature.json"),
+        )?)
+    }
+
+    fn health_check(&self) -> Result<(), BackendError> {
+        Ok(self.self_test()?)
+    }
+
+    fn execute<'a>(
+        &'a self,
+        job: &'a Job,
+        capabilities: &'a [ResolvedCapability],
+    ) -> BackendFuture<'a> {
+        Box::pin(async move { Ok(SandboxExecutor::execute(self, job, capabilities).await?) })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendInfo {
+    pub name: String,
+    pub description: String,
+    pub resource_model: ResourceModel,
+}
+
+pub struct BackendRegistry {
+    backends: BTreeMap<String, Arc<dyn ExecutionBackend>>,
+}
+
+impl BackendRegistry {
+    pub fn new(
+        models: Arc<ModelRegistry>,
+        modules_dir: &Path,
+        models_dir: &Path,
+    ) -> Result<Self, BackendError> {
+        let sandbox = SandboxExecutor::with_default_config()?.with_modules_dir(modules_dir);
+        let onnx = OnnxBackend::new(models, sandbox.max_execution_time());
+
+        let mut backends: BTreeMap<String, Arc<dyn ExecutionBackend>> = BTreeMap::new();
+        backends.insert("wasm".to_string(), Arc::new(sandbox));
+        backends.insert("mlp".to_string(), Arc::new(MlpBackend::new(models_dir)));
+        backends.insert("onnx".to_string(), Arc::new(onnx));
+
+        Ok(BackendRegistry { backends })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ExecutionBackend>> {
+        self.backends.get(name).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Arc<dyn ExecutionBackend>)> {
+        self.backends.iter()
+    }
+
+    pub fn list(&self) -> Vec<BackendInfo> {
+        self.backends
+            .iter()
+            .map(|(name, backend)| BackendInfo {
+                name: name.clone(),
+                description: backend.description().to_string(),
+                resource_model: backend.resource_model(),
+            })
+            .collect()
+    }
+
+    /// Checks that a tenant is only permitted backends that exist. Run when
+    /// tenants are loaded.
+    pub fn validate_tenant(&self, tenant: &Tenant) -> Result<(), BackendError> {
+        match tenant
+            .allowed_backends
+            .iter()
+            .find(|b| !self.backends.contains_key(*b))
+        {
+            Some(name) => Err(BackendError::Unknown(name.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackendError {
+    #[error("Unknown backend {0}")]
+    Unknown(String),
+    #[error("Job was cancelled")]
+    Cancelled,
+    #[error("{0}")]
+    Payload(String),
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+    #[error(transparent)]
+    Mlp(#[from] MlpError),
+    #[error(transparent)]
+    Onnx(#[from] OnnxError),
+    #[error(transparent)]
+    Tensor(#[from] TensorError),
+    #[error("Stage {stage} ({module_id}) failed: {source}")]
+    Stage {
+        stage: usize,
+        module_id: String,
+        source: Box<BackendError>,
+    },
+}
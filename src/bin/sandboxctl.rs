@@ -0,0 +1,1027 @@
+//! `sandboxctl`: command-line client for the controller's HTTP API.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use time::OffsetDateTime;
+use ureq::Agent;
+
+const USAGE: &str = "\
+Usage: sandboxctl [--url URL] [--config FILE] [--json] <command> [args]
+
+Commands:
+  submit <module_id> [--tenant ID] [--project ID] [--backend NAME]
+         [--payload FILE] [--tensors FILE] [--cap NAME]... [--profile NAME]
+         [--gang N] [--output-encoding json|base64] [--wait]
+                                Submit a job; FILE may be - for stdin
+  status <job_id>               Show a job
+  list [--tenant ID] [--status STATUS]
+                                List jobs, oldest first
+  watch [<job_id>] [--tenant ID] [--status STATUS] [--interval SECS]
+                                Follow one job until it ends, or refresh the list
+  cancel <job_id>               Cancel a queued or running job
+  logs <job_id>                 Print a finished job's logs
+  module upload <module_id> <file.wasm>
+                                Upload a core module or component (admin)
+  tenant list                   List tenants (admin)
+  tenant show <tenant_id>       Show a tenant (admin)
+  tenant suspend <tenant_id>    Suspend a tenant (admin)
+  tenant activate <tenant_id>   Reactivate a tenant (admin)
+  slots [--reservations]        Show GPU slot usage
+
+Settings are read from $SANDBOXCTL_CONFIG or ~/.config/sandboxctl/config.toml:
+  url = \"http://127.0.0.1:3000\"
+  tenant_id = \"tenant1\"          # default for submit, list and watch
+  admin_token = \"...\"            # sent as a bearer token
+";
+
+const DEFAULT_URL: &str = "http://127.0.0.1:3000";
+
+/// Options that take no value.
+const FLAGS: &[&str] = &["json", "wait", "reservations", "help"];
+
+#[derive(Deserialize, Default)]
+struct CliConfig {
+    url: Option<String>,
+    tenant_id: Option<String>,
+    admin_token: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum CliError {
+    #[error("{0}\n\n{USAGE}")]
+    Usage(String),
+    #[error("Config: {0}")]
+    Config(String),
+    #[error("{0}")]
+    Io(String),
+    #[error("Request failed: {0}")]
+    Http(String),
+    #[error("{status} {error}: {message}")]
+    Api {
+        status: u16,
+        error: String,
+        message: String,
+    },
+}
+
+struct Args {
+    positional: Vec<String>,
+    options: BTreeMap<String, Vec<String>>,
+}
+
+impl Args {
+    fn parse(raw: impl Iterator<Item = String>) -> Result<Self, CliError> {
+        let mut args = Args {
+            positional: Vec::new(),
+            options: BTreeMap::new(),
+        };
+        let mut raw = raw.peekable();
+        while let Some(arg) = raw.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                args.positional.push(arg);
+                continue;
+            };
+            let value = if FLAGS.contains(&name) {
+                String::new()
+            } else {
+                raw.next()
+                    .ok_or_else(|| CliError::Usage(format!("--{} needs a value", name)))?
+            };
+            args.options
+                .entry(name.to_string())
+                .or_default()
+                .push(value);
+        }
+        Ok(args)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .get(name)
+            .and_then(|v| v.last())
+            .map(String::as_str)
+    }
+
+    fn all(&self, name: &str) -> Vec<String> {
+        self.options.get(name).cloned().unwrap_or_default()
+    }
+
+    fn positional(&self, index: usize, what: &str) -> Result<&str, CliError> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| CliError::Usage(format!("missing {}", what)))
+    }
+}
+
+struct Client {
+    agent: Agent,
+    url: String,
+    admin_token: Option<String>,
+}
+
+impl Client {
+    fn new(url: String, admin_token: Option<String>) -> Self {
+        let agent = Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(30)))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Client {
+            agent,
+            url: url.trim_end_matches('/').to_string(),
+            admin_token,
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<Value, CliError> {
+        let mut request = self.agent.get(format!("{}{}", self.url, path));
+        if let Some(token) = &self.admin_token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        Self::read(request.call())
+    }
+
+    fn post(&self, path: &str, content_type: &str, body: &[u8]) -> Result<Value, CliError> {
+        let mut request = self
+            .agent
+            .post(format!("{}{}", self.url, path))
+            .header("Content-Type", content_type);
+        if let Some(token) = &self.admin_token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        Self::read(request.send(body))
+    }
+
+    fn post_json(&self, path: &str, body: &Value) -> Result<Value, CliError> {
+        self.post(path, "application/json", body.to_string().as_bytes())
+    }
+
+    /// Turns error responses (`{"error", "message"}`) into `CliError::Api`.
+    fn read(
+        response: Result<ureq::http::Response<ureq::Body>, ureq::Error>,
+    ) -> Result<Value, CliError> {
+        let mut response = response.map_err(|e| CliError::Http(e.to_string()))?;
+        let status = response.status().as_u16();
+        let body = response
+            .body_mut()
+            .read_to_string()
+            .map_err(|e| CliError::Http(e.to_string()))?;
+        let value: Value = serde_json::from_str(&body).unwrap_or(Value::String(body));
+
+        if status < 400 {
+            return Ok(value);
+        }
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string);
+        let reason = response.status().canonical_reason().unwrap_or("error");
+        Err(CliError::Api {
+            status,
+            error: field("error").unwrap_or_else(|| reason.to_lowercase().replace(' ', "_")),
+            message: field("message").unwrap_or_else(|| match value {
+                Value::String(body) if !body.is_empty() => body,
+                _ => reason.to_string(),
+            }),
+        })
+    }
+}
+
+fn load_config(path: Option<&str>) -> Result<CliConfig, CliError> {
+    load_config_with(path, |name| std::env::var(name).ok())
+}
+
+/// `--config`, then `$SANDBOXCTL_CONFIG`, then the file under `$HOME` if it
+/// exists. `env` looks up environment variables.
+fn load_config_with(
+    path: Option<&str>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<CliConfig, CliError> {
+    let (path, explicit) = match path
+        .map(str::to_string)
+        .or_else(|| env("SANDBOXCTL_CONFIG"))
+    {
+        Some(path) => (path, true),
+        None => match env("HOME") {
+            Some(home) => (format!("{}/.config/sandboxctl/config.toml", home), false),
+            None => return Ok(CliConfig::default()),
+        },
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => {
+            toml::from_str(&contents).map_err(|e| CliError::Config(format!("{}: {}", path, e)))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !explicit => Ok(CliConfig::default()),
+        Err(e) => Err(CliError::Config(format!("{}: {}", path, e))),
+    }
+}
+
+/// Reads a file, or stdin for `-`.
+fn read_input(path: &str) -> Result<Vec<u8>, CliError> {
+    if path == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| CliError::Io(format!("stdin: {}", e)))?;
+        Ok(bytes)
+    } else {
+        std::fs::read(path).map_err(|e| CliError::Io(format!("{}: {}", path, e)))
+    }
+}
+
+fn read_json(path: &str) -> Result<Value, CliError> {
+    serde_json::from_slice(&read_input(path)?).map_err(|e| CliError::Io(format!("{}: {}", path, e)))
+}
+
+fn print_json(value: &Value) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+    );
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    print!("{}", format_table(headers, rows));
+}
+
+fn format_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        format!("{}\n", padded.join("  ").trim_end())
+    };
+    let mut table = line(headers.to_vec());
+    for row in rows {
+        table.push_str(&line(row.iter().map(String::as_str).collect()));
+    }
+    table
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Timestamps come in `time`'s default serde format.
+fn timestamp(value: &Value) -> String {
+    match serde_json::from_value::<OffsetDateTime>(value.clone()) {
+        Ok(t) => format!(
+            "{} {:02}:{:02}:{:02}",
+            t.date(),
+            t.hour(),
+            t.minute(),
+            t.second()
+        ),
+        Err(_) => text(value),
+    }
+}
+
+fn duration(value: &Value) -> String {
+    match serde_json::from_value::<time::Duration>(value.clone()) {
+        Ok(d) => format!("{:.3}s", d.as_seconds_f64()),
+        Err(_) => text(value),
+    }
+}
+
+/// `JobStatus` is `"queued"` or `{"failed": "reason"}`.
+fn status(value: &Value) -> (String, Option<String>) {
+    match value {
+        Value::Object(map) => match map.iter().next() {
+            Some((name, detail)) => (name.clone(), detail.as_str().map(str::to_string)),
+            None => ("-".to_string(), None),
+        },
+        other => (text(other), None),
+    }
+}
+
+fn is_done(job: &Value) -> bool {
+    !matches!(status(&job["status"]).0.as_str(), "queued" | "running")
+}
+
+fn output_text(output: &Value) -> String {
+    let bytes: Vec<u8> = output
+        .as_array()
+        .map(|a| {
+            a.iter()
+                .filter_map(|b| b.as_u64())
+                .map(|b| b as u8)
+                .collect()
+        })
+        .unwrap_or_default();
+    match String::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => format!("<{} bytes>", e.into_bytes().len()),
+    }
+}
+
+fn print_job(job: &Value) {
+    let (name, detail) = status(&job["status"]);
+    let mut fields = vec![
+        ("Job", text(&job["job_id"])),
+        ("Tenant", text(&job["tenant_id"])),
+        ("Project", text(&job["project_id"])),
+        ("Module", text(&job["module_id"])),
+        ("Backend", text(&job["backend"])),
+        (
+            "Status",
+            match detail {
+                Some(detail) => format!("{} ({})", name, detail),
+                None => name,
+            },
+        ),
+        ("Submitted", timestamp(&job["submitted_at"])),
+        ("Started", timestamp(&job["started_at"])),
+        ("Finished", timestamp(&job["finished_at"])),
+        ("Duration", duration(&job["duration"])),
+    ];
+
+    let placements: Vec<String> = job["placements"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|p| {
+            format!(
+                "{} ({}s/{}GB)",
+                text(&p["device_id"]),
+                text(&p["shape"]["compute_slices"]),
+                text(&p["shape"]["memory_gb"])
+            )
+        })
+        .collect();
+    if !placements.is_empty() {
+        fields.push(("Placements", placements.join(", ")));
+    }
+
+    if let Some(result) = job["result"].as_object() {
+        fields.push(("Output", output_text(&result["output"])));
+        fields.push(("Memory", format!("{} bytes", text(&result["memory_used"]))));
+        if let Some(tensors) = result.get("tensors").and_then(Value::as_object) {
+            for (name, tensor) in tensors {
+                fields.push((
+                    "Tensor",
+                    format!(
+                        "{} {}{}",
+                        name,
+                        text(&tensor["dtype"]),
+                        text(&tensor["shape"])
+                    ),
+                ));
+            }
+        }
+        let logs = result["logs"].as_array().map_or(0, Vec::len);
+        fields.push(("Logs", format!("{} line(s)", logs)));
+    }
+
+    for (label, value) in fields {
+        println!("{:<11} {}", format!("{}:", label), value);
+    }
+}
+
+fn job_rows(list: &Value) -> Vec<Vec<String>> {
+    list["jobs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|job| {
+            vec![
+                text(&job["job_id"]),
+                text(&job["tenant_id"]),
+                text(&job["module_id"]),
+                text(&job["backend"]),
+                status(&job["status"]).0,
+                timestamp(&job["submitted_at"]),
+            ]
+        })
+        .collect()
+}
+
+const JOB_HEADERS: &[&str] = &["JOB", "TENANT", "MODULE", "BACKEND", "STATUS", "SUBMITTED"];
+
+fn list_path(args: &Args, config: &CliConfig) -> String {
+    let mut query = Vec::new();
+    if let Some(tenant) = args.option("tenant").or(config.tenant_id.as_deref()) {
+        query.push(format!("tenant_id={}", tenant));
+    }
+    if let Some(status) = args.option("status") {
+        query.push(format!("status={}", status));
+    }
+    if query.is_empty() {
+        "/jobs/list".to_string()
+    } else {
+        format!("/jobs/list?{}", query.join("&"))
+    }
+}
+
+fn interval(args: &Args) -> Result<Duration, CliError> {
+    let secs: f64 = args
+        .option("interval")
+        .unwrap_or("1")
+        .parse()
+        .map_err(|_| CliError::Usage("--interval takes seconds".to_string()))?;
+    Ok(Duration::from_secs_f64(secs.max(0.1)))
+}
+
+/// Polls a job until it leaves the queued and running states.
+fn wait_for(client: &Client, job_id: &str, every: Duration, json: bool) -> Result<(), CliError> {
+    let mut last = String::new();
+    loop {
+        let job = client.get(&format!("/jobs/{}", job_id))?;
+        let (name, _) = status(&job["status"]);
+        if !json && name != last {
+            eprintln!("{} {}", job_id, name);
+            last = name;
+        }
+        if is_done(&job) {
+            if json {
+                print_json(&job);
+            } else {
+                print_job(&job);
+            }
+            return Ok(());
+        }
+        std::thread::sleep(every);
+    }
+}
+
+fn submit(client: &Client, args: &Args, config: &CliConfig, json: bool) -> Result<(), CliError> {
+    let module_id = args.positional(1, "module_id")?;
+    let tenant_id = args
+        .option("tenant")
+        .or(config.tenant_id.as_deref())
+        .ok_or_else(|| CliError::Usage("no --tenant and no tenant_id in the config".into()))?;
+
+    let mut body = json!({
+        "tenant_id": tenant_id,
+        "module_id": module_id,
+        "payload": match args.option("payload") {
+            Some(path) => read_json(path)?,
+            None => json!({}),
+        },
+        "capabilities": args.all("cap"),
+    });
+    if let Some(path) = args.option("tensors") {
+        body["tensors"] = read_json(path)?;
+    }
+    for (option, field) in [
+        ("project", "project_id"),
+        ("backend", "backend"),
+        ("output-encoding", "output_encoding"),
+    ] {
+        if let Some(value) = args.option(option) {
+            body[field] = json!(value);
+        }
+    }
+    if let Some(profile) = args.option("profile") {
+        body["resources"] = json!({ "profile": profile });
+    }
+    if let Some(gang) = args.option("gang") {
+        let gang: u32 = gang
+            .parse()
+            .map_err(|_| CliError::Usage("--gang takes a number".to_string()))?;
+        body["gang_size"] = json!(gang);
+    }
+
+    let response = client.post_json("/jobs", &body)?;
+    let job_id = text(&response["job_id"]);
+    if args.flag("wait") {
+        if !json {
+            eprintln!("Submitted {}", job_id);
+        }
+        return wait_for(client, &job_id, interval(args)?, json);
+    }
+
+    if json {
+        print_json(&response);
+    } else {
+        println!("{}", job_id);
+    }
+    Ok(())
+}
+
+fn watch(client: &Client, args: &Args, config: &CliConfig, json: bool) -> Result<(), CliError> {
+    let every = interval(args)?;
+    if let Some(job_id) = args.positional.get(1) {
+        return wait_for(client, job_id, every, json);
+    }
+
+    let path = list_path(args, config);
+    loop {
+        let list = client.get(&path)?;
+        if json {
+            println!("{}", list);
+        } else {
+            // Clear the screen and redraw, like `watch(1)`
+            print!("\x1b[2J\x1b[H");
+            print_table(JOB_HEADERS, &job_rows(&list));
+        }
+        std::thread::sleep(every);
+    }
+}
+
+fn module(client: &Client, args: &Args, json: bool) -> Result<(), CliError> {
+    match args.positional(1, "module subcommand")? {
+        "upload" => {
+            let module_id = args.positional(2, "module_id")?;
+            let path = args.positional(3, "module file")?;
+            let response = client.post(
+                &format!("/modules/{}", module_id),
+                "application/wasm",
+                &read_input(path)?,
+            )?;
+            if json {
+                print_json(&response);
+            } else {
+                let kind = if response["component"] == json!(true) {
+                    "component"
+                } else {
+                    "module"
+                };
+                println!(
+                    "Uploaded {} {} ({} bytes)",
+                    kind,
+                    text(&response["module_id"]),
+                    text(&response["size_bytes"])
+                );
+            }
+            Ok(())
+        }
+        other => Err(CliError::Usage(format!("unknown module command {}", other))),
+    }
+}
+
+fn print_tenants(tenants: &[Value]) {
+    let rows: Vec<Vec<String>> = tenants
+        .iter()
+        .map(|t| {
+            let names = |field: &str| {
+                t[field]
+                    .as_array()
+                    .map(|a| a.iter().map(text).collect::<Vec<_>>().join(","))
+                    .unwrap_or_default()
+            };
+            vec![
+                text(&t["tenant_id"]),
+                text(&t["org_id"]),
+                text(&t["status"]),
+                names("allowed_backends"),
+                names("allowed_capabilities"),
+                names("projects"),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            "TENANT",
+            "ORG",
+            "STATUS",
+            "BACKENDS",
+            "CAPABILITIES",
+            "PROJECTS",
+        ],
+        &rows,
+    );
+}
+
+fn tenant(client: &Client, args: &Args, json: bool) -> Result<(), CliError> {
+    let response = match args.positional(1, "tenant subcommand")? {
+        "list" => client.get("/tenants")?,
+        "show" => client.get(&format!("/tenants/{}", args.positional(2, "tenant_id")?))?,
+        action @ ("suspend" | "activate") => client.post(
+            &format!("/tenants/{}/{}", args.positional(2, "tenant_id")?, action),
+            "application/json",
+            b"",
+        )?,
+        other => return Err(CliError::Usage(format!("unknown tenant command {}", other))),
+    };
+
+    if json {
+        print_json(&response);
+    } else {
+        match &response {
+            Value::Array(tenants) => print_tenants(tenants),
+            tenant => print_tenants(std::slice::from_ref(tenant)),
+        }
+    }
+    Ok(())
+}
+
+fn slots(client: &Client, args: &Args, json: bool) -> Result<(), CliError> {
+    let usage = client.get("/gpu")?;
+    if json {
+        print_json(&usage);
+        return Ok(());
+    }
+
+    let shape = |v: &Value, field: &str| text(&v[field]);
+    let rows: Vec<Vec<String>> = usage["devices"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|d| {
+            vec![
+                text(&d["device_id"]),
+                format!(
+                    "{}/{}",
+                    shape(&d["used"], "compute_slices"),
+                    shape(&d["capacity"], "compute_slices")
+                ),
+                format!(
+                    "{}/{}",
+                    shape(&d["used"], "memory_gb"),
+                    shape(&d["capacity"], "memory_gb")
+                ),
+            ]
+        })
+        .collect();
+    print_table(&["DEVICE", "SLICES", "MEMORY GB"], &rows);
+
+    let fragmentation = &usage["fragmentation"];
+    println!(
+        "\nStrategy {}, {} free slice(s), largest block {}, fragmentation {:.2}",
+        text(&usage["strategy"]),
+        text(&fragmentation["total_free_slices"]),
+        text(&fragmentation["largest_free_slices"]),
+        fragmentation["fragmentation"].as_f64().unwrap_or_default()
+    );
+    if let Some(hold) = usage["hold"].as_object() {
+        println!(
+            "Held for job {} on {}",
+            text(&hold["job_id"]),
+            text(&hold["device_ids"])
+        );
+    }
+
+    if args.flag("reservations") {
+        let rows: Vec<Vec<String>> = usage["reservations"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|r| {
+                let devices: Vec<String> = r["placements"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|p| text(&p["device_id"]))
+                    .collect();
+                vec![
+                    text(&r["job_id"]),
+                    text(&r["tenant_id"]),
+                    devices.join(","),
+                    timestamp(&r["reserved_at"]),
+                ]
+            })
+            .collect();
+        println!();
+        print_table(&["JOB", "TENANT", "DEVICES", "RESERVED"], &rows);
+    }
+    Ok(())
+}
+
+/// `--url` wins over the config file, which wins over `DEFAULT_URL`.
+fn client(args: &Args, config: &CliConfig) -> Client {
+    let url = args
+        .option("url")
+        .map(str::to_string)
+        .or_else(|| config.url.clone())
+        .unwrap_or_else(|| DEFAULT_URL.to_string());
+    Client::new(url, config.admin_token.clone())
+}
+
+fn run(args: Args) -> Result<(), CliError> {
+    let config = load_config(args.option("config"))?;
+    let client = client(&args, &config);
+    let json = args.flag("json");
+
+    match args.positional(0, "command")? {
+        "submit" => submit(&client, &args, &config, json),
+        "status" => {
+            let job = client.get(&format!("/jobs/{}", args.positional(1, "job_id")?))?;
+            if json {
+                print_json(&job);
+            } else {
+                print_job(&job);
+            }
+            Ok(())
+        }
+        "list" => {
+            let list = client.get(&list_path(&args, &config))?;
+            if json {
+                print_json(&list);
+            } else {
+                print_table(JOB_HEADERS, &job_rows(&list));
+            }
+            Ok(())
+        }
+        "watch" => watch(&client, &args, &config, json),
+        "cancel" => {
+            let job_id = args.positional(1, "job_id")?;
+            let job = client.post(&format!("/jobs/{}/cancel", job_id), "application/json", b"")?;
+            if json {
+                print_json(&job);
+            } else {
+                println!("Cancelled {}", job_id);
+            }
+            Ok(())
+        }
+        "logs" => {
+            let logs = client.get(&format!("/jobs/{}/logs", args.positional(1, "job_id")?))?;
+            if json {
+                print_json(&logs);
+            } else {
+                for line in logs["logs"].as_array().into_iter().flatten() {
+                    println!("{}", text(line));
+                }
+            }
+            Ok(())
+        }
+        "module" => module(&client, &args, json),
+        "tenant" => tenant(&client, &args, json),
+        "slots" => slots(&client, &args, json),
+        other => Err(CliError::Usage(format!("unknown command {}", other))),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) if args.flag("help") || args.positional.is_empty() => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e @ CliError::Usage(_)) => {
+            eprintln!("{}", e);
+            ExitCode::from(2)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn args(raw: &[&str]) -> Args {
+        Args::parse(raw.iter().map(|s| s.to_string())).unwrap()
+    }
+
+    /// Answers a single request with `status` and `body`, returns the base URL.
+    fn respond_once(status: &str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let status = status.to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn parses_positionals_flags_and_options() {
+        let args = args(&[
+            "submit", "hello", "--json", "--tenant", "alice", "--cap", "logging", "--cap", "wasi",
+            "--tenant", "bob",
+        ]);
+        assert_eq!(args.positional(0, "command").unwrap(), "submit");
+        assert_eq!(args.positional(1, "module").unwrap(), "hello");
+        assert!(args.flag("json"));
+        assert!(!args.flag("wait"));
+        // The last value wins; repeated options keep every value
+        assert_eq!(args.option("tenant"), Some("bob"));
+        assert_eq!(args.all("cap"), vec!["logging", "wasi"]);
+        assert_eq!(args.option("status"), None);
+        assert!(args.all("status").is_empty());
+    }
+
+    #[test]
+    fn options_need_a_value() {
+        let err = Args::parse(["list", "--tenant"].iter().map(|s| s.to_string()))
+            .err()
+            .unwrap();
+        assert!(matches!(&err, CliError::Usage(m) if m == "--tenant needs a value"));
+
+        let err = args(&["get"]).positional(1, "job id").unwrap_err();
+        assert!(matches!(&err, CliError::Usage(m) if m == "missing job id"));
+        assert!(err.to_string().starts_with("missing job id\n\nUsage"));
+    }
+
+    #[test]
+    fn config_path_comes_from_the_flag_then_env_then_home() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &std::path::Path, url: &str| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, format!("url = \"{}\"\n", url)).unwrap();
+        };
+        let flag = dir.path().join("flag.toml");
+        let from_env = dir.path().join("env.toml");
+        let home = dir.path().join(".config/sandboxctl/config.toml");
+        write(&flag, "http://flag");
+        write(&from_env, "http://env");
+        write(&home, "http://home");
+
+        let env = |name: &str| match name {
+            "SANDBOXCTL_CONFIG" => Some(from_env.to_str().unwrap().to_string()),
+            "HOME" => Some(dir.path().to_str().unwrap().to_string()),
+            _ => None,
+        };
+        let home_only = |name: &str| (name == "HOME").then(|| env(name)).flatten();
+
+        let url = |config: CliConfig| config.url.unwrap();
+        assert_eq!(
+            url(load_config_with(flag.to_str(), env).unwrap()),
+            "http://flag"
+        );
+        assert_eq!(url(load_config_with(None, env).unwrap()), "http://env");
+        assert_eq!(
+            url(load_config_with(None, home_only).unwrap()),
+            "http://home"
+        );
+    }
+
+    #[test]
+    fn missing_config_is_an_error_only_when_explicit() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = |name: &str| (name == "HOME").then(|| dir.path().to_str().unwrap().to_string());
+
+        let config = load_config_with(None, home).unwrap();
+        assert!(config.url.is_none() && config.admin_token.is_none());
+        assert!(load_config_with(None, |_| None).unwrap().url.is_none());
+
+        let missing = dir.path().join("missing.toml");
+        let err = load_config_with(missing.to_str(), home).err().unwrap();
+        assert!(matches!(err, CliError::Config(_)));
+        let err = load_config_with(None, |name| {
+            (name == "SANDBOXCTL_CONFIG").then(|| missing.to_str().unwrap().to_string())
+        })
+        .err()
+        .unwrap();
+        assert!(matches!(err, CliError::Config(_)));
+
+        let invalid = dir.path().join("invalid.toml");
+        std::fs::write(&invalid, "url = ").unwrap();
+        let err = load_config_with(invalid.to_str(), home).err().unwrap();
+        assert!(
+            err.to_string()
+                .starts_with(&format!("Config: {}:", invalid.display()))
+        );
+    }
+
+    #[test]
+    fn url_flag_wins_over_config_and_default() {
+        let config = CliConfig {
+            url: Some("http://config:3000/".to_string()),
+            tenant_id: None,
+            admin_token: Some("secret".to_string()),
+        };
+
+        let flagged = client(&args(&["list", "--url", "http://flag:3000"]), &config);
+        assert_eq!(flagged.url, "http://flag:3000");
+        assert_eq!(flagged.admin_token.as_deref(), Some("secret"));
+        assert_eq!(client(&args(&["list"]), &config).url, "http://config:3000");
+        assert_eq!(
+            client(&args(&["list"]), &CliConfig::default()).url,
+            DEFAULT_URL
+        );
+    }
+
+    #[test]
+    fn list_path_uses_the_configured_tenant_unless_given() {
+        let config = CliConfig {
+            tenant_id: Some("alice".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            list_path(&args(&["list"]), &CliConfig::default()),
+            "/jobs/list"
+        );
+        assert_eq!(
+            list_path(&args(&["list", "--status", "failed"]), &config),
+            "/jobs/list?tenant_id=alice&status=failed"
+        );
+        assert_eq!(
+            list_path(&args(&["list", "--tenant", "bob"]), &config),
+            "/jobs/list?tenant_id=bob"
+        );
+    }
+
+    #[test]
+    fn formats_statuses_outputs_and_tables() {
+        assert_eq!(status(&json!("queued")), ("queued".to_string(), None));
+        assert_eq!(
+            status(&json!({"failed": "trap"})),
+            ("failed".to_string(), Some("trap".to_string()))
+        );
+        assert!(!is_done(&json!({"status": "running"})));
+        assert!(is_done(&json!({"status": {"failed": "trap"}})));
+
+        assert_eq!(output_text(&json!([104, 105])), "hi");
+        assert_eq!(output_text(&json!([255, 0, 1])), "<3 bytes>");
+        assert_eq!(text(&Value::Null), "-");
+        assert_eq!(duration(&json!([1, 500_000_000])), "1.500s");
+
+        let list = json!({"jobs": [{
+            "job_id": "j1",
+            "tenant_id": "alice",
+            "module_id": "simple-compute",
+            "backend": null,
+            "status": "finished",
+            "submitted_at": [2026, 100, 13, 5, 9, 0, 0, 0, 0],
+        }]});
+        let rows = job_rows(&list);
+        assert_eq!(
+            rows,
+            vec![vec![
+                "j1",
+                "alice",
+                "simple-compute",
+                "-",
+                "finished",
+                "2026-04-10 13:05:09"
+            ]]
+        );
+        assert_eq!(
+            format_table(JOB_HEADERS, &rows),
+            "JOB  TENANT  MODULE          BACKEND  STATUS    SUBMITTED\n\
+             j1   alice   simple-compute  -        finished  2026-04-10 13:05:09\n"
+        );
+    }
+
+    #[test]
+    fn error_responses_become_api_errors() {
+        let body = r#"{"error":"job_not_cancellable","message":"job j1 already finished"}"#;
+        let client = Client::new(respond_once("409 Conflict", body), None);
+        let err = client
+            .post_json("/jobs/j1/cancel", &json!({}))
+            .err()
+            .unwrap();
+        assert!(matches!(err, CliError::Api { status: 409, .. }));
+        assert_eq!(
+            err.to_string(),
+            "409 job_not_cancellable: job j1 already finished"
+        );
+
+        // Without a JSON body the status line fills in
+        let client = Client::new(respond_once("502 Bad Gateway", "upstream down"), None);
+        let err = client.get("/jobs/list").err().unwrap();
+        assert_eq!(err.to_string(), "502 bad_gateway: upstream down");
+
+        let client = Client::new(respond_once("404 Not Found", ""), None);
+        let err = client.get("/jobs/missing").err().unwrap();
+        assert_eq!(err.to_string(), "404 not_found: Not Found");
+
+        let client = Client::new(respond_once("200 OK", r#"{"jobs":[]}"#), None);
+        assert_eq!(client.get("/jobs/list").unwrap(), json!({"jobs": []}));
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::tenant::Tenant;
+
+/// A capability as declared under `[capabilities.<name>]` in `config.toml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CapabilityDefinition {
+    pub description: String,
+    pub version: String,
+    #[serde(default)]
+    pub params: Option<serde_json::Value>,
+}
+
+/// Typed parameters, one variant per capability the sandbox implements.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CapabilityParams {
+    GpuCompute,
+    Logging(LoggingParams),
+    NetworkEgress(EgressParams),
+    Wasi(WasiParams),
+    WasiFs,
+    WasiClocks,
+    WasiRandom,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingParams {
+    /// Total bytes a job may log
+    #[serde(default = "default_log_bytes")]
+    pub max_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EgressParams {
+    /// Hosts (optionally `host:port`) the guest may reach. Empty allows none.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Applies to request and response bodies alike
+    #[serde(default = "default_body_bytes")]
+    pub max_body_bytes: usize,
+    #[serde(default = "default_egress_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasiParams {
+    /// Cap on captured stdout and on captured stderr
+    #[serde(default = "default_output_bytes")]
+    pub max_output_bytes: usize,
+}
+
+fn default_log_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_body_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_output_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_egress_timeout_ms() -> u64 {
+    5_000
+}
+
+impl CapabilityParams {
+    fn parse(name: &str, params: Option<&serde_json::Value>) -> Result<Self, CapabilityError> {
+        let value = params
+            .cloned()
+            .unwrap_or_else(|| serde_json::Value::Object(Default::default()));
+        let invalid =
+            |e: serde_json::Error| CapabilityError::InvalidParams(name.to_string(), e.to_string());
+
+        match name {
+            "gpu.compute" => Ok(CapabilityParams::GpuCompute),
+            "logging" => Ok(CapabilityParams::Logging(
+                serde_json::from_value(value).map_err(invalid)?,
+            )),
+            "network.egress" => Ok(CapabilityParams::NetworkEgress(
+                serde_json::from_value(value).map_err(invalid)?,
+            )),
+            "wasi" => Ok(CapabilityParams::Wasi(
+                serde_json::from_value(value).map_err(invalid)?,
+            )),
+            "wasi.fs" => Ok(CapabilityParams::WasiFs),
+            "wasi.clocks" => Ok(CapabilityParams::WasiClocks),
+            "wasi.random" => Ok(CapabilityParams::WasiRandom),
+            _ => Err(CapabilityError::Unimplemented(name.to_string())),
+        }
+    }
+
+    /// Applies a tenant grant on top of the registry parameters. Parameters
+    /// the grant leaves out are inherited; a grant can only tighten limits,
+    /// never widen them.
+    fn narrow(&self, name: &str, grant: &serde_json::Value) -> Result<Self, CapabilityError> {
+        match self {
+            CapabilityParams::GpuCompute
+            | CapabilityParams::WasiFs
+            | CapabilityParams::WasiClocks
+            | CapabilityParams::WasiRandom => Ok(self.clone()),
+            CapabilityParams::Logging(base) => {
+                let granted: LoggingParams = merge(name, base, grant)?;
+                Ok(CapabilityParams::Logging(LoggingParams {
+                    max_bytes: base.max_bytes.min(granted.max_bytes),
+                }))
+            }
+            CapabilityParams::NetworkEgress(base) => {
+                let granted: EgressParams = merge(name, base, grant)?;
+                if let Some(host) = granted
+                    .allowed_hosts
+                    .iter()
+                    .find(|h| !host_covered(&base.allowed_hosts, h))
+                {
+                    return Err(CapabilityError::WidenedGrant(
+                        name.to_string(),
+                        format!("host {} is not in the registry allowlist", host),
+                    ));
+                }
+                Ok(CapabilityParams::NetworkEgress(EgressParams {
+                    allowed_hosts: granted.allowed_hosts,
+                    max_body_bytes: base.max_body_bytes.min(granted.max_body_bytes),
+                    timeout_ms: base.timeout_ms.min(granted.timeout_ms),
+                }))
+            }
+            CapabilityParams::Wasi(base) => {
+                let granted: WasiParams = merge(name, base, grant)?;
+                Ok(CapabilityParams::Wasi(WasiParams {
+                    max_output_bytes: base.max_output_bytes.min(granted.max_output_bytes),
+                }))
+            }
+        }
+    }
+}
+
+/// Whether an allowlist reaches `host`. A bare host allows any port, so it
+/// also covers every `host:port` entry for that host.
+fn host_covered(allowed_hosts: &[String], host: &str) -> bool {
+    let bare = host
+        .rsplit_once(':')
+        .filter(|(_, port)| port.parse::<u16>().is_ok())
+        .map(|(bare, _)| bare);
+    allowed_hosts
+        .iter()
+        .any(|allowed| allowed == host || Some(allowed.as_str()) == bare)
+}
+
+/// Overlays the keys of `grant` onto the serialized `base` parameters.
+fn merge<T: Serialize + DeserializeOwned>(
+    name: &str,
+    base: &T,
+    grant: &serde_json::Value,
+) -> Result<T, CapabilityError> {
+    let invalid =
+        |e: serde_json::Error| CapabilityError::InvalidParams(name.to_string(), e.to_string());
+
+    let mut merged = serde_json::to_value(base).map_err(invalid)?;
+    let (Some(merged_map), Some(grant_map)) = (merged.as_object_mut(), grant.as_object()) else {
+        return Err(CapabilityError::InvalidParams(
+            name.to_string(),
+            "params must be an object".to_string(),
+        ));
+    };
+    for (key, value) in grant_map {
+        merged_map.insert(key.clone(), value.clone());
+    }
+
+    serde_json::from_value(merged).map_err(invalid)
+}
+
+/// An entry in a tenant's `allowed_capabilities`: either a bare name, which
+/// takes the registry parameters as-is, or a name with narrowed parameters.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum CapabilityGrant {
+    Name(String),
+    WithParams {
+        name: String,
+        params: serde_json::Value,
+    },
+}
+
+impl CapabilityGrant {
+    pub fn name(&self) -> &str {
+        match self {
+            CapabilityGrant::Name(name) => name,
+            CapabilityGrant::WithParams { name, .. } => name,
+        }
+    }
+
+    fn params(&self) -> Option<&serde_json::Value> {
+        match self {
+            CapabilityGrant::Name(_) => None,
+            CapabilityGrant::WithParams { params, .. } => Some(params),
+        }
+    }
+}
+
+/// A capability with the parameters that apply to one job.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedCapability {
+    pub name: String,
+    pub version: String,
+    pub params: CapabilityParams,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisteredCapability {
+    pub name: String,
+    pub description: String,
+    pub version: String,
+    pub params: CapabilityParams,
+}
+
+pub struct CapabilityRegistry {
+    capabilities: BTreeMap<String, RegisteredCapability>,
+}
+
+impl CapabilityRegistry {
+    pub fn new(
+        definitions: &HashMap<String, CapabilityDefinition>,
+    ) -> Result<Self, CapabilityError> {
+        let capabilities = definitions
+            .iter()
+            .map(|(name, def)| {
+                let params = CapabilityParams::parse(name, def.params.as_ref())?;
+                Ok((
+                    name.clone(),
+                    RegisteredCapability {
+                        name: name.clone(),
+                        description: def.description.clone(),
+                        version: def.version.clone(),
+                        params,
+                    },
+                ))
+            })
+            .collect::<Result<_, CapabilityError>>()?;
+
+        Ok(CapabilityRegistry { capabilities })
+    }
+
+    pub fn list(&self) -> Vec<RegisteredCapability> {
+        self.capabilities.values().cloned().collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.capabilities.contains_key(name)
+    }
+
+    /// Checks that every grant names a registered capability and only
+    /// narrows its parameters. Run when tenants are loaded.
+    pub fn validate_tenant(&self, tenant: &Tenant) -> Result<(), CapabilityError> {
+        for grant in &tenant.allowed_capabilities {
+            self.resolve_grant(grant)?;
+        }
+        Ok(())
+    }
+
+    /// Resolves the capabilities a job asked for against its tenant's grants.
+    pub fn resolve(
+        &self,
+        tenant: &Tenant,
+        requested: &[String],
+    ) -> Result<Vec<ResolvedCapability>, CapabilityError> {
+        requested
+            .iter()
+            .map(|name| {
+                let Some(grant) = tenant.grant(name) else {
+                    return Err(CapabilityError::NotGranted(name.clone()));
+                };
+                self.resolve_grant(grant)
+            })
+            .collect()
+    }
+
+    fn resolve_grant(
+        &self,
+        grant: &CapabilityGrant,
+    ) -> Result<ResolvedCapability, CapabilityError> {
+        let Some(registered) = self.capabilities.get(grant.name()) else {
+            return Err(CapabilityError::Unknown(grant.name().to_string()));
+        };
+
+        let params = match grant.params() {
+            Some(params) => registered.params.narrow(grant.name(), params)?,
+            None => registered.params.clone(),
+        };
+
+        Ok(ResolvedCapability {
+            name: registered.name.clone(),
+            version: registered.version.clone(),
+            params,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CapabilityError {
+    #[error("Unknown capability {0}")]
+    Unknown(String),
+    #[error("Capability {0} has no sandbox implementation")]
+    Unimplemented(String),
+    #[error("Capability {0} not granted")]
+    NotGranted(String),
+    #[error("Invalid parameters for capability {0}: {1}")]
+    InvalidParams(String, String),
+    #[error("Grant for capability {0} widens the registry parameters: {1}")]
+    WidenedGrant(String, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn egress(allowed_hosts: &[&str]) -> CapabilityParams {
+        CapabilityParams::NetworkEgress(EgressParams {
+            allowed_hosts: allowed_hosts.iter().map(|h| h.to_string()).collect(),
+            max_body_bytes: 1024,
+            timeout_ms: 1_000,
+        })
+    }
+
+    #[test]
+    fn egress_grants_may_only_narrow_the_allowlist() {
+        // (registry allowlist, granted allowlist, accepted)
+        let cases: &[(&[&str], &[&str], bool)] = &[
+            (&["api.example"], &["api.example"], true),
+            (&["api.example"], &["api.example:443"], true),
+            (&["api.example", "db.example"], &["db.example:5432"], true),
+            (&["api.example:443"], &["api.example:443"], true),
+            (&["api.example"], &[], true),
+            (&["api.example:443"], &["api.example"], false),
+            (&["api.example:443"], &["api.example:80"], false),
+            (&["api.example"], &["other.example:443"], false),
+            (&["api.example"], &["api.example.evil:443"], false),
+            (&["api.example"], &["api.example:notaport"], false),
+            (&[], &["api.example"], false),
+        ];
+
+        for (base, granted, accepted) in cases {
+            let grant = serde_json::json!({ "allowed_hosts": granted });
+            let result = egress(base).narrow("network.egress", &grant);
+            assert_eq!(result.is_ok(), *accepted, "{:?} -> {:?}", base, granted);
+
+            match result {
+                Ok(CapabilityParams::NetworkEgress(params)) => {
+                    assert_eq!(params.allowed_hosts, *granted);
+                }
+                Ok(other) => panic!("unexpected params {:?}", other),
+                Err(err) => assert!(matches!(err, CapabilityError::WidenedGrant(..))),
+            }
+        }
+    }
+
+    #[test]
+    fn grants_keep_the_tighter_limits() {
+        let grant = serde_json::json!({ "max_body_bytes": 4096, "timeout_ms": 10 });
+        let Ok(CapabilityParams::NetworkEgress(params)) =
+            egress(&["api.example"]).narrow("network.egress", &grant)
+        else {
+            panic!("grant should narrow");
+        };
+        assert_eq!(params.allowed_hosts, ["api.example"]);
+        assert_eq!(params.max_body_bytes, 1024);
+        assert_eq!(params.timeout_ms, 10);
+
+        let logging = CapabilityParams::Logging(LoggingParams { max_bytes: 100 });
+        let grant = serde_json::json!({ "max_bytes": 1_000_000 });
+        let Ok(CapabilityParams::Logging(params)) = logging.narrow("logging", &grant) else {
+            panic!("grant should narrow");
+        };
+        assert_eq!(params.max_bytes, 100);
+    }
+}
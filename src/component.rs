@@ -0,0 +1,283 @@
+use wasmtime::Engine;
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, HasSelf, Linker};
+
+use crate::capability::{CapabilityParams, ResolvedCapability};
+use crate::egress;
+use crate::sandbox::{SandboxContext, SandboxError};
+use crate::tensor::{DType, Tensor};
+use crate::vgpu::{Kernel, VgpuError};
+
+wasmtime::component::bindgen!({
+    path: "wit/guest.wit",
+    world: "guest",
+});
+
+use gpu_sandbox::guest::{compute, device, egress as guest_egress, logging, tensors, types};
+
+/// The `gpu-sandbox:guest` package version components must target.
+pub const WORLD_VERSION: &str = "0.1.0";
+
+/// Core modules and components share the `\0asm` magic and differ in the
+/// version/layer field that follows it.
+pub fn is_component(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && bytes[0..4] == *b"\0asm" && bytes[6..8] == [0x01, 0x00]
+}
+
+/// The capabilities each importable interface needs. `types` and `tensors`
+/// only touch the job's own data and are always available. WASI preview2
+/// interfaces need `wasi`, and clocks and random their own grant on top.
+fn required_capabilities(interface: &str) -> Option<&'static [&'static str]> {
+    if let Some(wasi) = interface.strip_prefix("wasi:") {
+        let (path, version) = wasi.split_once('@')?;
+        if !version.starts_with("0.2.") {
+            return None;
+        }
+        return match path.split_once('/')?.0 {
+            "cli" | "io" | "filesystem" => Some(&["wasi"]),
+            "clocks" => Some(&["wasi", "wasi.clocks"]),
+            "random" => Some(&["wasi", "wasi.random"]),
+            _ => None,
+        };
+    }
+
+    let (name, version) = interface
+        .strip_prefix("gpu-sandbox:guest/")?
+        .split_once('@')?;
+    if version != WORLD_VERSION {
+        return None;
+    }
+    match name {
+        "types" | "tensors" => Some(&[]),
+        "logging" => Some(&["logging"]),
+        "compute" | "device" => Some(&["gpu.compute"]),
+        "egress" => Some(&["network.egress"]),
+        _ => None,
+    }
+}
+
+/// Checks every import of `component` against the world and the granted
+/// capabilities.
+pub fn check_imports(
+    engine: &Engine,
+    component: &Component,
+    capabilities: &[ResolvedCapability],
+) -> Result<(), SandboxError> {
+    for (name, item) in component.component_type().imports(engine) {
+        if !matches!(item, ComponentItem::ComponentInstance(_)) {
+            return Err(SandboxError::CapabilityViolation(format!(
+                "Component imports {} which is not part of gpu-sandbox:guest@{} or WASI 0.2",
+                name, WORLD_VERSION
+            )));
+        }
+        let Some(required) = required_capabilities(name) else {
+            return Err(SandboxError::CapabilityViolation(format!(
+                "Component imports {} which is not part of gpu-sandbox:guest@{} or WASI 0.2",
+                name, WORLD_VERSION
+            )));
+        };
+        if let Some(capability) = required
+            .iter()
+            .find(|&&required| !capabilities.iter().any(|c| c.name == required))
+        {
+            return Err(SandboxError::CapabilityViolation(format!(
+                "Component imports {} which needs {}, not granted",
+                name, capability
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Links the world interfaces covered by the job's capabilities.
+pub fn build_linker(
+    engine: &Engine,
+    capabilities: &[ResolvedCapability],
+) -> Result<Linker<SandboxContext>, SandboxError> {
+    let link_failed = |e: wasmtime::Error| {
+        SandboxError::ExecutionFailed(format!("Failed to link component imports: {}", e))
+    };
+
+    let mut linker = Linker::new(engine);
+    types::add_to_linker::<_, HasSelf<_>>(&mut linker, |ctx| ctx).map_err(link_failed)?;
+    tensors::add_to_linker::<_, HasSelf<_>>(&mut linker, |ctx| ctx).map_err(link_failed)?;
+
+    for capability in capabilities {
+        match &capability.params {
+            CapabilityParams::GpuCompute => {
+                compute::add_to_linker::<_, HasSelf<_>>(&mut linker, |ctx| ctx)
+                    .map_err(link_failed)?;
+                device::add_to_linker::<_, HasSelf<_>>(&mut linker, |ctx| ctx)
+                    .map_err(link_failed)?
+            }
+            CapabilityParams::Logging(_) => {
+                logging::add_to_linker::<_, HasSelf<_>>(&mut linker, |ctx| ctx)
+                    .map_err(link_failed)?
+            }
+            CapabilityParams::NetworkEgress(_) => {
+                guest_egress::add_to_linker::<_, HasSelf<_>>(&mut linker, |ctx| ctx)
+                    .map_err(link_failed)?
+            }
+            // WASI 0.2 for wasip2 guests; `check_imports` keeps out the
+            // interfaces the job wasn't granted
+            CapabilityParams::Wasi(_) => {
+                wasmtime_wasi::p2::add_to_linker_sync(&mut linker).map_err(link_failed)?
+            }
+            CapabilityParams::WasiFs
+            | CapabilityParams::WasiClocks
+            | CapabilityParams::WasiRandom => {}
+        }
+    }
+
+    Ok(linker)
+}
+
+pub fn job_input(job: &crate::domain::Job) -> JobInput {
+    JobInput {
+        job_id: job.job_id.to_string(),
+        tenant_id: job.tenant_id.clone(),
+        module_id: job.module_id.clone(),
+        payload: job.payload.to_string(),
+    }
+}
+
+impl types::Host for SandboxContext {}
+
+impl From<DType> for tensors::Dtype {
+    fn from(dtype: DType) -> Self {
+        match dtype {
+            DType::F32 => tensors::Dtype::F32,
+            DType::F64 => tensors::Dtype::F64,
+            DType::I32 => tensors::Dtype::I32,
+            DType::I64 => tensors::Dtype::I64,
+            DType::U8 => tensors::Dtype::U8,
+        }
+    }
+}
+
+impl From<tensors::Dtype> for DType {
+    fn from(dtype: tensors::Dtype) -> Self {
+        match dtype {
+            tensors::Dtype::F32 => DType::F32,
+            tensors::Dtype::F64 => DType::F64,
+            tensors::Dtype::I32 => DType::I32,
+            tensors::Dtype::I64 => DType::I64,
+            tensors::Dtype::U8 => DType::U8,
+        }
+    }
+}
+
+impl tensors::Host for SandboxContext {
+    fn input(&mut self, name: String) -> Option<tensors::Tensor> {
+        self.tensors.inputs.get(&name).map(|t| tensors::Tensor {
+            dtype: t.dtype().into(),
+            shape: t.shape().iter().map(|d| *d as u32).collect(),
+            data: t.bytes().to_vec(),
+        })
+    }
+
+    fn output(&mut self, name: String, value: tensors::Tensor) -> Result<(), String> {
+        let shape = value.shape.iter().map(|d| *d as usize).collect();
+        let tensor =
+            Tensor::new(value.dtype.into(), shape, value.data).map_err(|e| e.to_string())?;
+        self.tensors.outputs.insert(name, tensor);
+        Ok(())
+    }
+}
+
+impl logging::Host for SandboxContext {
+    fn log(&mut self, message: String) -> Result<(), String> {
+        if self.log_bytes_remaining == 0 {
+            return Err("Log budget exhausted".to_string());
+        }
+        let mut len = message.len().min(self.log_bytes_remaining);
+        while !message.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.write_log(&message[..len]);
+        Ok(())
+    }
+}
+
+impl compute::Host for SandboxContext {
+    fn compute(&mut self, value: i32) -> i32 {
+        self.gpu_compute(value)
+    }
+}
+
+impl From<VgpuError> for device::DeviceError {
+    fn from(e: VgpuError) -> Self {
+        match e {
+            VgpuError::OutOfMemory { requested, .. } => {
+                device::DeviceError::OutOfMemory(requested as u64)
+            }
+            VgpuError::InvalidHandle(handle) => device::DeviceError::InvalidHandle(handle),
+            VgpuError::UnknownKernel(name) => device::DeviceError::UnknownKernel(name),
+            VgpuError::OutOfBounds { handle, .. } => device::DeviceError::OutOfBounds(handle),
+            e @ (VgpuError::InvalidArguments(..) | VgpuError::GuestMemory) => {
+                device::DeviceError::InvalidArguments(e.to_string())
+            }
+        }
+    }
+}
+
+impl device::Host for SandboxContext {
+    fn alloc(&mut self, elements: u32) -> Result<u32, device::DeviceError> {
+        Ok(self.device.alloc(elements as usize)?)
+    }
+
+    fn free(&mut self, buffer: u32) -> Result<(), device::DeviceError> {
+        Ok(self.device.free(buffer)?)
+    }
+
+    fn write(&mut self, buffer: u32, data: Vec<f32>) -> Result<(), device::DeviceError> {
+        Ok(self.device.write(buffer, &data)?)
+    }
+
+    fn read(&mut self, buffer: u32, elements: u32) -> Result<Vec<f32>, device::DeviceError> {
+        Ok(self.device.read(buffer, elements as usize)?.to_vec())
+    }
+
+    fn launch(
+        &mut self,
+        kernel: String,
+        buffers: Vec<u32>,
+        scalars: Vec<f32>,
+    ) -> Result<(), device::DeviceError> {
+        let kernel = Kernel::parse(&kernel)?;
+        Ok(self.device.launch(kernel, &buffers, &scalars)?)
+    }
+}
+
+impl guest_egress::Host for SandboxContext {
+    fn http_post(
+        &mut self,
+        url: String,
+        headers: Vec<guest_egress::Header>,
+        body: Vec<u8>,
+    ) -> Result<guest_egress::Response, guest_egress::EgressError> {
+        let headers: Vec<(String, String)> =
+            headers.into_iter().map(|h| (h.name, h.value)).collect();
+
+        match self.egress_post(url, &headers, &body) {
+            Ok(response) => Ok(guest_egress::Response {
+                status: response.status,
+                body: response.body,
+            }),
+            Err(e) => Err(match e {
+                egress::EgressError::InvalidUrl(url) => guest_egress::EgressError::InvalidUrl(url),
+                egress::EgressError::HostNotAllowed(host) => {
+                    guest_egress::EgressError::HostNotAllowed(host)
+                }
+                egress::EgressError::RequestTooLarge(len) => {
+                    guest_egress::EgressError::RequestTooLarge(len as u64)
+                }
+                egress::EgressError::ResponseTooLarge(len) => {
+                    guest_egress::EgressError::ResponseTooLarge(len as u64)
+                }
+                egress::EgressError::Timeout => guest_egress::EgressError::Timeout,
+                egress::EgressError::Transport(msg) => guest_egress::EgressError::Transport(msg),
+            }),
+        }
+    }
+}
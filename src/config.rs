@@ -1,17 +1,83 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
 use serde::Deserialize;
 use tokio::fs;
 
+use crate::capability::CapabilityDefinition;
+use crate::deadline::DispatchConfig;
+use crate::gpu_manager::{PlacementStrategy, ResourceShape};
+use crate::health::HealthConfig;
+use crate::quota::QuotaDefaults;
+use crate::shutdown::ShutdownConfig;
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub queue_length: usize,
-    pub gpu_slots: usize,
-    pub per_tenant_limit: usize,
+    /// Most jobs accepted by one `POST /jobs/batch`
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+    pub capabilities: HashMap<String, CapabilityDefinition>,
+    #[serde(default)]
+    pub quota_defaults: QuotaDefaults,
+    pub default_profile: String,
+    #[serde(default)]
+    pub placement_strategy: PlacementStrategy,
+    pub profiles: HashMap<String, ResourceShape>,
+    pub devices: Vec<DeviceConfig>,
+    /// Bearer token for the admin routes; they are disabled without one
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub dispatch: DispatchConfig,
+    /// Where `modules/{id}.wasm` files are looked up and uploaded to
+    #[serde(default = "default_modules_dir")]
+    pub modules_dir: PathBuf,
+    /// Native models; ONNX models live under its `onnx/` subdirectory
+    #[serde(default = "default_models_dir")]
+    pub models_dir: PathBuf,
+}
+
+fn default_max_batch_size() -> usize {
+    100
+}
+
+fn default_modules_dir() -> PathBuf {
+    PathBuf::from("modules")
+}
+
+fn default_models_dir() -> PathBuf {
+    PathBuf::from("models")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceConfig {
+    pub device_id: String,
+    pub compute_slices: u32,
+    pub memory_gb: u32,
 }
 
 impl Config {
     pub async fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let contents: String = fs::read_to_string(path).await?;
-        let config = toml::from_str(&contents)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(contents)?;
+
+        if !config.profiles.contains_key(&config.default_profile) {
+            return Err(format!(
+                "default_profile {} is not a known profile",
+                config.default_profile
+            )
+            .into());
+        }
+
         Ok(config)
     }
 }
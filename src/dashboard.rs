@@ -0,0 +1,27 @@
+//! The operator dashboard under `/dashboard`. The page is static and polls
+//! the JSON API, so its files are compiled into the binary.
+
+use axum::http::header;
+use axum::response::{Html, IntoResponse};
+
+const INDEX_HTML: &str = include_str!("../dashboard/index.html");
+const DASHBOARD_JS: &str = include_str!("../dashboard/dashboard.js");
+const DASHBOARD_CSS: &str = include_str!("../dashboard/dashboard.css");
+
+pub async fn index() -> impl IntoResponse {
+    Html(INDEX_HTML)
+}
+
+pub async fn script() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/javascript; charset=utf-8")],
+        DASHBOARD_JS,
+    )
+}
+
+pub async fn stylesheet() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/css; charset=utf-8")],
+        DASHBOARD_CSS,
+    )
+}
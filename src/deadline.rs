@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::domain::{Job, JobStatus};
+use crate::gpu_manager::GpuManager;
+use crate::state::AppState;
+
+/// Weight of the latest run in a module's runtime estimate.
+const ESTIMATE_WEIGHT: f64 = 0.3;
+
+/// Order in which the dispatcher walks the pending jobs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DispatchPolicy {
+    /// Oldest job first
+    #[default]
+    Fifo,
+    /// Earliest deadline first; jobs without one go last, oldest first
+    Edf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DispatchConfig {
+    pub policy: DispatchPolicy,
+    /// Runtime assumed for modules that haven't finished a job yet
+    pub default_runtime_secs: u64,
+}
+
+impl Default for DispatchConfig {
+    fn default() -> Self {
+        Self {
+            policy: DispatchPolicy::Fifo,
+            default_runtime_secs: 1,
+        }
+    }
+}
+
+/// Moving average of how long jobs of a backend and module (or list of
+/// pipeline stages) take to run, used to turn down deadlines on submit.
+pub struct RuntimeEstimates {
+    estimates: Mutex<HashMap<String, Duration>>,
+    default: Duration,
+}
+
+impl RuntimeEstimates {
+    pub fn new(default: Duration) -> Self {
+        Self {
+            estimates: Mutex::new(HashMap::new()),
+            default,
+        }
+    }
+
+    /// Folds the runtime of a job that finished into its module's estimate.
+    pub fn record(&self, job: &Job, runtime: Duration) {
+        let mut estimates = self.estimates.lock().unwrap();
+        estimates
+            .entry(key(job))
+            .and_modify(|estimate| {
+                *estimate =
+                    estimate.mul_f64(1.0 - ESTIMATE_WEIGHT) + runtime.mul_f64(ESTIMATE_WEIGHT)
+            })
+            .or_insert(runtime);
+    }
+
+    pub fn estimate(&self, job: &Job) -> Duration {
+        let estimates = self.estimates.lock().unwrap();
+        estimates.get(&key(job)).copied().unwrap_or(self.default)
+    }
+}
+
+fn key(job: &Job) -> String {
+    if job.stages.is_empty() {
+        return format!("{}/{}", job.backend, job.module_id);
+    }
+    let modules: Vec<&str> = job.stages.iter().map(|s| s.module_id.as_str()).collect();
+    format!("{}/{}", job.backend, modules.join("+"))
+}
+
+/// When a job submitted now would finish at the earliest: the rest of the
+/// running jobs and the queued jobs dispatched before it are spread over all
+/// compute slices, then it runs for its estimated runtime. Host-CPU jobs take
+/// no slices and only wait for `not_before`. Either way it starts no earlier
+/// than the estimated finish of its unfinished `depends_on` parents; the
+/// `after` steps of a workflow aren't parents yet when this is checked.
+/// `None` if that is past the end of time, so no deadline can be met.
+pub fn earliest_finish(state: &AppState, job: &Job, now: OffsetDateTime) -> Option<OffsetDateTime> {
+    earliest_finish_in(state, job, now, &mut HashMap::new())
+}
+
+/// `earliest_finish`, with the parents already estimated in `parents`.
+fn earliest_finish_in(
+    state: &AppState,
+    job: &Job,
+    now: OffsetDateTime,
+    parents: &mut HashMap<Uuid, Option<OffsetDateTime>>,
+) -> Option<OffsetDateTime> {
+    let slices = |job: &Job| f64::from(job.resources.compute_slices) * f64::from(job.gang_size);
+    let mut start = now;
+
+    if slices(job) > 0.0 {
+        let edf = state.dispatch_policy == DispatchPolicy::Edf;
+        // Slice-seconds of work ahead of the job
+        let ahead: f64 = state
+            .jobs
+            .collect(|other| {
+                if other.job_id == job.job_id {
+                    return None;
+                }
+                let remaining = match other.status {
+                    JobStatus::Running => {
+                        let elapsed = other.started_at.map_or(time::Duration::ZERO, |s| now - s);
+                        state
+                            .runtime_estimates
+                            .estimate(other)
+                            .saturating_sub(elapsed.try_into().unwrap_or_default())
+                    }
+                    JobStatus::Queued if !edf || runs_before(other, job) => {
+                        state.runtime_estimates.estimate(other)
+                    }
+                    _ => return None,
+                };
+                Some(remaining.as_secs_f64() * slices(other))
+            })
+            .into_iter()
+            .sum();
+
+        let capacity = GpuManager::lock(&state.gpu_manager).total_capacity();
+        if capacity.compute_slices > 0 {
+            let wait =
+                Duration::try_from_secs_f64(ahead / f64::from(capacity.compute_slices)).ok()?;
+            start = start.checked_add(wait.try_into().ok()?)?;
+        }
+    }
+
+    for parent in &job.depends_on {
+        let finish = match parents.get(parent) {
+            Some(finish) => *finish,
+            None => {
+                let finish = parent_finish(state, *parent, now, parents);
+                parents.insert(*parent, finish);
+                finish
+            }
+        };
+        start = start.max(finish?);
+    }
+
+    start = start.max(job.not_before.unwrap_or(start));
+    start.checked_add(state.runtime_estimates.estimate(job).try_into().ok()?)
+}
+
+/// When parent `job_id` should be done: now if it already is, or is gone.
+fn parent_finish(
+    state: &AppState,
+    job_id: Uuid,
+    now: OffsetDateTime,
+    parents: &mut HashMap<Uuid, Option<OffsetDateTime>>,
+) -> Option<OffsetDateTime> {
+    let Some(parent) = state.jobs.get(job_id) else {
+        return Some(now);
+    };
+    match parent.status {
+        JobStatus::Running => {
+            let started = parent.started_at.unwrap_or(now);
+            let finish =
+                started.checked_add(state.runtime_estimates.estimate(&parent).try_into().ok()?)?;
+            Some(finish.max(now))
+        }
+        JobStatus::Queued => earliest_finish_in(state, &parent, now, parents),
+        _ => Some(now),
+    }
+}
+
+/// Whether EDF dispatch takes queued job `other` before `job`.
+fn runs_before(other: &Job, job: &Job) -> bool {
+    match (other.deadline, job.deadline) {
+        (Some(theirs), Some(ours)) => theirs <= ours,
+        (Some(_), None) | (None, None) => true,
+        (None, Some(_)) => false,
+    }
+}
+
+/// Sorts pending jobs for `DispatchPolicy::Edf`. The sort is stable, so jobs
+/// with the same deadline, or none, keep their submission order.
+pub fn by_deadline(a: &Job, b: &Job) -> std::cmp::Ordering {
+    (a.deadline.is_none(), a.deadline).cmp(&(b.deadline.is_none(), b.deadline))
+}
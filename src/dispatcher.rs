@@ -1,14 +1,282 @@
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use time::OffsetDateTime;
 use tokio::sync::mpsc::Receiver;
+use tokio::sync::{Mutex, watch};
+use tokio::task::{self, JoinError, JoinSet};
+use uuid::Uuid;
 
-use crate::domain::{Job, JobStatus};
-use crate::sandbox::{ExecutionResult, SandboxError, SandboxExecutor};
+use crate::backend::{ExecutionBackend, ResourceModel};
+use crate::capability::ResolvedCapability;
+use crate::deadline::{self, DispatchPolicy};
+use crate::domain::{Job, JobStatus, ParentFailurePolicy};
+use crate::gpu_manager::{GpuError, GpuManager, SlotReservation};
+use crate::health::HEARTBEAT_INTERVAL;
+use crate::pipeline;
 use crate::state::AppState;
 use crate::tenant;
-use crate::tenant::TenantStatus;
+use crate::workflow::{self, Parents};
+
+/// Wait before the first restart of a crashed dispatcher, doubled per crash.
+const MIN_RESTART_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+
+/// What the dispatch loop works on. It lives outside the loop so it survives
+/// a panic and the restarted loop carries on with the same jobs.
+struct DispatchQueue {
+    rx: Receiver<Job>,
+    // Jobs waiting for GPU capacity or their `not_before`, in dispatch order
+    pending: VecDeque<Job>,
+    // Jobs whose parents haven't all finished yet
+    waiting: VecDeque<Job>,
+    // One `run_task` per started job
+    running: JoinSet<()>,
+    tasks: HashMap<task::Id, Uuid>,
+    // The job being dispatched, blamed if the loop panics
+    current: Option<Uuid>,
+    closed: bool,
+}
+
+impl DispatchQueue {
+    fn new(rx: Receiver<Job>) -> Self {
+        Self {
+            rx,
+            pending: VecDeque::new(),
+            waiting: VecDeque::new(),
+            running: JoinSet::new(),
+            tasks: HashMap::new(),
+            current: None,
+            closed: false,
+        }
+    }
+
+    /// The next `not_before` or deadline of a job that hasn't started, so the
+    /// loop wakes up for it between heartbeats.
+    fn next_timer(&self, now: OffsetDateTime) -> Option<Duration> {
+        self.pending
+            .iter()
+            .chain(&self.waiting)
+            .flat_map(|job| [job.not_before, job.deadline])
+            .flatten()
+            .filter(|at| *at > now)
+            .min()
+            .and_then(|at| (at - now).try_into().ok())
+    }
+
+    fn spawn_task(
+        &mut self,
+        job: Job,
+        capabilities: Vec<ResolvedCapability>,
+        backend: Arc<dyn ExecutionBackend>,
+        reservation: Option<SlotReservation>,
+        state: &AppState,
+    ) {
+        let job_id = job.job_id;
+        let handle = self.running.spawn(run_task(
+            job,
+            capabilities,
+            backend,
+            reservation,
+            state.clone(),
+        ));
+        self.tasks.insert(handle.id(), job_id);
+    }
+
+    /// Forgets a finished `run_task`, and fails its job if it panicked.
+    fn task_finished(&mut self, joined: Result<(task::Id, ()), JoinError>, state: &AppState) {
+        let id = match &joined {
+            Ok((id, ())) => *id,
+            Err(e) => e.id(),
+        };
+        let Some(job_id) = self.tasks.remove(&id) else {
+            return;
+        };
+
+        if let Err(e) = joined
+            && e.is_panic()
+        {
+            let message = panic_message(e.into_panic());
+            eprintln!("Task for job {} panicked: {}", job_id, message);
+            fail_unfinished(state, job_id, &format!("Job task panicked: {}", message));
+        }
+    }
+}
+
+/// Runs the dispatch loop and restarts it with backoff if it panics. The job
+/// it was dispatching is failed, so the same job can't crash it again.
+pub async fn run_dispatcher(rx: Receiver<Job>, state: AppState, grace_period: Duration) {
+    let queue = Arc::new(Mutex::new(DispatchQueue::new(rx)));
+    let mut shutdown = state.shutdown.subscribe();
+    let mut backoff = MIN_RESTART_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+        let joined = tokio::spawn(dispatch_loop(queue.clone(), state.clone())).await;
+        let message = match joined {
+            Err(e) if e.is_panic() => panic_message(e.into_panic()),
+            _ => break,
+        };
+
+        state.dispatcher_health.record_crash();
+        match queue.lock().await.current.take() {
+            Some(job_id) => {
+                eprintln!("Dispatcher panicked on job {}: {}", job_id, message);
+                fail_unfinished(
+                    &state,
+                    job_id,
+                    &format!("Dispatcher crashed on this job: {}", message),
+                );
+            }
+            None => eprintln!("Dispatcher panicked: {}", message),
+        }
+
+        // Only back off further while crashes come in quick succession
+        if started.elapsed() > MAX_RESTART_BACKOFF {
+            backoff = MIN_RESTART_BACKOFF;
+        }
+        eprintln!("Restarting dispatcher in {}ms", backoff.as_millis());
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown_requested(&mut shutdown) => break,
+        }
+        backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+    }
+
+    // Pending jobs are left queued; `shutdown::persist_queued` reports them
+    let _alive = state.dispatcher_health.start();
+    let mut queue = queue.lock().await;
+    queue.rx.close();
+    drain(&mut queue.running, &state, grace_period).await;
+}
+
+async fn dispatch_loop(queue: Arc<Mutex<DispatchQueue>>, state: AppState) {
+    let mut queue = queue.lock().await;
+    let slot_released = GpuManager::lock(&state.gpu_manager).released();
+    let mut shutdown = state.shutdown.subscribe();
+    let _alive = state.dispatcher_health.start();
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+    loop {
+        let queue = &mut *queue;
+        let timer = queue.next_timer(OffsetDateTime::now_utc());
+        tokio::select! {
+            // `queue_length` is enforced when jobs are admitted, so take all of them
+            job = queue.rx.recv(), if !queue.closed => match job {
+                Some(job) if job.depends_on.is_empty() => queue.pending.push_back(job),
+                Some(job) => queue.waiting.push_back(job),
+                None => queue.closed = true,
+            },
+            _ = slot_released.notified() => {}
+            Some(joined) = queue.running.join_next_with_id() => {
+                queue.task_finished(joined, &state);
+            }
+            _ = heartbeat.tick() => {}
+            _ = tokio::time::sleep(timer.unwrap_or_default()), if timer.is_some() => {}
+            _ = shutdown_requested(&mut shutdown) => break,
+        }
+
+        dispatch_pending(queue, &state).await;
+        state.dispatcher_health.beat();
+
+        if queue.closed && queue.pending.is_empty() && queue.waiting.is_empty() {
+            break;
+        }
+    }
+}
+
+async fn shutdown_requested(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|shutting_down| *shutting_down).await;
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+/// Waits up to `grace_period` for the started jobs, then cancels the rest and
+/// aborts their tasks. Aborting drops their slot reservations; the guests
+/// stop at their next epoch tick.
+async fn drain(running: &mut JoinSet<()>, state: &AppState, grace_period: Duration) {
+    if running.is_empty() {
+        return;
+    }
+    println!(
+        "Shutdown: waiting up to {}s for {} running job(s)",
+        grace_period.as_secs(),
+        running.len()
+    );
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let finished = tokio::time::timeout(grace_period, async {
+        loop {
+            tokio::select! {
+                joined = running.join_next() => if joined.is_none() { break },
+                _ = heartbeat.tick() => state.dispatcher_health.beat(),
+            }
+        }
+    })
+    .await;
+    if finished.is_ok() {
+        return;
+    }
+
+    let unfinished = state
+        .jobs
+        .collect(|job| matches!(job.status, JobStatus::Running).then_some(job.job_id));
+    for job_id in unfinished {
+        if cancel(state, job_id) == Some(Ok(())) {
+            println!("Shutdown: cancelled running job {}", job_id);
+        }
+    }
+
+    running.abort_all();
+    while running.join_next().await.is_some() {}
+}
+
+/// Walks the pending jobs in submission order, or by deadline under
+/// `DispatchPolicy::Edf`, and starts every job that fits. Jobs that don't fit
+/// or whose `not_before` is still ahead stay pending; the first one blocked on
+/// global capacity gets a hold so later, smaller jobs can only backfill around
+/// it. Jobs past their deadline expire.
+async fn dispatch_pending(queue: &mut DispatchQueue, state: &AppState) {
+    release_waiting(queue, state);
+    if state.dispatch_policy == DispatchPolicy::Edf {
+        queue
+            .pending
+            .make_contiguous()
+            .sort_by(deadline::by_deadline);
+    }
+
+    // Jobs that stay pending go straight back, so a panic only loses `current`
+    for _ in 0..queue.pending.len() {
+        let Some(job) = queue.pending.pop_front() else {
+            break;
+        };
+        queue.current = Some(job.job_id);
+
+        if is_cancelled(state, &job) {
+            GpuManager::lock(&state.gpu_manager).clear_hold(job.job_id);
+            continue;
+        }
+
+        let now = OffsetDateTime::now_utc();
+        if job.deadline.is_some_and(|deadline| deadline <= now) {
+            expire(state, &job);
+            continue;
+        }
+        if job.not_before.is_some_and(|not_before| not_before > now) {
+            queue.pending.push_back(job);
+            continue;
+        }
 
-pub async fn run_dispatcher(mut rx: Receiver<Job>, state: AppState) {
-    while let Some(job) = rx.recv().await {
         // Fetch the tenant
         let tenant_opt = {
             let tenants = state.tenants.read().await;
@@ -16,75 +284,249 @@ pub async fn run_dispatcher(mut rx: Receiver<Job>, state: AppState) {
         };
 
         let Some(tenant) = tenant_opt else {
-            let mut inner = state.inner.write().await;
-            if let Some(job_in_map) = inner.jobs.get_mut(&job.job_id) {
-                job_in_map.status = JobStatus::Failed("Tenant ID not found".to_string());
-                job_in_map.finished_at = Some(OffsetDateTime::now_utc());
-            }
+            fail_job(state, &job, "Tenant ID not found");
             continue;
         };
 
         // Validate tenant
         if !matches!(tenant.status, tenant::TenantStatus::Active) {
-            let mut inner = state.inner.write().await;
-            if let Some(job_in_map) = inner.jobs.get_mut(&job.job_id) {
-                job_in_map.status = JobStatus::Failed("Tenant not authorized".to_string());
-                job_in_map.finished_at = Some(OffsetDateTime::now_utc());
-            }
+            fail_job(state, &job, "Tenant not authorized");
             continue;
         }
 
-        if job
-            .capabilities
-            .iter()
-            .any(|c| !tenant.allowed_capabilities.contains(c))
-        {
-            let mut inner = state.inner.write().await;
-            if let Some(job_in_map) = inner.jobs.get_mut(&job.job_id) {
-                job_in_map.status =
-                    JobStatus::Failed("Unauthorized capabilities requested".to_string());
-                job_in_map.finished_at = Some(OffsetDateTime::now_utc());
+        let backend = match state.backends.get(&job.backend) {
+            Some(backend) if tenant.allowed_backends.contains(&job.backend) => backend,
+            _ => {
+                fail_job(
+                    state,
+                    &job,
+                    &format!("Backend {} not permitted", job.backend),
+                );
+                continue;
             }
+        };
+
+        let capabilities = match state.capabilities.resolve(&tenant, &job.capabilities) {
+            Ok(capabilities) => capabilities,
+            Err(e) => {
+                fail_job(
+                    state,
+                    &job,
+                    &format!("Unauthorized capabilities requested: {}", e),
+                );
+                continue;
+            }
+        };
+
+        let quotas = match state.quota_chain(&tenant, job.project_id.as_deref()).await {
+            Ok(quotas) => quotas,
+            Err(e) => {
+                fail_job(state, &job, &e.to_string());
+                continue;
+            }
+        };
+
+        // Host-CPU backends don't take a GPU slot
+        if backend.resource_model() == ResourceModel::HostCpu {
+            queue.spawn_task(job, capabilities, backend, None, state);
             continue;
         }
 
         // Try to allocate ressources
+        let reserved = GpuManager::try_reserve(
+            &state.gpu_manager,
+            &tenant.tenant_id,
+            &quotas,
+            job.job_id,
+            job.resources,
+            job.gang_size,
+        );
+        if matches!(reserved, Err(GpuError::NoGlobalCapacity)) {
+            let mut gpu_manager = GpuManager::lock(&state.gpu_manager);
+            gpu_manager.hold_for(job.job_id, &job.resources, job.gang_size);
+        }
+
+        match reserved {
+            Ok(reservation) => {
+                queue.spawn_task(job, capabilities, backend, Some(reservation), state);
+            }
+            Err(GpuError::NoGlobalCapacity | GpuError::QuotaReached(_)) => {
+                queue.pending.push_back(job);
+            }
+            Err(e) => {
+                fail_job(state, &job, &format!("GPU reservation failed: {}", e));
+            }
+        }
+    }
+
+    queue.current = None;
+}
+
+/// Moves jobs whose parents all finished to the pending queue, and settles
+/// jobs with a failed parent according to their `on_parent_failure` policy.
+fn release_waiting(queue: &mut DispatchQueue, state: &AppState) {
+    for _ in 0..queue.waiting.len() {
+        let Some(mut job) = queue.waiting.pop_front() else {
+            break;
+        };
+        queue.current = Some(job.job_id);
+
+        if is_cancelled(state, &job) {
+            continue;
+        }
+        if job
+            .deadline
+            .is_some_and(|deadline| deadline <= OffsetDateTime::now_utc())
         {
-            let mut gpu_manager = state.gpu_manager.write().await;
-            if let Err(_) = gpu_manager.try_reserve_slot(&tenant) {
-                let mut inner = state.inner.write().await;
-
-                if let Some(job_in_map) = inner.jobs.get_mut(&job.job_id) {
-                    job_in_map.status =
-                        JobStatus::Failed("No GPU capacity, please try again later".to_string());
-                    job_in_map.finished_at = Some(OffsetDateTime::now_utc());
+            expire(state, &job);
+            continue;
+        }
+
+        match workflow::parents(&state.jobs, &job) {
+            Parents::Pending => queue.waiting.push_back(job),
+            Parents::Finished => {
+                if job.pass_outputs {
+                    if let Err(e) = workflow::pass_outputs(&state.jobs, &mut job) {
+                        fail_job(state, &job, &e);
+                        continue;
+                    }
+                    state.jobs.update(job.job_id, |job_in_map| {
+                        job_in_map.tensors = job.tensors.clone();
+                        job_in_map.payload = job.payload.clone();
+                    });
                 }
-                continue;
+                queue.pending.push_back(job);
             }
+            Parents::Failed(parent) => match job.on_parent_failure {
+                ParentFailurePolicy::Cancel => {
+                    state.jobs.update(job.job_id, |job| {
+                        job.status = JobStatus::Cancelled;
+                        job.finished_at = Some(OffsetDateTime::now_utc());
+                    });
+                }
+                ParentFailurePolicy::Fail => {
+                    fail_job(
+                        state,
+                        &job,
+                        &format!("Parent job {} did not finish", parent),
+                    );
+                }
+            },
         }
+    }
 
-        let state_clone = state.clone();
+    queue.current = None;
+}
 
-        // This can return a handle so we can manage sth like timeouts
-        let _handle = tokio::spawn(run_task(job, state_clone));
-    }
+/// Cancels a queued or running job. A running job is told to stop through
+/// its `CancelFlag` and keeps its slot until its backend has returned, so the
+/// slot is never handed out while the guest still runs on it. Returns the
+/// status the job had, or `None` for unknown jobs.
+pub fn cancel(state: &AppState, job_id: Uuid) -> Option<Result<(), &'static str>> {
+    let now = OffsetDateTime::now_utc();
+    let cancelled = state.jobs.update(job_id, |job| {
+        if !matches!(job.status, JobStatus::Queued | JobStatus::Running) {
+            return Err(job.status.name());
+        }
+        job.cancel.cancel();
+        job.status = JobStatus::Cancelled;
+        job.finished_at = Some(now);
+        if let Some(started) = job.started_at {
+            job.duration = Some(now - started);
+        }
+        Ok(())
+    });
+
+    // Pending jobs are dropped, with any hold they had, on the next pass over
+    // the queue
+    GpuManager::lock(&state.gpu_manager).released().notify_one();
+    cancelled
+}
+
+fn is_cancelled(state: &AppState, job: &Job) -> bool {
+    state
+        .jobs
+        .read(job.job_id, |j| matches!(j.status, JobStatus::Cancelled))
+        .unwrap_or(false)
+}
+
+/// Settles a job whose deadline passed before it could start.
+fn expire(state: &AppState, job: &Job) {
+    GpuManager::lock(&state.gpu_manager).clear_hold(job.job_id);
+
+    state.jobs.update(job.job_id, |job_in_map| {
+        if matches!(job_in_map.status, JobStatus::Queued) {
+            job_in_map.status = JobStatus::Expired;
+            job_in_map.finished_at = Some(OffsetDateTime::now_utc());
+        }
+    });
 }
 
-async fn run_task(job: Job, state: AppState) {
-    // Mark as running
-    {
-        let mut inner = state.inner.write().await;
+fn fail_job(state: &AppState, job: &Job, reason: &str) {
+    GpuManager::lock(&state.gpu_manager).clear_hold(job.job_id);
 
-        if let Some(job_in_map) = inner.jobs.get_mut(&job.job_id) {
-            job_in_map.status = JobStatus::Running;
-            job_in_map.started_at = Some(OffsetDateTime::now_utc());
+    state.jobs.update(job.job_id, |job_in_map| {
+        job_in_map.status = JobStatus::Failed(reason.to_string());
+        job_in_map.finished_at = Some(OffsetDateTime::now_utc());
+    });
+}
+
+/// Fails a job after a crash, unless it already finished or was cancelled.
+fn fail_unfinished(state: &AppState, job_id: Uuid, reason: &str) {
+    GpuManager::lock(&state.gpu_manager).clear_hold(job_id);
+
+    state.jobs.update(job_id, |job| {
+        if !matches!(job.status, JobStatus::Queued | JobStatus::Running) {
+            return;
         }
+        let finished = OffsetDateTime::now_utc();
+        job.status = JobStatus::Failed(reason.to_string());
+        job.finished_at = Some(finished);
+        if let Some(started) = job.started_at {
+            job.duration = Some(finished - started);
+        }
+    });
+}
+
+async fn run_task(
+    job: Job,
+    capabilities: Vec<ResolvedCapability>,
+    backend: Arc<dyn ExecutionBackend>,
+    reservation: Option<SlotReservation>,
+    state: AppState,
+) {
+    // Mark as running, unless it was cancelled since it was dispatched
+    let cancelled = state.jobs.update(job.job_id, |job_in_map| {
+        if matches!(job_in_map.status, JobStatus::Cancelled) {
+            return true;
+        }
+        job_in_map.status = JobStatus::Running;
+        if let Some(reservation) = &reservation {
+            job_in_map.placements = reservation.info().placements.clone();
+        }
+        job_in_map.started_at = Some(OffsetDateTime::now_utc());
+        false
+    });
+    if cancelled == Some(true) {
+        return;
     }
 
-    match execute_job(&job).await {
-        Ok(result) => {
-            let mut inner = state.inner.write().await;
-            if let Some(job_in_map) = inner.jobs.get_mut(&job.job_id) {
+    let started = Instant::now();
+    let executed = pipeline::execute(&backend, &job, &capabilities, &state.jobs).await;
+    // A job cancelled while it ran keeps its cancelled status
+    if job.cancel.is_cancelled() {
+        return;
+    }
+    match executed {
+        Ok(mut result) => {
+            state.runtime_estimates.record(&job, started.elapsed());
+            for tensor in result.tensors.values_mut() {
+                tensor.set_encoding(job.output_encoding);
+            }
+            state.jobs.update(job.job_id, |job_in_map| {
+                if !matches!(job_in_map.status, JobStatus::Running) {
+                    return;
+                }
                 job_in_map.status =
                     JobStatus::Finished("Successfully wasted 5 seconds".to_string());
                 let finished = OffsetDateTime::now_utc();
@@ -93,28 +535,23 @@ async fn run_task(job: Job, state: AppState) {
                     job_in_map.duration = Some(finished - started);
                 }
                 job_in_map.result = Some(result);
-            }
+            });
         }
         Err(e) => {
-            let mut inner = state.inner.write().await;
-            if let Some(job_in_map) = inner.jobs.get_mut(&job.job_id) {
-                job_in_map.status = JobStatus::Failed(format!("Job execution failed: {:?}", e));
+            state.jobs.update(job.job_id, |job_in_map| {
+                if !matches!(job_in_map.status, JobStatus::Running) {
+                    return;
+                }
+                job_in_map.status = JobStatus::Failed(format!("Job execution failed: {}", e));
                 let finished = OffsetDateTime::now_utc();
                 job_in_map.finished_at = Some(finished);
                 if let Some(started) = job_in_map.started_at {
                     job_in_map.duration = Some(finished - started);
                 }
-            }
+            });
         }
     }
 
-    {
-        let mut gpu_manager = state.gpu_manager.write().await;
-        let _ = gpu_manager.release_slot(&job.tenant_id);
-    }
-}
-
-async fn execute_job(job: &Job) -> Result<ExecutionResult, SandboxError> {
-    let executor = SandboxExecutor::default()?;
-    executor.execute(job).await
+    // Released explicitly here, and implicitly on panic or shutdown
+    drop(reservation);
 }
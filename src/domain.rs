@@ -1,17 +1,76 @@
+use std::collections::BTreeMap;
 use std::str;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
 use uuid::Uuid;
 
+use crate::gpu_manager::{Placement, ResourceShape};
+use crate::quota::QuotaLimits;
 use crate::sandbox::ExecutionResult;
+use crate::tenant::{Tenant, TenantStatus};
+use crate::tensor::{Tensor, TensorEncoding};
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct SubmitJobRequest {
     pub tenant_id: String,
+    #[serde(default)]
+    pub project_id: Option<String>,
     pub module_id: String,
     pub payload: serde_json::Value,
+    /// Named input tensors, checked against the module's signature if it has one
+    #[serde(default)]
+    pub tensors: BTreeMap<String, Tensor>,
+    /// Encoding of tensors in the result
+    #[serde(default)]
+    pub output_encoding: TensorEncoding,
     pub capabilities: Vec<String>,
+    /// Execution backend, `wasm` unless given
+    #[serde(default)]
+    pub backend: Option<String>,
+    #[serde(default)]
+    pub resources: Option<ResourceRequest>,
+    /// Number of partitions the job needs at the same time.
+    #[serde(default)]
+    pub gang_size: Option<u32>,
+    /// Jobs of the same tenant that have to finish successfully first
+    #[serde(default)]
+    pub depends_on: Vec<Uuid>,
+    #[serde(default)]
+    pub on_parent_failure: ParentFailurePolicy,
+    /// Adds the parents' output tensors to the job's inputs and their
+    /// base64-encoded outputs to `payload.parent_outputs`
+    #[serde(default)]
+    pub pass_outputs: bool,
+    /// Modules run after `module_id` on the same slot reservation, each taking
+    /// the output of the stage before as input
+    #[serde(default)]
+    pub stages: Vec<String>,
+    /// The job is not started before this time
+    #[serde(default)]
+    pub not_before: Option<OffsetDateTime>,
+    /// The job expires instead of starting once this time has passed
+    #[serde(default)]
+    pub deadline: Option<OffsetDateTime>,
+}
+
+/// What happens to a job when one of its parents fails or is cancelled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParentFailurePolicy {
+    #[default]
+    Cancel,
+    Fail,
+}
+
+/// Either a named partition profile from `config.toml` or an explicit shape.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceRequest {
+    Profile(String),
+    Shape(ResourceShape),
 }
 
 #[derive(Serialize)]
@@ -19,6 +78,42 @@ pub struct SubmitJobResponse {
     pub job_id: Uuid,
 }
 
+/// What happens to the rest of a batch when some of its jobs are rejected.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    /// Nothing is queued unless every job is accepted
+    #[default]
+    AllOrNothing,
+    /// Accepted jobs are queued and rejected ones reported
+    BestEffort,
+}
+
+#[derive(Deserialize)]
+pub struct SubmitBatchRequest {
+    #[serde(default)]
+    pub mode: BatchMode,
+    pub jobs: Vec<SubmitJobRequest>,
+}
+
+/// Outcome of one job of a batch, in request order: its id, or the status and
+/// error a single `POST /jobs` would have returned.
+#[derive(Serialize)]
+pub struct BatchItemResult {
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub error: Option<JobErrorResponse>,
+}
+
+#[derive(Serialize)]
+pub struct SubmitBatchResponse {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub jobs: Vec<BatchItemResult>,
+}
+
 #[derive(Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum JobStatus {
@@ -26,6 +121,23 @@ pub enum JobStatus {
     Running,
     Finished(String),
     Failed(String),
+    Cancelled,
+    /// Still queued when its deadline passed, so it never ran
+    Expired,
+}
+
+impl JobStatus {
+    /// The status as used in `GET /jobs/list?status=`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Finished(_) => "finished",
+            JobStatus::Failed(_) => "failed",
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::Expired => "expired",
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -38,26 +150,167 @@ pub struct JobErrorResponse {
 pub struct Job {
     pub job_id: Uuid,
     pub tenant_id: String,
+    pub project_id: Option<String>,
     pub module_id: String,
     pub payload: serde_json::Value,
+    pub tensors: BTreeMap<String, Tensor>,
+    pub output_encoding: TensorEncoding,
     pub capabilities: Vec<String>,
+    pub backend: String,
+    pub profile: Option<String>,
+    pub resources: ResourceShape,
+    pub gang_size: u32,
+    pub quota_scopes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<Uuid>,
+    pub on_parent_failure: ParentFailurePolicy,
+    pub pass_outputs: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workflow_id: Option<Uuid>,
+    /// Pipeline stages, `module_id` first; empty for single-module jobs
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<Stage>,
+    /// What the guest reads from stdin: the output of the previous stage
+    #[serde(skip)]
+    pub input: Vec<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<OffsetDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<OffsetDateTime>,
+    pub placements: Vec<Placement>,
     pub submitted_at: OffsetDateTime,
     pub started_at: Option<OffsetDateTime>,
     pub finished_at: Option<OffsetDateTime>,
     pub duration: Option<Duration>,
     pub status: JobStatus,
     pub result: Option<ExecutionResult>,
+    #[serde(skip)]
+    pub cancel: CancelFlag,
+}
+
+/// Set when a job is cancelled. Every clone of the job shares it, so the
+/// backend running the job sees it and stops early where it can.
+#[derive(Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Job {
+    /// Sets `payload.{key}`, turning a null payload into an object. Scalar and
+    /// array payloads have nowhere to put it.
+    pub fn add_to_payload(&mut self, key: &str, value: serde_json::Value) -> Result<(), String> {
+        match &mut self.payload {
+            serde_json::Value::Object(payload) => {
+                payload.insert(key.to_string(), value);
+            }
+            payload @ serde_json::Value::Null => {
+                *payload = serde_json::json!({ key: value });
+            }
+            _ => return Err(format!("Payload must be an object to add {}", key)),
+        }
+        Ok(())
+    }
+}
+
+/// One module of a pipeline job, with its own status and timings.
+#[derive(Clone, Serialize)]
+pub struct Stage {
+    pub module_id: String,
+    pub status: JobStatus,
+    pub started_at: Option<OffsetDateTime>,
+    pub duration: Option<Duration>,
+}
+
+impl Stage {
+    pub fn new(module_id: String) -> Self {
+        Self {
+            module_id,
+            status: JobStatus::Queued,
+            started_at: None,
+            duration: None,
+        }
+    }
+}
+
+/// Filters for `GET /jobs/list`.
+#[derive(Deserialize)]
+pub struct ListJobsQuery {
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct JobListItem {
     pub job_id: Uuid,
     pub tenant_id: String,
+    pub module_id: String,
+    pub backend: String,
     pub status: JobStatus,
     pub submitted_at: OffsetDateTime,
+    pub started_at: Option<OffsetDateTime>,
+    pub finished_at: Option<OffsetDateTime>,
 }
 
 #[derive(Serialize)]
 pub struct JobListResponse {
     pub jobs: Vec<JobListItem>,
 }
+
+#[derive(Serialize)]
+pub struct JobLogsResponse {
+    pub job_id: Uuid,
+    pub status: JobStatus,
+    pub logs: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ModuleUploadResponse {
+    pub module_id: String,
+    pub size_bytes: usize,
+    pub component: bool,
+}
+
+/// A tenant as shown by the admin API.
+#[derive(Serialize)]
+pub struct TenantSummary {
+    pub tenant_id: String,
+    pub org_id: Option<String>,
+    pub status: TenantStatus,
+    pub allowed_capabilities: Vec<String>,
+    pub allowed_backends: Vec<String>,
+    pub projects: Vec<String>,
+    #[serde(flatten)]
+    pub limits: QuotaLimits,
+}
+
+impl From<&Tenant> for TenantSummary {
+    fn from(tenant: &Tenant) -> Self {
+        TenantSummary {
+            tenant_id: tenant.tenant_id.clone(),
+            org_id: tenant.org_id.clone(),
+            status: tenant.status.clone(),
+            allowed_capabilities: tenant
+                .allowed_capabilities
+                .iter()
+                .map(|g| g.name().to_string())
+                .collect(),
+            allowed_backends: tenant.allowed_backends.clone(),
+            projects: tenant
+                .projects
+                .iter()
+                .map(|p| p.project_id.clone())
+                .collect(),
+            limits: tenant.limits.clone(),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use ureq::Agent;
+use ureq::http::Uri;
+
+use crate::capability::EgressParams;
+
+/// One outbound call made by a guest, allowed or not.
+#[derive(Debug, Clone, Serialize)]
+pub struct EgressRecord {
+    pub url: String,
+    pub requested_at: OffsetDateTime,
+    pub request_bytes: usize,
+    pub status: Option<u16>,
+    pub response_bytes: usize,
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug)]
+pub struct EgressResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EgressError {
+    #[error("Invalid URL {0}")]
+    InvalidUrl(String),
+    #[error("Host {0} is not in the egress allowlist")]
+    HostNotAllowed(String),
+    #[error("Request body of {0} bytes exceeds the limit")]
+    RequestTooLarge(usize),
+    #[error("Response body exceeds the limit of {0} bytes")]
+    ResponseTooLarge(usize),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Request failed: {0}")]
+    Transport(String),
+}
+
+impl EgressError {
+    /// Negative status handed back to the guest in place of an HTTP status.
+    pub fn guest_code(&self) -> i32 {
+        match self {
+            EgressError::InvalidUrl(_) => -1,
+            EgressError::HostNotAllowed(_) => -2,
+            EgressError::RequestTooLarge(_) => -3,
+            EgressError::ResponseTooLarge(_) => -4,
+            EgressError::Timeout => -5,
+            EgressError::Transport(_) => -6,
+        }
+    }
+}
+
+/// Checks `url` against the allowlist. Entries are either `host`, matching
+/// any port, or `host:port`.
+pub fn check_allowed(params: &EgressParams, url: &str) -> Result<(), EgressError> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|_| EgressError::InvalidUrl(url.to_string()))?;
+
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err(EgressError::InvalidUrl(url.to_string()));
+    }
+
+    let Some(host) = uri.host() else {
+        return Err(EgressError::InvalidUrl(url.to_string()));
+    };
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        });
+    let host_port = format!("{}:{}", host, port);
+
+    if params
+        .allowed_hosts
+        .iter()
+        .any(|allowed| *allowed == host || *allowed == host_port)
+    {
+        Ok(())
+    } else {
+        Err(EgressError::HostNotAllowed(host_port))
+    }
+}
+
+/// Sends a POST on behalf of a guest. Blocking; call it from the guest's
+/// execution thread. Redirects are not followed so they can't leave the
+/// allowlist.
+pub fn post(
+    params: &EgressParams,
+    url: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> Result<EgressResponse, EgressError> {
+    check_allowed(params, url)?;
+
+    if body.len() > params.max_body_bytes {
+        return Err(EgressError::RequestTooLarge(body.len()));
+    }
+
+    let agent: Agent = Agent::config_builder()
+        .timeout_global(Some(Duration::from_millis(params.timeout_ms)))
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .proxy(None)
+        .build()
+        .into();
+
+    let mut request = agent.post(url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+
+    let mut response = request.send(body).map_err(map_error)?;
+    let status = response.status().as_u16();
+    let body = response
+        .body_mut()
+        .with_config()
+        .limit(params.max_body_bytes as u64)
+        .read_to_vec()
+        .map_err(|e| match e {
+            ureq::Error::BodyExceedsLimit(_) => {
+                EgressError::ResponseTooLarge(params.max_body_bytes)
+            }
+            other => map_error(other),
+        })?;
+
+    Ok(EgressResponse { status, body })
+}
+
+fn map_error(e: ureq::Error) -> EgressError {
+    match e {
+        ureq::Error::Timeout(_) => EgressError::Timeout,
+        other => EgressError::Transport(other.to_string()),
+    }
+}
+
+/// Parses `Name: value` lines as passed by the guest.
+pub fn parse_headers(raw: &str) -> Vec<(String, String)> {
+    raw.lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .filter(|(name, _)| !name.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    /// Serves one request with `response_body` and hands back what it received.
+    fn stand_in_server(response_body: &'static str) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(len) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = len.trim().parse().unwrap();
+                }
+                head.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            tx.send(format!("{}{}", head, String::from_utf8_lossy(&body)))
+                .unwrap();
+
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 201 Created\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response_body.len(),
+                response_body
+            )
+            .unwrap();
+        });
+
+        (addr.to_string(), rx)
+    }
+
+    fn params(allowed_hosts: Vec<String>) -> EgressParams {
+        EgressParams {
+            allowed_hosts,
+            max_body_bytes: 64,
+            timeout_ms: 2000,
+        }
+    }
+
+    #[test]
+    fn posts_to_allowed_host() {
+        let (addr, received) = stand_in_server("accepted");
+        let headers = parse_headers("X-Job: 42\nContent-Type: text/plain");
+
+        let response = post(
+            &params(vec![addr.clone()]),
+            &format!("http://{}/ingest", addr),
+            &headers,
+            b"hello",
+        )
+        .unwrap();
+
+        assert_eq!(response.status, 201);
+        assert_eq!(response.body, b"accepted");
+
+        let request = received.recv().unwrap();
+        assert!(request.starts_with("POST /ingest"));
+        assert!(request.to_ascii_lowercase().contains("x-job: 42"));
+        assert!(request.ends_with("hello"));
+    }
+
+    #[test]
+    fn rejects_host_outside_allowlist() {
+        let (addr, _received) = stand_in_server("never sent");
+
+        let err = post(
+            &params(vec!["api.example.com".to_string()]),
+            &format!("http://{}/", addr),
+            &[],
+            b"",
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, EgressError::HostNotAllowed(_)));
+    }
+
+    #[test]
+    fn enforces_body_limits() {
+        let (addr, _received) =
+            stand_in_server("this response is far longer than the sixty-four byte limit allows");
+        let params = params(vec![addr.clone()]);
+        let url = format!("http://{}/", addr);
+
+        let err = post(&params, &url, &[], &[0; 65]).unwrap_err();
+        assert!(matches!(err, EgressError::RequestTooLarge(65)));
+
+        let err = post(&params, &url, &[], b"small").unwrap_err();
+        assert!(matches!(err, EgressError::ResponseTooLarge(64)));
+    }
+
+    #[test]
+    fn allowlist_matches_host_or_host_port() {
+        let params = params(vec![
+            "api.example.com".to_string(),
+            "10.0.0.1:8080".to_string(),
+        ]);
+
+        assert!(check_allowed(&params, "https://api.example.com/v1").is_ok());
+        assert!(check_allowed(&params, "http://api.example.com:9000/").is_ok());
+        assert!(check_allowed(&params, "http://10.0.0.1:8080/").is_ok());
+        assert!(check_allowed(&params, "http://10.0.0.1:9090/").is_err());
+        assert!(check_allowed(&params, "ftp://api.example.com/").is_err());
+    }
+}
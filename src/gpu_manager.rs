@@ -1,60 +1,491 @@
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
-use crate::{config::Config, tenant::Tenant};
+use time::OffsetDateTime;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, domain::ResourceRequest, quota::ScopeQuota};
+
+/// A slice of GPU capacity, expressed in MIG-style compute slices and memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceShape {
+    pub compute_slices: u32,
+    pub memory_gb: u32,
+}
+
+impl ResourceShape {
+    pub fn fits_within(&self, capacity: &ResourceShape) -> bool {
+        self.compute_slices <= capacity.compute_slices && self.memory_gb <= capacity.memory_gb
+    }
+
+    /// Saturates, so a sum past `u32::MAX` fits within no real capacity.
+    pub fn plus(&self, other: &ResourceShape) -> ResourceShape {
+        ResourceShape {
+            compute_slices: self.compute_slices.saturating_add(other.compute_slices),
+            memory_gb: self.memory_gb.saturating_add(other.memory_gb),
+        }
+    }
+
+    pub fn minus(&self, other: &ResourceShape) -> ResourceShape {
+        ResourceShape {
+            compute_slices: self.compute_slices.saturating_sub(other.compute_slices),
+            memory_gb: self.memory_gb.saturating_sub(other.memory_gb),
+        }
+    }
+
+    /// `count` copies of the shape, or `None` if that overflows.
+    pub fn times(&self, count: u32) -> Option<ResourceShape> {
+        Some(ResourceShape {
+            compute_slices: self.compute_slices.checked_mul(count)?,
+            memory_gb: self.memory_gb.checked_mul(count)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.compute_slices == 0 && self.memory_gb == 0
+    }
+}
+
+/// Where a job's partition was carved out.
+#[derive(Debug, Clone, Serialize)]
+pub struct Placement {
+    pub device_id: String,
+    pub shape: ResourceShape,
+}
+
+/// How `GpuManager` picks a device when several can host a partition.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlacementStrategy {
+    /// First device in config order with room.
+    #[default]
+    FirstFit,
+    /// Device left with the least free capacity, keeping whole devices free.
+    BestFit,
+    /// Device with the most free capacity, balancing load.
+    Spread,
+}
+
+#[derive(Serialize)]
+pub struct DeviceUsage {
+    pub device_id: String,
+    pub capacity: ResourceShape,
+    pub used: ResourceShape,
+}
+
+/// Free capacity that is scattered over devices can't host large partitions.
+/// `fragmentation` is `1 - largest_free / total_free` over compute slices.
+#[derive(Serialize)]
+pub struct FragmentationReport {
+    pub total_free_slices: u32,
+    pub largest_free_slices: u32,
+    pub idle_devices: usize,
+    pub fragmentation: f64,
+}
+
+#[derive(Serialize)]
+pub struct GpuUsage {
+    pub strategy: PlacementStrategy,
+    pub devices: Vec<DeviceUsage>,
+    pub scopes: HashMap<String, ResourceShape>,
+    pub hold: Option<CapacityHold>,
+    pub reservations: Vec<ReservationInfo>,
+    pub fragmentation: FragmentationReport,
+}
+
+struct VirtualDevice {
+    device_id: String,
+    capacity: ResourceShape,
+    used: ResourceShape,
+}
+
+impl VirtualDevice {
+    fn free(&self) -> ResourceShape {
+        self.capacity.minus(&self.used)
+    }
+}
+
+/// Devices set aside for the oldest job blocked on global capacity. No other
+/// job is placed on them, so they drain until the held job fits.
+#[derive(Clone, Serialize)]
+pub struct CapacityHold {
+    pub job_id: Uuid,
+    pub device_ids: Vec<String>,
+}
+
+/// What a live reservation holds. Listed by `GpuManager::reservations` so
+/// reservations that outlive their job are visible.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReservationInfo {
+    pub job_id: Uuid,
+    pub tenant_id: String,
+    pub scopes: Vec<String>,
+    pub placements: Vec<Placement>,
+    pub reserved_at: OffsetDateTime,
+}
+
+/// Holds GPU capacity for one job and gives it back on drop, so a panicking
+/// or cancelled task can't leak slots.
+pub struct SlotReservation {
+    manager: Arc<Mutex<GpuManager>>,
+    released: Arc<Notify>,
+    info: ReservationInfo,
+}
+
+impl SlotReservation {
+    pub fn info(&self) -> &ReservationInfo {
+        &self.info
+    }
+}
+
+impl Drop for SlotReservation {
+    fn drop(&mut self) {
+        // Never panic here; we may already be unwinding
+        let mut manager = GpuManager::lock(&self.manager);
+        manager.release(self.info.job_id);
+        self.released.notify_one();
+    }
+}
 
 pub struct GpuManager {
-    gpu_slots: usize,
-    per_tenant_limit: usize,
-    tenant_resources: HashMap<String, usize>, // tenant_id -> current_count
+    profiles: HashMap<String, ResourceShape>,
+    default_profile: String,
+    strategy: PlacementStrategy,
+    devices: Vec<VirtualDevice>,
+    scope_resources: HashMap<String, ResourceShape>, // quota scope -> current usage
+    hold: Option<CapacityHold>,
+    reservations: HashMap<Uuid, ReservationInfo>, // job_id -> reservation
+    released: Arc<Notify>,
 }
 
 impl GpuManager {
     pub fn new(config: &Config) -> Self {
         GpuManager {
-            gpu_slots: config.gpu_slots,
-            per_tenant_limit: config.per_tenant_limit,
-            tenant_resources: HashMap::new(),
+            profiles: config.profiles.clone(),
+            default_profile: config.default_profile.clone(),
+            strategy: config.placement_strategy,
+            devices: config
+                .devices
+                .iter()
+                .map(|d| VirtualDevice {
+                    device_id: d.device_id.clone(),
+                    capacity: ResourceShape {
+                        compute_slices: d.compute_slices,
+                        memory_gb: d.memory_gb,
+                    },
+                    used: ResourceShape::default(),
+                })
+                .collect(),
+            scope_resources: HashMap::new(),
+            hold: None,
+            reservations: HashMap::new(),
+            released: Arc::new(Notify::new()),
         }
     }
 
-    pub fn try_reserve_slot(&mut self, tenant: &Tenant) -> Result<(), GpuError> {
-        let total_used: usize = self.tenant_resources.values().sum();
-        if total_used >= self.gpu_slots {
+    /// Locks the manager, also after a panic poisoned the lock. Its state is
+    /// only updated once a reservation is known to succeed, so it is still
+    /// consistent, and refusing it would stall the dispatcher for good.
+    pub fn lock(manager: &Mutex<GpuManager>) -> MutexGuard<'_, GpuManager> {
+        manager.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Turns a job's resource request into a concrete shape, returning the
+    /// profile name if one was used.
+    pub fn resolve(
+        &self,
+        request: Option<&ResourceRequest>,
+    ) -> Result<(Option<String>, ResourceShape), GpuError> {
+        let profile = match request {
+            Some(ResourceRequest::Shape(shape)) => {
+                if shape.compute_slices == 0 {
+                    return Err(GpuError::InvalidShape);
+                }
+                return Ok((None, *shape));
+            }
+            Some(ResourceRequest::Profile(name)) => name,
+            None => &self.default_profile,
+        };
+
+        self.profiles
+            .get(profile)
+            .map(|shape| (Some(profile.clone()), *shape))
+            .ok_or_else(|| GpuError::UnknownProfile(profile.clone()))
+    }
+
+    /// Whether `count` partitions of the shape fit on the devices once they are idle.
+    pub fn can_ever_fit(&self, shape: &ResourceShape, count: u32) -> bool {
+        let mut free: Vec<ResourceShape> = self.devices.iter().map(|d| d.capacity).collect();
+        self.plan(shape, count, &mut free, |_| true).is_some()
+    }
+
+    /// Capacity of all devices together.
+    pub fn total_capacity(&self) -> ResourceShape {
+        self.devices
+            .iter()
+            .fold(ResourceShape::default(), |total, d| total.plus(&d.capacity))
+    }
+
+    /// Signalled whenever a reservation is released.
+    pub fn released(&self) -> Arc<Notify> {
+        self.released.clone()
+    }
+
+    pub fn reservations(&self) -> Vec<ReservationInfo> {
+        let mut reservations: Vec<ReservationInfo> = self.reservations.values().cloned().collect();
+        reservations.sort_by_key(|r| r.reserved_at);
+        reservations
+    }
+
+    /// Reserves `count` partitions of the shape all-or-nothing, one placement
+    /// per partition. Partitions may land on different devices. The total is
+    /// charged against every level of the job's quota chain.
+    pub fn try_reserve(
+        manager: &Arc<Mutex<GpuManager>>,
+        tenant_id: &str,
+        quotas: &[ScopeQuota],
+        job_id: Uuid,
+        shape: ResourceShape,
+        count: u32,
+    ) -> Result<SlotReservation, GpuError> {
+        let mut this = GpuManager::lock(manager);
+        let placements = this.reserve(quotas, job_id, shape, count)?;
+
+        let info = ReservationInfo {
+            job_id,
+            tenant_id: tenant_id.to_string(),
+            scopes: quotas.iter().map(|q| q.scope.clone()).collect(),
+            placements,
+            reserved_at: OffsetDateTime::now_utc(),
+        };
+        this.reservations.insert(job_id, info.clone());
+
+        Ok(SlotReservation {
+            manager: manager.clone(),
+            released: this.released.clone(),
+            info,
+        })
+    }
+
+    fn reserve(
+        &mut self,
+        quotas: &[ScopeQuota],
+        job_id: Uuid,
+        shape: ResourceShape,
+        count: u32,
+    ) -> Result<Vec<Placement>, GpuError> {
+        if self.reservations.contains_key(&job_id) {
+            return Err(GpuError::AlreadyReserved(job_id));
+        }
+
+        let Some(total) = shape.times(count) else {
             return Err(GpuError::NoGlobalCapacity);
+        };
+
+        for quota in quotas {
+            let current = self
+                .scope_resources
+                .get(&quota.scope)
+                .copied()
+                .unwrap_or_default();
+
+            if let Some(limit) = quota.limits.gpu_limit
+                && !current.plus(&total).fits_within(&limit)
+            {
+                return Err(GpuError::QuotaReached(quota.scope.clone()));
+            }
         }
 
-        let current_count = self
-            .tenant_resources
-            .entry(tenant.tenant_id.to_string())
-            .or_insert(0);
+        let held: Vec<String> = match &self.hold {
+            Some(hold) if hold.job_id != job_id => hold.device_ids.clone(),
+            _ => Vec::new(),
+        };
+
+        let mut free: Vec<ResourceShape> = self.devices.iter().map(|d| d.free()).collect();
+        let Some(indices) = self.plan(&shape, count, &mut free, |i| {
+            !held.contains(&self.devices[i].device_id)
+        }) else {
+            return Err(GpuError::NoGlobalCapacity);
+        };
 
-        if *current_count >= tenant.gpu_slot_limit {
-            return Err(GpuError::TenantLimitReached);
+        let placements = indices
+            .into_iter()
+            .map(|index| {
+                let device = &mut self.devices[index];
+                device.used = device.used.plus(&shape);
+                Placement {
+                    device_id: device.device_id.clone(),
+                    shape,
+                }
+            })
+            .collect();
+
+        for quota in quotas {
+            let current = self.scope_resources.entry(quota.scope.clone()).or_default();
+            *current = current.plus(&total);
         }
 
-        *current_count += 1;
+        if self.hold.as_ref().is_some_and(|h| h.job_id == job_id) {
+            self.hold = None;
+        }
 
-        Ok(())
+        Ok(placements)
     }
 
-    pub fn release_slot(&mut self, tenant_id: &str) -> Result<(), GpuError> {
-        let should_remove = {
-            if let Some(count) = self.tenant_resources.get_mut(tenant_id) {
-                if *count == 0 {
-                    return Err(GpuError::TenantHasNoSlots);
+    /// Earmarks devices for a job that is blocked on global capacity. Only one
+    /// hold exists at a time; the oldest blocked job keeps it until it runs.
+    pub fn hold_for(&mut self, job_id: Uuid, shape: &ResourceShape, count: u32) {
+        if self.hold.is_some() {
+            return;
+        }
+
+        // Plan against idle devices, preferring those closest to draining.
+        let mut order: Vec<usize> = (0..self.devices.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(self.devices[i].free().compute_slices));
+
+        let mut device_ids = Vec::new();
+        let mut capacity: Vec<ResourceShape> = self.devices.iter().map(|d| d.capacity).collect();
+        let mut remaining = count;
+        for index in order {
+            while remaining > 0 && shape.fits_within(&capacity[index]) {
+                capacity[index] = capacity[index].minus(shape);
+                remaining -= 1;
+                if !device_ids.contains(&self.devices[index].device_id) {
+                    device_ids.push(self.devices[index].device_id.clone());
                 }
-                *count -= 1;
-                *count == 0
-            } else {
-                return Err(GpuError::TenantHasNoSlots);
             }
+        }
+
+        if remaining == 0 {
+            self.hold = Some(CapacityHold { job_id, device_ids });
+        }
+    }
+
+    /// Drops the hold if `job_id` owns it, e.g. because the job failed.
+    pub fn clear_hold(&mut self, job_id: Uuid) {
+        if self.hold.as_ref().is_some_and(|h| h.job_id == job_id) {
+            self.hold = None;
+        }
+    }
+
+    /// Picks a device for each of `count` partitions against `free`, which is
+    /// updated as partitions are placed.
+    fn plan(
+        &self,
+        shape: &ResourceShape,
+        count: u32,
+        free: &mut [ResourceShape],
+        allowed: impl Fn(usize) -> bool,
+    ) -> Option<Vec<usize>> {
+        // Every partition takes at least one slice
+        if count > self.total_capacity().compute_slices {
+            return None;
+        }
+
+        let mut indices = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let index = self.pick_device(shape, free, &allowed)?;
+            free[index] = free[index].minus(shape);
+            indices.push(index);
+        }
+        Some(indices)
+    }
+
+    fn pick_device(
+        &self,
+        shape: &ResourceShape,
+        free: &[ResourceShape],
+        allowed: impl Fn(usize) -> bool,
+    ) -> Option<usize> {
+        let mut candidates = free
+            .iter()
+            .enumerate()
+            .filter(|(i, f)| allowed(*i) && shape.fits_within(f));
+
+        // min_by_key/max_by_key pick the first/last of equal keys, so ties
+        // keep config order for best-fit and the later device for spread.
+        match self.strategy {
+            PlacementStrategy::FirstFit => candidates.next(),
+            PlacementStrategy::BestFit => {
+                candidates.min_by_key(|(_, f)| (f.compute_slices, f.memory_gb))
+            }
+            PlacementStrategy::Spread => {
+                candidates.max_by_key(|(_, f)| (f.compute_slices, f.memory_gb))
+            }
+        }
+        .map(|(index, _)| index)
+    }
+
+    pub fn usage(&self) -> GpuUsage {
+        GpuUsage {
+            strategy: self.strategy,
+            devices: self
+                .devices
+                .iter()
+                .map(|d| DeviceUsage {
+                    device_id: d.device_id.clone(),
+                    capacity: d.capacity,
+                    used: d.used,
+                })
+                .collect(),
+            scopes: self.scope_resources.clone(),
+            hold: self.hold.clone(),
+            reservations: self.reservations(),
+            fragmentation: self.fragmentation(),
+        }
+    }
+
+    pub fn fragmentation(&self) -> FragmentationReport {
+        let free: Vec<u32> = self
+            .devices
+            .iter()
+            .map(|d| d.free().compute_slices)
+            .collect();
+        let total_free_slices: u32 = free.iter().sum();
+        let largest_free_slices = free.iter().copied().max().unwrap_or(0);
+
+        let fragmentation = if total_free_slices == 0 {
+            0.0
+        } else {
+            1.0 - f64::from(largest_free_slices) / f64::from(total_free_slices)
         };
 
-        if should_remove {
-            self.tenant_resources.remove(tenant_id);
+        FragmentationReport {
+            total_free_slices,
+            largest_free_slices,
+            idle_devices: self.devices.iter().filter(|d| d.used.is_empty()).count(),
+            fragmentation,
+        }
+    }
+
+    fn release(&mut self, job_id: Uuid) {
+        let Some(info) = self.reservations.remove(&job_id) else {
+            return;
+        };
+
+        for placement in &info.placements {
+            if let Some(device) = self
+                .devices
+                .iter_mut()
+                .find(|d| d.device_id == placement.device_id)
+            {
+                device.used = device.used.minus(&placement.shape);
+            }
         }
 
-        Ok(())
+        for scope in &info.scopes {
+            if let Some(current) = self.scope_resources.get_mut(scope) {
+                for placement in &info.placements {
+                    *current = current.minus(&placement.shape);
+                }
+                if current.is_empty() {
+                    self.scope_resources.remove(scope);
+                }
+            }
+        }
     }
 }
 
@@ -62,8 +493,265 @@ impl GpuManager {
 pub enum GpuError {
     #[error("No GPU slots available")]
     NoGlobalCapacity,
-    #[error("Tenant reached capacity")]
-    TenantLimitReached,
-    #[error("Tenant has no active slots to free")]
-    TenantHasNoSlots,
+    #[error("Quota {0} reached capacity")]
+    QuotaReached(String),
+    #[error("Job {0} already holds a reservation")]
+    AlreadyReserved(Uuid),
+    #[error("Unknown partition profile {0}")]
+    UnknownProfile(String),
+    #[error("Resource shape must request at least one compute slice")]
+    InvalidShape,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One device per entry of `slices`, 10 GB of memory per slice.
+    fn manager(strategy: PlacementStrategy, slices: &[u32]) -> Arc<Mutex<GpuManager>> {
+        Arc::new(Mutex::new(GpuManager {
+            profiles: HashMap::new(),
+            default_profile: String::new(),
+            strategy,
+            devices: slices
+                .iter()
+                .enumerate()
+                .map(|(i, &slices)| VirtualDevice {
+                    device_id: format!("gpu{}", i),
+                    capacity: shape(slices),
+                    used: ResourceShape::default(),
+                })
+                .collect(),
+            scope_resources: HashMap::new(),
+            hold: None,
+            reservations: HashMap::new(),
+            released: Arc::new(Notify::new()),
+        }))
+    }
+
+    fn shape(slices: u32) -> ResourceShape {
+        ResourceShape {
+            compute_slices: slices,
+            memory_gb: slices * 10,
+        }
+    }
+
+    fn reserve(
+        manager: &Arc<Mutex<GpuManager>>,
+        job_id: Uuid,
+        slices: u32,
+        count: u32,
+    ) -> Result<SlotReservation, GpuError> {
+        let quotas = [ScopeQuota {
+            scope: "tenant:t".to_string(),
+            limits: Default::default(),
+        }];
+        GpuManager::try_reserve(manager, "t", &quotas, job_id, shape(slices), count)
+    }
+
+    fn used(manager: &Arc<Mutex<GpuManager>>) -> Vec<u32> {
+        let manager = GpuManager::lock(manager);
+        manager
+            .devices
+            .iter()
+            .map(|d| d.used.compute_slices)
+            .collect()
+    }
+
+    #[test]
+    fn sums_of_shapes_saturate() {
+        let huge = ResourceShape {
+            compute_slices: u32::MAX - 1,
+            memory_gb: 1,
+        };
+        let sum = huge.plus(&shape(4));
+        assert_eq!(sum.compute_slices, u32::MAX);
+        assert_eq!(sum.memory_gb, 41);
+        assert!(!sum.fits_within(&shape(7)));
+    }
+
+    #[test]
+    fn reservation_is_released_on_drop() {
+        let manager = manager(PlacementStrategy::FirstFit, &[2]);
+        let job_id = Uuid::new_v4();
+        let reservation = reserve(&manager, job_id, 2, 1).unwrap();
+        assert_eq!(used(&manager), [2]);
+        assert!(
+            GpuManager::lock(&manager)
+                .reservations
+                .contains_key(&job_id)
+        );
+
+        drop(reservation);
+        assert_eq!(used(&manager), [0]);
+        assert!(GpuManager::lock(&manager).reservations.is_empty());
+        assert_eq!(
+            GpuManager::lock(&manager).scope_resources.get("tenant:t"),
+            None
+        );
+    }
+
+    #[test]
+    fn reservation_is_released_on_early_return() {
+        fn run(manager: &Arc<Mutex<GpuManager>>) -> Result<(), GpuError> {
+            let _reservation = reserve(manager, Uuid::new_v4(), 1, 1)?;
+            // Fails while the first reservation is held
+            reserve(manager, Uuid::new_v4(), 1, 1)?;
+            unreachable!("the device only has one slice");
+        }
+
+        let manager = manager(PlacementStrategy::FirstFit, &[1]);
+        assert!(run(&manager).is_err());
+        assert_eq!(used(&manager), [0]);
+    }
+
+    #[test]
+    fn reservation_is_released_on_panic() {
+        let manager = manager(PlacementStrategy::FirstFit, &[1]);
+        let result = std::panic::catch_unwind(|| {
+            let _reservation = reserve(&manager, Uuid::new_v4(), 1, 1).unwrap();
+            panic!("job panicked");
+        });
+        assert!(result.is_err());
+        assert_eq!(used(&manager), [0]);
+    }
+
+    #[test]
+    fn poisoned_lock_still_reserves_and_releases() {
+        let manager = manager(PlacementStrategy::FirstFit, &[1]);
+        let reservation = reserve(&manager, Uuid::new_v4(), 1, 1).unwrap();
+        let poisoner = manager.clone();
+        let result = std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(manager.is_poisoned());
+
+        drop(reservation);
+        assert_eq!(used(&manager), [0]);
+        assert!(reserve(&manager, Uuid::new_v4(), 1, 1).is_ok());
+    }
+
+    #[test]
+    fn strategies_pick_different_devices_on_the_same_layout() {
+        // Free slices [2, 1, 4] out of 4 each
+        let cases = [
+            (PlacementStrategy::FirstFit, [3, 3, 0]),
+            (PlacementStrategy::BestFit, [2, 4, 0]),
+            (PlacementStrategy::Spread, [2, 3, 1]),
+        ];
+        for (strategy, expected) in cases {
+            let manager = manager(strategy, &[4, 4, 4]);
+            for (device, used) in GpuManager::lock(&manager).devices.iter_mut().zip([2, 3, 0]) {
+                device.used = shape(used);
+            }
+
+            let _job = reserve(&manager, Uuid::new_v4(), 1, 1).unwrap();
+            assert_eq!(used(&manager), expected, "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn fragmentation_depends_on_the_strategy() {
+        // (used slices, largest free, idle devices) after jobs of 2, 3 and 1
+        let cases = [
+            (PlacementStrategy::FirstFit, [3, 3, 0], 4, 1),
+            (PlacementStrategy::BestFit, [2, 4, 0], 4, 1),
+            (PlacementStrategy::Spread, [1, 3, 2], 3, 0),
+        ];
+        for (strategy, expected, largest, idle) in cases {
+            let manager = manager(strategy, &[4, 4, 4]);
+            let _jobs: Vec<_> = [2, 3, 1]
+                .into_iter()
+                .map(|slices| reserve(&manager, Uuid::new_v4(), slices, 1).unwrap())
+                .collect();
+            assert_eq!(used(&manager), expected, "{:?}", strategy);
+
+            let report = GpuManager::lock(&manager).fragmentation();
+            assert_eq!(report.total_free_slices, 6);
+            assert_eq!(report.largest_free_slices, largest, "{:?}", strategy);
+            assert_eq!(report.idle_devices, idle, "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn gang_is_reserved_all_or_nothing() {
+        let manager = manager(PlacementStrategy::FirstFit, &[2, 2]);
+        let _small = reserve(&manager, Uuid::new_v4(), 1, 1).unwrap();
+
+        // Two of the three partitions would fit; none are taken
+        let err = reserve(&manager, Uuid::new_v4(), 1, 4);
+        assert!(matches!(err, Err(GpuError::NoGlobalCapacity)));
+        assert_eq!(used(&manager), [1, 0]);
+        assert_eq!(
+            GpuManager::lock(&manager).scope_resources["tenant:t"],
+            shape(1)
+        );
+
+        let gang = reserve(&manager, Uuid::new_v4(), 1, 3).unwrap();
+        assert_eq!(gang.info().placements.len(), 3);
+        assert_eq!(used(&manager), [2, 2]);
+    }
+
+    #[test]
+    fn oversized_gangs_are_refused_without_overflow() {
+        let manager = manager(PlacementStrategy::FirstFit, &[4]);
+        assert!(shape(2).times(u32::MAX).is_none());
+        assert!(!GpuManager::lock(&manager).can_ever_fit(&shape(1), u32::MAX));
+
+        let err = reserve(&manager, Uuid::new_v4(), 2, u32::MAX);
+        assert!(matches!(err, Err(GpuError::NoGlobalCapacity)));
+        assert_eq!(used(&manager), [0]);
+    }
+
+    #[test]
+    fn hold_blocks_smaller_jobs_until_the_held_job_runs() {
+        let manager = manager(PlacementStrategy::Spread, &[2, 2]);
+        let first = reserve(&manager, Uuid::new_v4(), 1, 1).unwrap();
+        let second = reserve(&manager, Uuid::new_v4(), 1, 1).unwrap();
+        assert_eq!(used(&manager), [1, 1]);
+
+        // Two free slices, but not two whole devices
+        let gang_id = Uuid::new_v4();
+        assert!(reserve(&manager, gang_id, 2, 2).is_err());
+        GpuManager::lock(&manager).hold_for(gang_id, &shape(2), 2);
+        let hold = GpuManager::lock(&manager).hold.clone().unwrap();
+        assert_eq!(hold.job_id, gang_id);
+        assert_eq!(hold.device_ids.len(), 2);
+
+        // The free slices are set aside for the gang
+        assert!(matches!(
+            reserve(&manager, Uuid::new_v4(), 1, 1),
+            Err(GpuError::NoGlobalCapacity)
+        ));
+
+        // Releasing the small jobs lets the gang in, which drops the hold
+        drop(first);
+        assert!(reserve(&manager, gang_id, 2, 2).is_err());
+        drop(second);
+        let gang = reserve(&manager, gang_id, 2, 2).unwrap();
+        assert!(GpuManager::lock(&manager).hold.is_none());
+
+        drop(gang);
+        assert!(reserve(&manager, Uuid::new_v4(), 1, 1).is_ok());
+    }
+
+    #[test]
+    fn only_the_owner_clears_a_hold() {
+        let manager = manager(PlacementStrategy::FirstFit, &[2]);
+        let _busy = reserve(&manager, Uuid::new_v4(), 1, 1).unwrap();
+        let (held, other) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let mut this = GpuManager::lock(&manager);
+        this.hold_for(held, &shape(2), 1);
+        // Only one hold at a time; the first blocked job keeps it
+        this.hold_for(other, &shape(1), 1);
+        this.clear_hold(other);
+        assert_eq!(this.hold.as_ref().unwrap().job_id, held);
+
+        this.clear_hold(held);
+        assert!(this.hold.is_none());
+    }
 }
@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gpu_manager::GpuManager;
+use crate::state::AppState;
+
+/// How often an idle dispatcher still reports in.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Dispatcher crashes within `CRASH_WINDOW` at which `/readyz` fails.
+const MAX_CRASHES: usize = 3;
+const CRASH_WINDOW: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Queued jobs at which `/readyz` reports not ready, `queue_length` if unset
+    pub max_queue_depth: Option<usize>,
+    /// Dispatcher heartbeat age after which it counts as stalled, 10s if unset
+    pub dispatcher_stall_secs: Option<u64>,
+}
+
+/// What the dispatcher reports about itself, read by the health checks.
+pub struct DispatcherHealth {
+    alive: AtomicBool,
+    last_beat: Mutex<Instant>,
+    stall_after: Duration,
+    // Recent panics of the dispatch loop, oldest first
+    crashes: Mutex<VecDeque<Instant>>,
+}
+
+impl DispatcherHealth {
+    pub fn new(stall_after: Duration) -> Self {
+        Self {
+            alive: AtomicBool::new(false),
+            last_beat: Mutex::new(Instant::now()),
+            stall_after,
+            crashes: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Marks the dispatcher alive until the returned guard is dropped, which
+    /// also happens if it panics.
+    pub fn start(&self) -> DispatcherGuard<'_> {
+        self.beat();
+        self.alive.store(true, Ordering::SeqCst);
+        DispatcherGuard(self)
+    }
+
+    pub fn beat(&self) {
+        *self.last_beat.lock().unwrap() = Instant::now();
+    }
+
+    pub fn record_crash(&self) {
+        self.crashes.lock().unwrap().push_back(Instant::now());
+    }
+
+    fn crash_check(&self) -> Check {
+        let mut crashes = self.crashes.lock().unwrap();
+        while crashes
+            .front()
+            .is_some_and(|crash| crash.elapsed() > CRASH_WINDOW)
+        {
+            crashes.pop_front();
+        }
+
+        let detail = format!(
+            "{} crash(es) in the last {}s",
+            crashes.len(),
+            CRASH_WINDOW.as_secs()
+        );
+        if crashes.len() < MAX_CRASHES {
+            Check::passed(detail)
+        } else {
+            Check::failed(detail)
+        }
+    }
+
+    fn check(&self) -> Check {
+        if !self.alive.load(Ordering::SeqCst) {
+            return Check::failed("dispatcher is not running".to_string());
+        }
+        let age = self.last_beat.lock().unwrap().elapsed();
+        if age > self.stall_after {
+            return Check::failed(format!("no dispatcher heartbeat for {}s", age.as_secs()));
+        }
+        Check::passed(format!("last heartbeat {}ms ago", age.as_millis()))
+    }
+}
+
+pub struct DispatcherGuard<'a>(&'a DispatcherHealth);
+
+impl Drop for DispatcherGuard<'_> {
+    fn drop(&mut self) {
+        self.0.alive.store(false, Ordering::SeqCst);
+    }
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn passed(detail: String) -> Self {
+        Self { ok: true, detail }
+    }
+
+    fn failed(detail: String) -> Self {
+        Self { ok: false, detail }
+    }
+}
+
+#[derive(Serialize)]
+pub struct HealthReport {
+    pub ok: bool,
+    pub checks: BTreeMap<String, Check>,
+}
+
+impl HealthReport {
+    fn new(checks: BTreeMap<String, Check>) -> Self {
+        Self {
+            ok: checks.values().all(|check| check.ok),
+            checks,
+        }
+    }
+}
+
+/// Whether the process should be restarted: only a dead or stalled
+/// dispatcher counts.
+pub fn liveness(state: &AppState) -> HealthReport {
+    let mut checks = BTreeMap::new();
+    checks.insert("dispatcher".to_string(), state.dispatcher_health.check());
+    HealthReport::new(checks)
+}
+
+/// Whether the controller should get new jobs.
+pub async fn readiness(state: &AppState) -> HealthReport {
+    let mut checks = BTreeMap::new();
+
+    let shutdown = if state.is_shutting_down() {
+        Check::failed("shutting down".to_string())
+    } else {
+        Check::passed("accepting jobs".to_string())
+    };
+    checks.insert("shutdown".to_string(), shutdown);
+    checks.insert("dispatcher".to_string(), state.dispatcher_health.check());
+    checks.insert(
+        "dispatcher_crashes".to_string(),
+        state.dispatcher_health.crash_check(),
+    );
+
+    let queued = state.jobs.queued();
+    let detail = format!("{} queued, limit {}", queued, state.max_queue_depth);
+    let queue = if queued < state.max_queue_depth {
+        Check::passed(detail)
+    } else {
+        Check::failed(detail)
+    };
+    checks.insert("queue".to_string(), queue);
+
+    let free_slices = GpuManager::lock(&state.gpu_manager)
+        .usage()
+        .fragmentation
+        .total_free_slices;
+    let slots = if free_slices > 0 {
+        Check::passed(format!("{} free compute slices", free_slices))
+    } else {
+        Check::failed("no free compute slices".to_string())
+    };
+    checks.insert("slots".to_string(), slots);
+
+    // Backend probes and storage writes block, so they run off the runtime
+    let backends = state.backends.clone();
+    let storage_dirs = vec![
+        state.modules_dir.to_path_buf(),
+        state.models.root().to_path_buf(),
+    ];
+    let blocking = tokio::task::spawn_blocking(move || {
+        let mut checks = BTreeMap::new();
+        for (name, backend) in backends.iter() {
+            let check = match backend.health_check() {
+                Ok(()) => Check::passed("ok".to_string()),
+                Err(e) => Check::failed(e.to_string()),
+            };
+            checks.insert(format!("backend.{}", name), check);
+        }
+        checks.insert("storage".to_string(), check_storage(&storage_dirs));
+        checks
+    })
+    .await;
+    match blocking {
+        Ok(blocking) => checks.extend(blocking),
+        Err(e) => {
+            checks.insert("backends".to_string(), Check::failed(e.to_string()));
+        }
+    }
+
+    HealthReport::new(checks)
+}
+
+/// Creates and removes a temporary file in each directory uploads go to.
+fn check_storage(dirs: &[PathBuf]) -> Check {
+    for dir in dirs {
+        let written = std::fs::create_dir_all(dir).and_then(|_| tempfile::tempfile_in(dir));
+        if let Err(e) = written {
+            return Check::failed(format!("{} is not writable: {}", dir.display(), e));
+        }
+    }
+
+    let dirs: Vec<String> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+    Check::passed(format!("{} writable", dirs.join(", ")))
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
+
+use uuid::Uuid;
+
+use crate::domain::{Job, JobStatus};
+
+/// Number of job table shards. Job ids are random, so their low bits spread
+/// jobs evenly.
+const SHARDS: usize = 32;
+
+/// The job table, split into independently locked shards so submissions,
+/// status queries and job tasks only contend when they touch the same shard.
+///
+/// Queued and running jobs are counted as they change status, so limits and
+/// health checks don't have to scan the table. The queued count is what
+/// `queue_length` bounds. Every lock is held only for
+/// the duration of a single call, and no call holds two of them at once.
+/// A panic while one is held doesn't take the store down with it: poisoned
+/// locks are used as they are.
+pub struct JobStore {
+    shards: Box<[RwLock<HashMap<Uuid, Job>>]>,
+    // quota scope -> queued jobs charged to it
+    queued_by_scope: Mutex<HashMap<String, usize>>,
+    queued: AtomicUsize,
+    running: AtomicUsize,
+    max_queued: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobStoreError {
+    #[error("Job queue full: max {0} queued jobs")]
+    QueueFull(usize),
+    #[error("Too many queued jobs for {scope}: max {limit}")]
+    TooManyQueued { scope: String, limit: usize },
+}
+
+impl JobStore {
+    /// A store that takes at most `max_queued` queued jobs in total.
+    pub fn new(max_queued: usize) -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+            queued_by_scope: Mutex::default(),
+            queued: AtomicUsize::new(0),
+            running: AtomicUsize::new(0),
+            max_queued,
+        }
+    }
+
+    fn shard(&self, job_id: Uuid) -> &RwLock<HashMap<Uuid, Job>> {
+        &self.shards[job_id.as_u128() as usize % SHARDS]
+    }
+
+    /// Adds a queued job, unless the store is full or one of its scopes
+    /// already has as many queued jobs as its `max_queued` limit (given as
+    /// `(scope, limit)`).
+    pub fn insert(&self, job: Job, max_queued: &[(&str, usize)]) -> Result<(), JobStoreError> {
+        {
+            // Inserts are serialized here, so checking and counting can't race
+            let mut queued_by_scope = self
+                .queued_by_scope
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if matches!(job.status, JobStatus::Queued) && self.queued() >= self.max_queued {
+                return Err(JobStoreError::QueueFull(self.max_queued));
+            }
+            for &(scope, limit) in max_queued {
+                if queued_by_scope.get(scope).copied().unwrap_or(0) >= limit {
+                    return Err(JobStoreError::TooManyQueued {
+                        scope: scope.to_string(),
+                        limit,
+                    });
+                }
+            }
+            if matches!(job.status, JobStatus::Queued) {
+                for scope in &job.quota_scopes {
+                    *queued_by_scope.entry(scope.clone()).or_insert(0) += 1;
+                }
+            }
+            self.count(job.status.name(), 1);
+        }
+
+        let previous = self
+            .shard(job.job_id)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(job.job_id, job);
+        if let Some(previous) = previous {
+            self.uncount(&previous);
+        }
+        Ok(())
+    }
+
+    /// Removes a job, e.g. one that couldn't be handed to the dispatcher.
+    pub fn remove(&self, job_id: Uuid) -> Option<Job> {
+        let job = self
+            .shard(job_id)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&job_id)?;
+        self.uncount(&job);
+        Some(job)
+    }
+
+    pub fn get(&self, job_id: Uuid) -> Option<Job> {
+        self.read(job_id, Job::clone)
+    }
+
+    /// Looks at a job without cloning it.
+    pub fn read<R>(&self, job_id: Uuid, f: impl FnOnce(&Job) -> R) -> Option<R> {
+        self.shard(job_id)
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&job_id)
+            .map(f)
+    }
+
+    /// Changes a job in place and keeps the queued and running counts in step
+    /// with its status. Returns `None` for unknown jobs.
+    pub fn update<R>(&self, job_id: Uuid, f: impl FnOnce(&mut Job) -> R) -> Option<R> {
+        let mut shard = self
+            .shard(job_id)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let job = shard.get_mut(&job_id)?;
+
+        let before = job.status.name();
+        let result = f(job);
+        let after = job.status.name();
+        let scopes = (before != after).then(|| job.quota_scopes.clone());
+        drop(shard);
+
+        if let Some(scopes) = scopes {
+            self.count(before, -1);
+            self.count(after, 1);
+            if before == "queued" {
+                self.count_scopes(&scopes, -1);
+            } else if after == "queued" {
+                self.count_scopes(&scopes, 1);
+            }
+        }
+        Some(result)
+    }
+
+    /// Applies `f` to every job and collects what it returns. Shards are
+    /// visited one at a time, so this isn't a snapshot of the whole table.
+    pub fn collect<T>(&self, mut f: impl FnMut(&Job) -> Option<T>) -> Vec<T> {
+        let mut collected = Vec::new();
+        for shard in self.shards.iter() {
+            collected.extend(
+                shard
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .values()
+                    .filter_map(&mut f),
+            );
+        }
+        collected
+    }
+
+    /// Number of queued jobs.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Number of running jobs.
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    fn uncount(&self, job: &Job) {
+        self.count(job.status.name(), -1);
+        if matches!(job.status, JobStatus::Queued) {
+            self.count_scopes(&job.quota_scopes, -1);
+        }
+    }
+
+    /// Adjusts the count of jobs with status `name`, if it's one we count.
+    fn count(&self, name: &str, delta: isize) {
+        let counter = match name {
+            "queued" => &self.queued,
+            "running" => &self.running,
+            _ => return,
+        };
+        if delta > 0 {
+            counter.fetch_add(1, Ordering::Relaxed);
+        } else {
+            counter.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn count_scopes(&self, scopes: &[String], delta: isize) {
+        let mut queued_by_scope = self
+            .queued_by_scope
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for scope in scopes {
+            let count = queued_by_scope.entry(scope.clone()).or_insert(0);
+            *count = count.saturating_add_signed(delta);
+            if *count == 0 {
+                queued_by_scope.remove(scope);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::gpu_manager::ResourceShape;
+    use crate::tensor::TensorEncoding;
+
+    fn job(scopes: &[&str]) -> Job {
+        Job {
+            job_id: Uuid::new_v4(),
+            tenant_id: "tenant1".to_string(),
+            project_id: None,
+            module_id: "simple-compute".to_string(),
+            payload: serde_json::Value::Null,
+            tensors: Default::default(),
+            output_encoding: TensorEncoding::default(),
+            capabilities: Vec::new(),
+            backend: "wasm".to_string(),
+            profile: None,
+            resources: ResourceShape::default(),
+            gang_size: 1,
+            quota_scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            depends_on: Vec::new(),
+            on_parent_failure: Default::default(),
+            pass_outputs: false,
+            workflow_id: None,
+            stages: Vec::new(),
+            input: Vec::new(),
+            not_before: None,
+            deadline: None,
+            placements: Vec::new(),
+            submitted_at: OffsetDateTime::now_utc(),
+            started_at: None,
+            finished_at: None,
+            duration: None,
+            status: JobStatus::Queued,
+            result: None,
+            cancel: Default::default(),
+        }
+    }
+
+    #[test]
+    fn counts_follow_status_changes() {
+        let store = JobStore::new(usize::MAX);
+        let first = job(&["tenant:tenant1"]);
+        let second = job(&["tenant:tenant1"]);
+        let (first_id, second_id) = (first.job_id, second.job_id);
+        store.insert(first, &[]).unwrap();
+        store.insert(second, &[]).unwrap();
+        assert_eq!((store.queued(), store.running()), (2, 0));
+
+        store.update(first_id, |job| job.status = JobStatus::Running);
+        assert_eq!((store.queued(), store.running()), (1, 1));
+
+        store.update(first_id, |job| {
+            job.status = JobStatus::Finished(String::new())
+        });
+        store.update(second_id, |job| job.status = JobStatus::Cancelled);
+        assert_eq!((store.queued(), store.running()), (0, 0));
+        assert!(store.queued_by_scope.lock().unwrap().is_empty());
+
+        assert!(store.update(Uuid::new_v4(), |_| ()).is_none());
+    }
+
+    #[test]
+    fn max_queued_is_enforced_per_scope() {
+        let store = JobStore::new(usize::MAX);
+        let limits = [("org:acme", 2), ("tenant:tenant1", 1)];
+        let first = job(&["org:acme", "tenant:tenant1"]);
+        let first_id = first.job_id;
+        store.insert(first, &limits).unwrap();
+
+        let err = store
+            .insert(job(&["org:acme", "tenant:tenant1"]), &limits)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            JobStoreError::TooManyQueued { ref scope, limit: 1 } if scope == "tenant:tenant1"
+        ));
+
+        // Leaving the queue frees the slot, removing a queued job does too
+        store.update(first_id, |job| job.status = JobStatus::Running);
+        let second = job(&["org:acme", "tenant:tenant1"]);
+        let second_id = second.job_id;
+        store.insert(second, &limits).unwrap();
+        store.remove(second_id);
+        store
+            .insert(job(&["org:acme", "tenant:tenant1"]), &limits)
+            .unwrap();
+        assert_eq!(store.queued(), 1);
+    }
+
+    #[test]
+    fn panic_in_update_does_not_break_the_store() {
+        let store = JobStore::new(usize::MAX);
+        let first = job(&["tenant:tenant1"]);
+        let first_id = first.job_id;
+        store.insert(first, &[]).unwrap();
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            store.update(first_id, |_| panic!("update panicked"));
+        }));
+        assert!(panicked.is_err());
+        assert!(store.shard(first_id).is_poisoned());
+
+        // The poisoned shard is still readable and writable
+        assert!(store.get(first_id).is_some());
+        store.update(first_id, |job| job.status = JobStatus::Running);
+        assert_eq!((store.queued(), store.running()), (0, 1));
+        assert_eq!(store.collect(|job| Some(job.job_id)), [first_id]);
+        assert!(store.remove(first_id).is_some());
+    }
+
+    #[test]
+    fn max_queued_in_total_is_enforced() {
+        let store = JobStore::new(2);
+        let first = job(&["tenant:tenant1"]);
+        let first_id = first.job_id;
+        store.insert(first, &[]).unwrap();
+        store.insert(job(&["tenant:tenant2"]), &[]).unwrap();
+
+        let err = store.insert(job(&["tenant:tenant3"]), &[]).unwrap_err();
+        assert!(matches!(err, JobStoreError::QueueFull(2)));
+
+        // Running jobs no longer take queue space
+        store.update(first_id, |job| job.status = JobStatus::Running);
+        store.insert(job(&["tenant:tenant3"]), &[]).unwrap();
+        assert_eq!((store.queued(), store.running()), (2, 1));
+    }
+}
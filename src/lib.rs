@@ -0,0 +1,164 @@
+//! Multi-tenant job controller that runs sandboxed guests on virtual GPU
+//! partitions. `main` serves `Controller::router`; tests drive it in-process.
+
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    routing::{get, post},
+};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+pub mod api;
+pub mod backend;
+pub mod capability;
+pub mod component;
+pub mod config;
+pub mod dashboard;
+pub mod deadline;
+pub mod dispatcher;
+pub mod domain;
+pub mod egress;
+pub mod gpu_manager;
+pub mod health;
+pub mod job_store;
+pub mod mlp;
+pub mod model_registry;
+pub mod onnx;
+pub mod pipeline;
+pub mod quota;
+pub mod rate_limit;
+pub mod sandbox;
+pub mod schedule;
+pub mod shutdown;
+pub mod state;
+pub mod stats;
+pub mod tenant;
+pub mod tensor;
+pub mod vgpu;
+pub mod wasi;
+pub mod workflow;
+
+use api::{
+    activate_tenant, cancel_job, create_schedule, delete_schedule, get_job, get_schedule,
+    get_tenant, get_workflow, gpu_usage, job_logs, list_backends, list_capabilities, list_jobs,
+    list_model_versions, list_models, list_reservations, list_schedules, list_tenants, livez,
+    pause_schedule, queue_stats, readyz, resume_schedule, submit_batch, submit_job,
+    submit_workflow, suspend_tenant, upload_model, upload_module,
+};
+use backend::BackendRegistry;
+use capability::CapabilityRegistry;
+use config::Config;
+use domain::Job;
+use model_registry::ModelRegistry;
+use state::AppState;
+use tenant::{Organization, Tenant};
+
+use std::collections::HashMap;
+
+/// Largest model accepted by `POST /models/{name}`.
+const MAX_MODEL_BYTES: usize = 256 * 1024 * 1024;
+
+/// Largest module accepted by `POST /modules/{module_id}`.
+const MAX_MODULE_BYTES: usize = 64 * 1024 * 1024;
+
+/// A started controller: shared state, the running dispatcher and sampler,
+/// and the router to serve.
+pub struct Controller {
+    pub state: AppState,
+    pub router: Router,
+    pub dispatcher: JoinHandle<()>,
+}
+
+impl Controller {
+    /// Validates the tenants against `config` and starts the dispatcher.
+    /// Must be called inside a Tokio runtime.
+    pub fn start(
+        config: &Config,
+        tenants: HashMap<String, Tenant>,
+        organizations: HashMap<String, Organization>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let capabilities = CapabilityRegistry::new(&config.capabilities)?;
+        let models = Arc::new(ModelRegistry::new(config.models_dir.join("onnx")));
+        let backends =
+            BackendRegistry::new(models.clone(), &config.modules_dir, &config.models_dir)?;
+        for tenant in tenants.values() {
+            capabilities.validate_tenant(tenant)?;
+            backends.validate_tenant(tenant)?;
+        }
+
+        let (tx, rx) = mpsc::channel::<Job>(config.queue_length);
+
+        let state = AppState::new(
+            tx,
+            config,
+            tenants,
+            organizations,
+            capabilities,
+            backends,
+            models,
+        );
+
+        let dispatcher = tokio::spawn(dispatcher::run_dispatcher(
+            rx,
+            state.clone(),
+            config.shutdown.grace_period(),
+        ));
+        tokio::spawn(stats::run_sampler(state.clone()));
+        tokio::spawn(schedule::run_scheduler(state.clone()));
+
+        Ok(Controller {
+            router: router(state.clone()),
+            state,
+            dispatcher,
+        })
+    }
+}
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/healthz", get(|| async { "Hello Sandbox" }))
+        .route("/livez", get(livez))
+        .route("/readyz", get(readyz))
+        .route("/jobs", post(submit_job))
+        .route("/jobs/batch", post(submit_batch))
+        .route("/jobs/{job_id}", get(get_job))
+        .route("/jobs/{job_id}/cancel", post(cancel_job))
+        .route("/jobs/{job_id}/logs", get(job_logs))
+        .route("/jobs/list", get(list_jobs))
+        .route("/schedules", get(list_schedules).post(create_schedule))
+        .route(
+            "/schedules/{schedule_id}",
+            get(get_schedule).delete(delete_schedule),
+        )
+        .route("/schedules/{schedule_id}/pause", post(pause_schedule))
+        .route("/schedules/{schedule_id}/resume", post(resume_schedule))
+        .route("/workflows", post(submit_workflow))
+        .route("/workflows/{workflow_id}", get(get_workflow))
+        .route("/capabilities", get(list_capabilities))
+        .route("/backends", get(list_backends))
+        .route("/models", get(list_models))
+        .route(
+            "/models/{name}",
+            get(list_model_versions)
+                .post(upload_model)
+                .layer(DefaultBodyLimit::max(MAX_MODEL_BYTES)),
+        )
+        .route(
+            "/modules/{module_id}",
+            post(upload_module).layer(DefaultBodyLimit::max(MAX_MODULE_BYTES)),
+        )
+        .route("/tenants", get(list_tenants))
+        .route("/tenants/{tenant_id}", get(get_tenant))
+        .route("/tenants/{tenant_id}/suspend", post(suspend_tenant))
+        .route("/tenants/{tenant_id}/activate", post(activate_tenant))
+        .route("/gpu", get(gpu_usage))
+        .route("/gpu/reservations", get(list_reservations))
+        .route("/stats/queue", get(queue_stats))
+        .route("/dashboard", get(dashboard::index))
+        .route("/dashboard/dashboard.js", get(dashboard::script))
+        .route("/dashboard/dashboard.css", get(dashboard::stylesheet))
+        .with_state(state)
+}
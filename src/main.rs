@@ -1,46 +1,42 @@
-use axum::{
-    Router,
-    routing::{get, post},
-};
-
-mod api;
-mod config;
-mod dispatcher;
-mod domain;
-mod gpu_manager;
-mod sandbox;
-mod state;
-mod tenant;
-
-use api::{get_job, list_jobs, submit_job};
-use state::AppState;
-use tokio::sync::mpsc;
-
-use crate::domain::Job;
-use crate::{config::Config, tenant::Tenant};
+use tokio::sync::oneshot;
+
+use gpu_sandbox_controller::Controller;
+use gpu_sandbox_controller::config::Config;
+use gpu_sandbox_controller::shutdown;
+use gpu_sandbox_controller::tenant::Tenant;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config::load("config.toml").await?;
-    let tenants = Tenant::load_all("tenants.json").await?;
-
-    let (tx, rx) = mpsc::channel::<Job>(config.queue_length);
-
-    let state = AppState::new(tx, &config, tenants);
-
-    let state_clone = state.clone();
-    tokio::spawn(dispatcher::run_dispatcher(rx, state_clone));
+    let (tenants, organizations) = Tenant::load_all("tenants.json").await?;
 
-    let app = Router::new()
-        .route("/healthz", get(|| async { "Hello Sandbox" }))
-        .route("/jobs", post(submit_job))
-        .route("/jobs/{job_id}", get(get_job))
-        .route("/jobs/list", get(list_jobs))
-        .with_state(state);
+    let Controller {
+        state,
+        router,
+        dispatcher,
+    } = Controller::start(&config, tenants, organizations)?;
 
     let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
 
-    axum::serve(listener, app).await?;
+    // The server keeps answering status queries while running jobs drain
+    let (stop_server, server_stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(async move {
+        axum::serve(listener, router)
+            .with_graceful_shutdown(async {
+                let _ = server_stopped.await;
+            })
+            .await
+    });
+
+    shutdown::signal().await;
+    println!("Shutdown: no longer accepting jobs");
+    state.begin_shutdown();
+
+    dispatcher.await?;
+    let _ = stop_server.send(());
+    server.await??;
+
+    shutdown::persist_queued(&state, &config.shutdown.queue_file).await?;
 
     Ok(())
 }
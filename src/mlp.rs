@@ -0,0 +1,202 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{BackendFuture, ExecutionBackend, ResourceModel};
+use crate::capability::ResolvedCapability;
+use crate::domain::Job;
+use crate::sandbox::ExecutionResult;
+
+/// A dense feed-forward network as stored in `models/{id}.json`.
+#[derive(Debug, Deserialize)]
+pub struct MlpModel {
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Layer {
+    /// One row of input weights per output unit
+    pub weights: Vec<Vec<f32>>,
+    pub bias: Vec<f32>,
+    #[serde(default)]
+    pub activation: Activation,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    #[default]
+    Identity,
+    Relu,
+    Sigmoid,
+    Tanh,
+    Softmax,
+}
+
+/// `payload` for MLP jobs: a batch of input vectors.
+#[derive(Deserialize)]
+struct MlpPayload {
+    inputs: Vec<Vec<f32>>,
+}
+
+#[derive(Serialize)]
+struct MlpOutput {
+    outputs: Vec<Vec<f32>>,
+}
+
+impl MlpModel {
+    /// Checks that consecutive layers line up and returns the input width.
+    pub fn validate(&self) -> Result<usize, MlpError> {
+        let Some(first) = self.layers.first() else {
+            return Err(MlpError::InvalidModel("model has no layers".to_string()));
+        };
+        let input_width = first.weights.first().map_or(0, Vec::len);
+
+        let mut width = input_width;
+        for (i, layer) in self.layers.iter().enumerate() {
+            if layer.weights.is_empty() || layer.weights.len() != layer.bias.len() {
+                return Err(MlpError::InvalidModel(format!(
+                    "layer {} has {} weight rows and {} biases",
+                    i,
+                    layer.weights.len(),
+                    layer.bias.len()
+                )));
+            }
+            if layer.weights.iter().any(|row| row.len() != width) {
+                return Err(MlpError::InvalidModel(format!(
+                    "layer {} expects {} inputs per unit",
+                    i, width
+                )));
+            }
+            width = layer.weights.len();
+        }
+
+        Ok(input_width)
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|l| l.bias.len() + l.weights.iter().map(Vec::len).sum::<usize>())
+            .sum()
+    }
+
+    /// Runs one input through the network. Assumes `validate` passed.
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        self.layers.iter().fold(input.to_vec(), |x, layer| {
+            let z: Vec<f32> = layer
+                .weights
+                .iter()
+                .zip(&layer.bias)
+                .map(|(row, b)| row.iter().zip(&x).map(|(w, x)| w * x).sum::<f32>() + b)
+                .collect();
+            layer.activation.apply(z)
+        })
+    }
+}
+
+impl Activation {
+    fn apply(self, mut z: Vec<f32>) -> Vec<f32> {
+        match self {
+            Activation::Identity => {}
+            Activation::Relu => z.iter_mut().for_each(|v| *v = v.max(0.0)),
+            Activation::Sigmoid => z.iter_mut().for_each(|v| *v = 1.0 / (1.0 + (-*v).exp())),
+            Activation::Tanh => z.iter_mut().for_each(|v| *v = v.tanh()),
+            Activation::Softmax => {
+                let max = z.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                z.iter_mut().for_each(|v| *v = (*v - max).exp());
+                let total: f32 = z.iter().sum();
+                z.iter_mut().for_each(|v| *v /= total);
+            }
+        }
+        z
+    }
+}
+
+/// Option A from the project plan: a tiny MLP evaluated natively on the CPU.
+/// `module_id` names the model file, `payload.inputs` holds the batch and the
+/// output is `{"outputs": [...]}` as JSON.
+pub struct MlpBackend {
+    models_dir: PathBuf,
+}
+
+impl MlpBackend {
+    pub fn new(models_dir: impl Into<PathBuf>) -> Self {
+        MlpBackend {
+            models_dir: models_dir.into(),
+        }
+    }
+
+    fn load_model(&self, model_id: &str) -> Result<MlpModel, MlpError> {
+        let path = self.models_dir.join(format!("{}.json", model_id));
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|_| MlpError::ModelNotFound(path.display().to_string()))?;
+        serde_json::from_str(&contents).map_err(|e| MlpError::InvalidModel(e.to_string()))
+    }
+
+    fn run(&self, job: &Job) -> Result<ExecutionResult, MlpError> {
+        let start_time = time::OffsetDateTime::now_utc();
+
+        let model = self.load_model(&job.module_id)?;
+        let input_width = model.validate()?;
+
+        let payload: MlpPayload = serde_json::from_value(job.payload.clone())
+            .map_err(|e| MlpError::InvalidInput(e.to_string()))?;
+        if let Some(input) = payload.inputs.iter().find(|i| i.len() != input_width) {
+            return Err(MlpError::InvalidInput(format!(
+                "model takes {} inputs, got {}",
+                input_width,
+                input.len()
+            )));
+        }
+
+        let outputs = payload.inputs.iter().map(|i| model.forward(i)).collect();
+        let output = serde_json::to_vec(&MlpOutput { outputs })
+            .map_err(|e| MlpError::InvalidInput(e.to_string()))?;
+
+        Ok(ExecutionResult {
+            output,
+            execution_time: time::OffsetDateTime::now_utc() - start_time,
+            memory_used: model.parameter_count() * std::mem::size_of::<f32>(),
+            logs: vec![format!(
+                "Evaluated {} input(s) on {} ({} layers)",
+                payload.inputs.len(),
+                job.module_id,
+                model.layers.len()
+            )],
+            ..Default::default()
+        })
+    }
+}
+
+impl ExecutionBackend for MlpBackend {
+    fn description(&self) -> &'static str {
+        "Native tiny-MLP inference on the host CPU"
+    }
+
+    fn resource_model(&self) -> ResourceModel {
+        ResourceModel::HostCpu
+    }
+
+    fn supports_capability(&self, _capability: &str) -> bool {
+        false
+    }
+
+    fn execute<'a>(
+        &'a self,
+        job: &'a Job,
+        _capabilities: &'a [ResolvedCapability],
+    ) -> BackendFuture<'a> {
+        Box::pin(async move { Ok(tokio::task::block_in_place(|| self.run(job))?) })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MlpError {
+    #[error("Model not found: {0}")]
+    ModelNotFound(String),
+    #[error("Invalid model: {0}")]
+    InvalidModel(String),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+}
@@ -0,0 +1,179 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+/// One stored version of a model.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelVersion {
+    pub name: String,
+    pub version: u32,
+    pub size_bytes: u64,
+    pub uploaded_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelSummary {
+    pub name: String,
+    pub latest: u32,
+    pub versions: Vec<u32>,
+}
+
+/// Versioned model storage on disk, laid out as `{root}/{name}/v{N}.onnx`.
+/// Jobs reference a model by `module_id` as `name` (latest) or `name@N`.
+pub struct ModelRegistry {
+    root: PathBuf,
+    // Serializes uploads so two of them can't claim the same version
+    upload_lock: Mutex<()>,
+}
+
+impl ModelRegistry {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        ModelRegistry {
+            root: root.into(),
+            upload_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Stores `bytes` as the next version of `name`. The caller validates
+    /// the model first.
+    pub fn upload(&self, name: &str, bytes: &[u8]) -> Result<ModelVersion, ModelError> {
+        check_name(name)?;
+        let _guard = self.upload_lock.lock().unwrap();
+
+        let version = self.version_numbers(name)?.last().copied().unwrap_or(0) + 1;
+        let dir = self.root.join(name);
+        std::fs::create_dir_all(&dir).map_err(|e| ModelError::Storage(e.to_string()))?;
+        std::fs::write(dir.join(format!("v{}.onnx", version)), bytes)
+            .map_err(|e| ModelError::Storage(e.to_string()))?;
+
+        self.version(name, version)
+    }
+
+    pub fn list(&self) -> Result<Vec<ModelSummary>, ModelError> {
+        let Ok(entries) = std::fs::read_dir(&self.root) else {
+            return Ok(Vec::new());
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_dir())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .collect();
+        names.sort();
+
+        let mut models = Vec::new();
+        for name in names {
+            let versions = self.version_numbers(&name)?;
+            if let Some(latest) = versions.last().copied() {
+                models.push(ModelSummary {
+                    name,
+                    latest,
+                    versions,
+                });
+            }
+        }
+        Ok(models)
+    }
+
+    pub fn versions(&self, name: &str) -> Result<Vec<ModelVersion>, ModelError> {
+        check_name(name)?;
+        let numbers = self.version_numbers(name)?;
+        if numbers.is_empty() {
+            return Err(ModelError::NotFound(name.to_string()));
+        }
+        numbers.into_iter().map(|v| self.version(name, v)).collect()
+    }
+
+    /// Resolves `name` or `name@N` to a stored version and its file.
+    pub fn resolve(&self, reference: &str) -> Result<(ModelVersion, PathBuf), ModelError> {
+        let (name, version) = match reference.split_once('@') {
+            Some((name, version)) => {
+                let version = version
+                    .parse()
+                    .map_err(|_| ModelError::NotFound(reference.to_string()))?;
+                (name, Some(version))
+            }
+            None => (reference, None),
+        };
+        check_name(name)?;
+
+        let numbers = self.version_numbers(name)?;
+        let version = match version {
+            Some(v) if numbers.contains(&v) => v,
+            None if !numbers.is_empty() => numbers[numbers.len() - 1],
+            _ => return Err(ModelError::NotFound(reference.to_string())),
+        };
+
+        Ok((self.version(name, version)?, self.path(name, version)))
+    }
+
+    fn path(&self, name: &str, version: u32) -> PathBuf {
+        self.root.join(name).join(format!("v{}.onnx", version))
+    }
+
+    fn version(&self, name: &str, version: u32) -> Result<ModelVersion, ModelError> {
+        let metadata = std::fs::metadata(self.path(name, version))
+            .map_err(|_| ModelError::NotFound(format!("{}@{}", name, version)))?;
+        Ok(ModelVersion {
+            name: name.to_string(),
+            version,
+            size_bytes: metadata.len(),
+            uploaded_at: metadata.modified().ok().map(OffsetDateTime::from),
+        })
+    }
+
+    /// Stored versions of `name`, ascending. Empty if there are none.
+    fn version_numbers(&self, name: &str) -> Result<Vec<u32>, ModelError> {
+        let entries = match std::fs::read_dir(self.root.join(name)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(ModelError::Storage(e.to_string())),
+        };
+
+        let mut versions: Vec<u32> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let file_name = e.file_name().into_string().ok()?;
+                file_name
+                    .strip_prefix('v')?
+                    .strip_suffix(".onnx")?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        versions.sort_unstable();
+        Ok(versions)
+    }
+}
+
+/// Names become directory names, so keep them to a safe alphabet.
+fn check_name(name: &str) -> Result<(), ModelError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(ModelError::InvalidName(name.to_string()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ModelError {
+    #[error("Invalid model name {0}: use up to 64 letters, digits, '-' or '_'")]
+    InvalidName(String),
+    #[error("Model {0} not found")]
+    NotFound(String),
+    #[error("Invalid model: {0}")]
+    Invalid(String),
+    #[error("Model storage failed: {0}")]
+    Storage(String),
+}
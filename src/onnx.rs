@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::Serialize;
+use time::Duration;
+use tract_onnx::prelude::*;
+use tract_onnx::tract_hir::infer::Factoid;
+
+use crate::backend::{BackendError, BackendFuture, ExecutionBackend, ResourceModel};
+use crate::capability::ResolvedCapability;
+use crate::domain::Job;
+use crate::model_registry::{ModelError, ModelRegistry};
+use crate::sandbox::ExecutionResult;
+use crate::tensor::{self, DType, Element, Signature, TensorSpec};
+
+/// `output` of ONNX jobs: the version that ran. The model's outputs are in
+/// `result.tensors`, named after the graph outputs.
+#[derive(Serialize)]
+struct OnnxOutput {
+    model: String,
+    version: u32,
+}
+
+/// Checks that `bytes` hold an ONNX model tract can load. Used on upload.
+pub fn check_model(bytes: &[u8]) -> Result<(), ModelError> {
+    tract_onnx::onnx()
+        .model_for_read(&mut &bytes[..])
+        .map(|_| ())
+        .map_err(|e| ModelError::Invalid(format!("{:#}", e)))
+}
+
+/// Runs ONNX models from the model registry on the CPU through tract. Jobs
+/// name the model in `module_id` (`name` or `name@version`) and pass one
+/// tensor per graph input, by name. They still take a GPU slot, so they go
+/// through the same reservation and quotas as WASM jobs.
+pub struct OnnxBackend {
+    models: Arc<ModelRegistry>,
+    max_execution_time: Duration,
+}
+
+impl OnnxBackend {
+    pub fn new(models: Arc<ModelRegistry>, max_execution_time: Duration) -> Self {
+        OnnxBackend {
+            models,
+            max_execution_time,
+        }
+    }
+
+    async fn run(&self, job: &Job) -> Result<ExecutionResult, OnnxError> {
+        let start_time = time::OffsetDateTime::now_utc();
+
+        let (version, path) = self.models.resolve(&job.module_id)?;
+        let inputs = job.tensors.clone();
+        let input_bytes: usize = inputs.values().map(|t| t.bytes().len()).sum();
+        let model_name = version.name.clone();
+
+        // Inference blocks, so it runs off the runtime like the WASM sandbox
+        let handle = tokio::task::spawn_blocking(move || infer(&path, inputs));
+        let outputs = tokio::time::timeout(
+            std::time::Duration::from_micros(self.max_execution_time.whole_microseconds() as u64),
+            handle,
+        )
+        .await
+        .map_err(|_| OnnxError::Timeout)?
+        .map_err(|e| OnnxError::Inference(format!("Task join failed: {}", e)))??;
+
+        let output_bytes: usize = outputs.values().map(|t| t.bytes().len()).sum();
+        let output = serde_json::to_vec(&OnnxOutput {
+            model: model_name.clone(),
+            version: version.version,
+        })
+        .map_err(|e| OnnxError::Inference(e.to_string()))?;
+
+        Ok(ExecutionResult {
+            output,
+            execution_time: time::OffsetDateTime::now_utc() - start_time,
+            memory_used: version.size_bytes as usize + input_bytes + output_bytes,
+            logs: vec![format!("Ran {}@{}", model_name, version.version)],
+            tensors: outputs,
+            ..Default::default()
+        })
+    }
+}
+
+fn inference(e: TractError) -> OnnxError {
+    OnnxError::Inference(format!("{:#}", e))
+}
+
+fn dtype_of(datum_type: DatumType) -> Option<DType> {
+    match datum_type {
+        DatumType::F32 => Some(DType::F32),
+        DatumType::F64 => Some(DType::F64),
+        DatumType::I32 => Some(DType::I32),
+        DatumType::I64 => Some(DType::I64),
+        DatumType::U8 => Some(DType::U8),
+        _ => None,
+    }
+}
+
+fn datum_type(dtype: DType) -> DatumType {
+    match dtype {
+        DType::F32 => DatumType::F32,
+        DType::F64 => DatumType::F64,
+        DType::I32 => DatumType::I32,
+        DType::I64 => DatumType::I64,
+        DType::U8 => DatumType::U8,
+    }
+}
+
+/// The graph inputs by name, with the element types and dims the model
+/// declares. Symbolic dims match any size.
+fn signature_of(model: &InferenceModel) -> Result<Signature, OnnxError> {
+    let mut inputs = BTreeMap::new();
+    for (i, outlet) in model.inputs.iter().enumerate() {
+        let name = model.node(outlet.node).name.clone();
+        let fact = model.input_fact(i).map_err(inference)?;
+        let Some(dtype) = fact.datum_type.concretize().and_then(dtype_of) else {
+            return Err(OnnxError::Model(ModelError::Invalid(format!(
+                "input {} has no supported element type",
+                name
+            ))));
+        };
+        let shape = (!fact.shape.is_open()).then(|| {
+            fact.shape
+                .dims()
+                .map(|d| d.concretize()?.to_i64().ok()?.try_into().ok())
+                .collect()
+        });
+        inputs.insert(name, TensorSpec { dtype, shape });
+    }
+    Ok(Signature { inputs })
+}
+
+fn to_tract(input: &tensor::Tensor) -> Result<Tensor, OnnxError> {
+    fn typed<T: Element + Datum>(input: &tensor::Tensor) -> Result<Tensor, OnnxError> {
+        let values = input
+            .values::<T>()
+            .map_err(|e| OnnxError::InvalidInput(e.to_string()))?;
+        Tensor::from_shape(input.shape(), &values)
+            .map_err(|e| OnnxError::InvalidInput(format!("{:#}", e)))
+    }
+    match input.dtype() {
+        DType::F32 => typed::<f32>(input),
+        DType::F64 => typed::<f64>(input),
+        DType::I32 => typed::<i32>(input),
+        DType::I64 => typed::<i64>(input),
+        DType::U8 => typed::<u8>(input),
+    }
+}
+
+/// Outputs of a type jobs can't carry are cast to `f32`.
+fn from_tract(value: &Tensor) -> Result<tensor::Tensor, OnnxError> {
+    fn typed<T: Element + Datum>(value: &Tensor) -> Result<tensor::Tensor, OnnxError> {
+        let view = value.to_plain_array_view::<T>().map_err(inference)?;
+        tensor::Tensor::from_values(view.shape().to_vec(), view.iter().copied())
+            .map_err(|e| OnnxError::Inference(e.to_string()))
+    }
+    match dtype_of(value.datum_type()) {
+        Some(DType::F32) => typed::<f32>(value),
+        Some(DType::F64) => typed::<f64>(value),
+        Some(DType::I32) => typed::<i32>(value),
+        Some(DType::I64) => typed::<i64>(value),
+        Some(DType::U8) => typed::<u8>(value),
+        None => typed::<f32>(&*value.cast_to::<f32>().map_err(inference)?),
+    }
+}
+
+/// Loads the model with input facts pinned to the given tensors, optimizes it
+/// and runs it once.
+fn infer(
+    path: &Path,
+    inputs: BTreeMap<String, tensor::Tensor>,
+) -> Result<BTreeMap<String, tensor::Tensor>, OnnxError> {
+    let mut model = tract_onnx::onnx().model_for_path(path).map_err(inference)?;
+
+    let input_names: Vec<String> = model
+        .inputs
+        .iter()
+        .map(|o| model.node(o.node).name.clone())
+        .collect();
+    let output_names: Vec<String> = model
+        .outputs
+        .iter()
+        .map(|o| {
+            model
+                .outlet_label(*o)
+                .unwrap_or(&model.node(o.node).name)
+                .to_string()
+        })
+        .collect();
+
+    let mut values: TVec<TValue> = tvec![];
+    for (i, name) in input_names.iter().enumerate() {
+        let input = inputs
+            .get(name)
+            .ok_or_else(|| OnnxError::InvalidInput(format!("missing input {}", name)))?;
+        model
+            .set_input_fact(
+                i,
+                InferenceFact::dt_shape(datum_type(input.dtype()), input.shape()),
+            )
+            .map_err(inference)?;
+        values.push(to_tract(input)?.into());
+    }
+
+    let plan = model
+        .into_optimized()
+        .and_then(|m| m.into_runnable())
+        .map_err(inference)?;
+    let outputs = plan.run(values).map_err(inference)?;
+
+    outputs
+        .iter()
+        .zip(output_names)
+        .map(|(value, name)| Ok((name, from_tract(value)?)))
+        .collect()
+}
+
+impl ExecutionBackend for OnnxBackend {
+    fn description(&self) -> &'static str {
+        "ONNX models from the model registry, run on the CPU with tract"
+    }
+
+    fn resource_model(&self) -> ResourceModel {
+        ResourceModel::GpuPartition
+    }
+
+    fn supports_capability(&self, _capability: &str) -> bool {
+        false
+    }
+
+    fn signature(&self, module_id: &str) -> Result<Option<Signature>, BackendError> {
+        let (_, path) = self.models.resolve(module_id).map_err(OnnxError::from)?;
+        let model = tract_onnx::onnx()
+            .model_for_path(path)
+            .map_err(|e| OnnxError::Model(ModelError::Invalid(format!("{:#}", e))))?;
+        Ok(Some(signature_of(&model)?))
+    }
+
+    fn execute<'a>(
+        &'a self,
+        job: &'a Job,
+        _capabilities: &'a [ResolvedCapability],
+    ) -> BackendFuture<'a> {
+        Box::pin(async move { Ok(self.run(job).await?) })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OnnxError {
+    #[error(transparent)]
+    Model(#[from] ModelError),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("Inference failed: {0}")]
+    Inference(String),
+    #[error("Inference timed out")]
+    Timeout,
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::backend::{BackendError, ExecutionBackend};
+use crate::capability::ResolvedCapability;
+use crate::domain::{Job, JobStatus};
+use crate::job_store::JobStore;
+use crate::sandbox::ExecutionResult;
+
+/// Most stages one pipeline job may run, `module_id` included.
+pub const MAX_STAGES: usize = 16;
+
+/// Runs a job on `backend`: its module, or each of its stages in turn within
+/// the one slot reservation the job holds. Stage status and timings are
+/// recorded in `jobs` as the stages run.
+pub async fn execute(
+    backend: &Arc<dyn ExecutionBackend>,
+    job: &Job,
+    capabilities: &[ResolvedCapability],
+    jobs: &JobStore,
+) -> Result<ExecutionResult, BackendError> {
+    if job.stages.is_empty() {
+        if job.pass_outputs {
+            check_inputs(backend, job).await?;
+        }
+        return backend.execute(job, capabilities).await;
+    }
+
+    let mut stage_job = job.clone();
+    let mut combined = ExecutionResult::default();
+    for (i, stage) in job.stages.iter().enumerate() {
+        // Stages left when the job is cancelled don't start
+        if job.cancel.is_cancelled() {
+            jobs.update(job.job_id, |job| {
+                for later in job.stages.iter_mut().skip(i) {
+                    later.status = JobStatus::Cancelled;
+                }
+            });
+            return Err(BackendError::Cancelled);
+        }
+
+        let started = OffsetDateTime::now_utc();
+        jobs.update(job.job_id, |job| {
+            if let Some(stage) = job.stages.get_mut(i) {
+                stage.status = JobStatus::Running;
+                stage.started_at = Some(started);
+            }
+        });
+
+        stage_job.module_id = stage.module_id.clone();
+        // Later stages take inputs the API never saw
+        let executed = if i > 0 || job.pass_outputs {
+            check_inputs(backend, &stage_job).await
+        } else {
+            Ok(())
+        };
+        let executed = match executed {
+            Ok(()) => backend.execute(&stage_job, capabilities).await,
+            Err(e) => Err(e),
+        };
+
+        let duration = OffsetDateTime::now_utc() - started;
+        let result = match executed {
+            Ok(result) => result,
+            Err(e) => {
+                let message = e.to_string();
+                let cancelled = job.cancel.is_cancelled();
+                jobs.update(job.job_id, |job| {
+                    if let Some(stage) = job.stages.get_mut(i) {
+                        stage.status = if cancelled {
+                            JobStatus::Cancelled
+                        } else {
+                            JobStatus::Failed(message)
+                        };
+                        stage.duration = Some(duration);
+                    }
+                    for later in job.stages.iter_mut().skip(i + 1) {
+                        later.status = JobStatus::Cancelled;
+                    }
+                });
+                return Err(BackendError::Stage {
+                    stage: i,
+                    module_id: stage.module_id.clone(),
+                    source: Box::new(e),
+                });
+            }
+        };
+
+        jobs.update(job.job_id, |job| {
+            if let Some(stage) = job.stages.get_mut(i) {
+                stage.status =
+                    JobStatus::Finished(format!("Produced {} bytes", result.output.len()));
+                stage.duration = Some(duration);
+            }
+        });
+
+        // The output bytes and tensors are all the next stage gets. Components
+        // have no stdin, so they read the bytes base64-encoded from the payload.
+        stage_job.input = result.output.clone();
+        stage_job.tensors = result.tensors.clone();
+        stage_job
+            .add_to_payload("stage_input", Value::String(BASE64.encode(&result.output)))
+            .map_err(BackendError::Payload)?;
+        combine(&mut combined, result);
+    }
+    Ok(combined)
+}
+
+/// Checks a job's input tensors against the module signature, for inputs the
+/// API couldn't check on submit.
+async fn check_inputs(backend: &Arc<dyn ExecutionBackend>, job: &Job) -> Result<(), BackendError> {
+    let (backend, module_id) = (backend.clone(), job.module_id.clone());
+    let signature = tokio::task::spawn_blocking(move || backend.signature(&module_id))
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))?;
+    if let Some(signature) = signature {
+        signature.check_inputs(&job.tensors)?;
+    }
+    Ok(())
+}
+
+/// Folds a stage's result into the job's: the last stage's output, every
+/// stage's logs, the total time and the peak memory.
+fn combine(combined: &mut ExecutionResult, stage: ExecutionResult) {
+    combined.output = stage.output;
+    combined.tensors = stage.tensors;
+    combined.execution_time += stage.execution_time;
+    combined.memory_used = combined.memory_used.max(stage.memory_used);
+    combined.device_memory_peak = combined.device_memory_peak.max(stage.device_memory_peak);
+    combined.egress_log.extend(stage.egress_log);
+    combined.logs.extend(stage.logs);
+}
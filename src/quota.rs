@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+
+use crate::gpu_manager::ResourceShape;
+use crate::tenant::{Organization, Tenant};
+
+/// Limits that can be set at every level of the quota hierarchy. Anything left
+/// unset is inherited from `quota_defaults` in `config.toml`; unset there too
+/// means unlimited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuotaLimits {
+    #[serde(default)]
+    pub gpu_limit: Option<ResourceShape>,
+    #[serde(default)]
+    pub rate_limit: Option<usize>, // #jobs / minute, 0 disables
+    #[serde(default)]
+    pub max_queued: Option<usize>,
+}
+
+impl QuotaLimits {
+    fn or(&self, defaults: &QuotaLimits) -> QuotaLimits {
+        QuotaLimits {
+            gpu_limit: self.gpu_limit.or(defaults.gpu_limit),
+            rate_limit: self.rate_limit.or(defaults.rate_limit),
+            max_queued: self.max_queued.or(defaults.max_queued),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QuotaDefaults {
+    #[serde(default)]
+    pub organization: QuotaLimits,
+    #[serde(default)]
+    pub tenant: QuotaLimits,
+    #[serde(default)]
+    pub project: QuotaLimits,
+}
+
+/// Effective limits for one level, keyed as `org:<id>`, `tenant:<id>` or
+/// `project:<tenant>/<id>`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScopeQuota {
+    pub scope: String,
+    pub limits: QuotaLimits,
+}
+
+/// Resolves the organization -> tenant -> project chain a job is charged
+/// against, outermost level first.
+pub fn quota_chain(
+    defaults: &QuotaDefaults,
+    org: Option<&Organization>,
+    tenant: &Tenant,
+    project_id: Option<&str>,
+) -> Result<Vec<ScopeQuota>, QuotaError> {
+    let mut chain = Vec::with_capacity(3);
+
+    if let Some(org) = org {
+        chain.push(ScopeQuota {
+            scope: format!("org:{}", org.org_id),
+            limits: org.limits.or(&defaults.organization),
+        });
+    }
+
+    chain.push(ScopeQuota {
+        scope: format!("tenant:{}", tenant.tenant_id),
+        limits: tenant.limits.or(&defaults.tenant),
+    });
+
+    if let Some(project_id) = project_id {
+        let Some(project) = tenant.projects.iter().find(|p| p.project_id == project_id) else {
+            return Err(QuotaError::UnknownProject(
+                tenant.tenant_id.clone(),
+                project_id.to_string(),
+            ));
+        };
+        chain.push(ScopeQuota {
+            scope: format!("project:{}/{}", tenant.tenant_id, project.project_id),
+            limits: project.limits.or(&defaults.project),
+        });
+    }
+
+    Ok(chain)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QuotaError {
+    #[error("Tenant {0} has no project {1}")]
+    UnknownProject(String, String),
+    #[error("Organization {0} not known")]
+    UnknownOrganization(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::gpu_manager::{GpuError, GpuManager, SlotReservation};
+
+    fn slices(slices: u32) -> serde_json::Value {
+        serde_json::json!({ "compute_slices": slices, "memory_gb": slices * 10 })
+    }
+
+    /// An `acme/t/p` chain with the given gpu limits in slices, outermost first.
+    fn chain(org: Option<u32>, tenant: Option<u32>, project: Option<u32>) -> Vec<ScopeQuota> {
+        let org: Organization = serde_json::from_value(serde_json::json!({
+            "org_id": "acme",
+            "gpu_limit": org.map(slices),
+        }))
+        .unwrap();
+        let tenant: Tenant = serde_json::from_value(serde_json::json!({
+            "tenant_id": "t",
+            "org_id": "acme",
+            "allowed_capabilities": [],
+            "status": "active",
+            "gpu_limit": tenant.map(slices),
+            "projects": [{ "project_id": "p", "gpu_limit": project.map(slices) }],
+        }))
+        .unwrap();
+
+        quota_chain(&QuotaDefaults::default(), Some(&org), &tenant, Some("p")).unwrap()
+    }
+
+    fn manager(slices: u32) -> Arc<Mutex<GpuManager>> {
+        let config = Config::parse(&format!(
+            r#"
+queue_length = 8
+default_profile = "1g.10gb"
+capabilities = {{}}
+
+[profiles."1g.10gb"]
+compute_slices = 1
+memory_gb = 10
+
+[[devices]]
+device_id = "gpu0"
+compute_slices = {slices}
+memory_gb = {}
+"#,
+            slices * 10
+        ))
+        .unwrap();
+        Arc::new(Mutex::new(GpuManager::new(&config)))
+    }
+
+    fn reserve(
+        manager: &Arc<Mutex<GpuManager>>,
+        quotas: &[ScopeQuota],
+    ) -> Result<SlotReservation, GpuError> {
+        let shape = ResourceShape {
+            compute_slices: 1,
+            memory_gb: 10,
+        };
+        GpuManager::try_reserve(manager, "t", quotas, Uuid::new_v4(), shape, 1)
+    }
+
+    #[test]
+    fn unset_limits_come_from_the_defaults() {
+        let defaults = QuotaDefaults {
+            tenant: QuotaLimits {
+                max_queued: Some(3),
+                rate_limit: Some(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let tenant: Tenant = serde_json::from_value(serde_json::json!({
+            "tenant_id": "t",
+            "allowed_capabilities": [],
+            "status": "active",
+            "rate_limit": 5,
+        }))
+        .unwrap();
+
+        let chain = quota_chain(&defaults, None, &tenant, None).unwrap();
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].scope, "tenant:t");
+        assert_eq!(chain[0].limits.rate_limit, Some(5));
+        assert_eq!(chain[0].limits.max_queued, Some(3));
+        assert!(chain[0].limits.gpu_limit.is_none());
+
+        assert!(matches!(
+            quota_chain(&defaults, None, &tenant, Some("missing")),
+            Err(QuotaError::UnknownProject(..))
+        ));
+    }
+
+    #[test]
+    fn tightest_limit_in_the_chain_wins() {
+        let cases = [
+            (chain(Some(2), Some(8), Some(8)), "org:acme"),
+            (chain(Some(8), Some(2), Some(8)), "tenant:t"),
+            (chain(Some(8), Some(8), Some(2)), "project:t/p"),
+            (chain(None, Some(2), None), "tenant:t"),
+        ];
+        for (quotas, tightest) in cases {
+            let manager = manager(8);
+            let _first = reserve(&manager, &quotas).unwrap();
+            let _second = reserve(&manager, &quotas).unwrap();
+
+            match reserve(&manager, &quotas) {
+                Err(GpuError::QuotaReached(scope)) => assert_eq!(scope, tightest),
+                _ => panic!("{tightest} should have refused the third slice"),
+            }
+        }
+    }
+
+    #[test]
+    fn usage_is_released_at_every_level() {
+        let manager = manager(8);
+        let quotas = chain(Some(2), Some(2), Some(2));
+        let sibling = quotas[..2].to_vec();
+
+        let reservation = reserve(&manager, &quotas).unwrap();
+        let scopes = GpuManager::lock(&manager).usage().scopes;
+        for scope in ["org:acme", "tenant:t", "project:t/p"] {
+            assert_eq!(scopes[scope].compute_slices, 1, "{scope}");
+        }
+        let _other = reserve(&manager, &sibling).unwrap();
+        assert!(reserve(&manager, &sibling).is_err());
+
+        drop(reservation);
+        let scopes = GpuManager::lock(&manager).usage().scopes;
+        assert_eq!(scopes["org:acme"].compute_slices, 1);
+        assert_eq!(scopes["tenant:t"].compute_slices, 1);
+        assert!(!scopes.contains_key("project:t/p"));
+
+        // The freed slice is usable again at the org and tenant level
+        assert!(reserve(&manager, &sibling).is_ok());
+    }
+}
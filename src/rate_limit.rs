@@ -0,0 +1,146 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Mutex, PoisonError};
+
+use time::{Duration, OffsetDateTime};
+
+use crate::quota::ScopeQuota;
+
+/// Number of shards of the submission windows.
+const SHARDS: usize = 16;
+
+/// `rate_limit` is "#jobs / minute" at every quota level.
+const WINDOW: Duration = Duration::minutes(1);
+
+// scope key (see `ScopeQuota`) -> submission timestamps in the window
+type Windows = HashMap<String, VecDeque<OffsetDateTime>>;
+
+/// Sliding one-minute submission windows per quota scope, sharded by scope
+/// so tenants don't wait on each other's windows.
+pub struct RateLimiter {
+    hasher: RandomState,
+    shards: Box<[Mutex<Windows>]>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitError {
+    #[error("Rate limit exceeded for {scope}: max {limit} jobs per minute")]
+    Exceeded { scope: String, limit: usize },
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            hasher: RandomState::new(),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+}
+
+impl RateLimiter {
+    fn shard(&self, scope: &str) -> &Mutex<Windows> {
+        &self.shards[self.hasher.hash_one(scope) as usize % SHARDS]
+    }
+
+    /// Takes a slot at `now` in the window of every rate-limited quota. If
+    /// one of them is full, the slots taken so far are given back.
+    pub fn acquire(
+        &self,
+        quotas: &[ScopeQuota],
+        now: OffsetDateTime,
+    ) -> Result<(), RateLimitError> {
+        for (i, quota) in quotas.iter().enumerate() {
+            let Some(limit) = quota.limits.rate_limit.filter(|l| *l > 0) else {
+                continue;
+            };
+
+            let mut shard = self
+                .shard(&quota.scope)
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let usage = shard.entry(quota.scope.clone()).or_default();
+            while usage.front().is_some_and(|ts| now - *ts > WINDOW) {
+                usage.pop_front();
+            }
+
+            if usage.len() >= limit {
+                drop(shard);
+                self.release(&quotas[..i]);
+                return Err(RateLimitError::Exceeded {
+                    scope: quota.scope.clone(),
+                    limit,
+                });
+            }
+            usage.push_back(now);
+        }
+        Ok(())
+    }
+
+    /// Gives back the slots of a submission that wasn't queued after all.
+    pub fn release(&self, quotas: &[ScopeQuota]) {
+        for quota in quotas {
+            if quota.limits.rate_limit.is_none_or(|l| l == 0) {
+                continue;
+            }
+
+            let mut shard = self
+                .shard(&quota.scope)
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if let Some(usage) = shard.get_mut(&quota.scope) {
+                let _ = usage.pop_back();
+                if usage.is_empty() {
+                    shard.remove(&quota.scope);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quota::QuotaLimits;
+
+    fn quota(scope: &str, rate_limit: Option<usize>) -> ScopeQuota {
+        ScopeQuota {
+            scope: scope.to_string(),
+            limits: QuotaLimits {
+                rate_limit,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn full_window_rejects_and_gives_back_earlier_slots() {
+        let limiter = RateLimiter::default();
+        let now = OffsetDateTime::now_utc();
+        let quotas = [quota("org:acme", Some(5)), quota("tenant:tenant1", Some(1))];
+
+        limiter.acquire(&quotas, now).unwrap();
+        let err = limiter.acquire(&quotas, now).unwrap_err();
+        assert!(matches!(err, RateLimitError::Exceeded { limit: 1, .. }));
+
+        // Only the first submission is counted against the org
+        let org = [quota("org:acme", Some(2))];
+        limiter.acquire(&org, now).unwrap();
+        assert!(limiter.acquire(&org, now).is_err());
+    }
+
+    #[test]
+    fn window_slides_and_release_undoes_acquire() {
+        let limiter = RateLimiter::default();
+        let now = OffsetDateTime::now_utc();
+        let quotas = [quota("tenant:tenant1", Some(1)), quota("project:p", None)];
+
+        limiter.acquire(&quotas, now).unwrap();
+        limiter.release(&quotas);
+        limiter.acquire(&quotas, now).unwrap();
+
+        assert!(limiter.acquire(&quotas, now).is_err());
+        limiter
+            .acquire(&quotas, now + WINDOW + Duration::seconds(1))
+            .unwrap();
+    }
+}
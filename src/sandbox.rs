@@ -1,44 +1,193 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use serde::Serialize;
 use time::Duration;
 
-use wasmtime::{Config, Engine, Linker, Module, Store};
+use wasmtime::{
+    Config, Engine, Instance, Linker, Module, ResourceLimiter, Store, Trap, TypedFunc,
+    UpdateDeadline,
+};
+
+use wasmtime::component::Component;
 
+use crate::capability::{CapabilityParams, EgressParams, ResolvedCapability};
+use crate::component::{self, JobInput};
 use crate::domain::Job;
+use crate::egress::{self, EgressRecord};
+use crate::tensor::{self, Tensor, TensorIo};
+use crate::vgpu::{self, VirtualDevice};
+use crate::wasi::{self, WasiSetup};
+
+/// How often running guests check whether to stop.
+const EPOCH_TICK: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Epoch ticks the health probe may run for, about a second.
+const PROBE_TICKS: u64 = 100;
 
 pub struct SandboxExecutor {
     engine: Engine,
     config: SandboxConfig,
+    modules_dir: PathBuf,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Default, Serialize)]
 pub struct ExecutionResult {
     pub output: Vec<u8>,
     pub execution_time: Duration,
     pub memory_used: usize,
+    pub egress_log: Vec<EgressRecord>,
+    pub logs: Vec<String>,
+    /// Peak bytes held in virtual device buffers
+    pub device_memory_peak: usize,
+    /// Named output tensors
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tensors: BTreeMap<String, Tensor>,
 }
 
 #[derive(Clone, Copy)]
 pub struct SandboxConfig {
     pub max_memory_bytes: usize,
     pub max_execution_time: Duration,
+    #[allow(dead_code)]
     pub module_cache_size: usize,
     pub enable_fuel: bool,
+    /// Host-side cap on emulated device memory, whatever the slot size
+    pub max_device_memory_bytes: usize,
 }
 
-struct SandboxContext {
+pub struct SandboxContext {
     pub job_id: uuid::Uuid,
     pub tenant_id: String,
     pub max_memory: usize,
+    pub memory_used: usize,
+    pub memory_exceeded: bool,
+    pub log_bytes_remaining: usize,
+    pub egress: Option<EgressParams>,
+    pub egress_log: Vec<EgressRecord>,
+    pub logs: Vec<String>,
+    pub wasi: Option<WasiSetup>,
+    pub device: VirtualDevice,
+    pub tensors: TensorIo,
 }
 
-#[derive(Debug)]
+impl SandboxContext {
+    /// Records a guest log line; callers cut it to `log_bytes_remaining` first.
+    pub fn write_log(&mut self, msg: &str) {
+        self.log_bytes_remaining = self.log_bytes_remaining.saturating_sub(msg.len());
+        println!("[WASM Log | Job {}] {}", self.job_id, msg);
+        self.logs.push(msg.to_string());
+    }
+
+    pub fn gpu_compute(&self, operation: i32) -> i32 {
+        println!(
+            "GPU compute called by tenant: {} for job: {}",
+            self.tenant_id, self.job_id
+        );
+
+        // Mock GPU computation
+        // Real implementation would submit to GPU queue
+        operation * 2
+    }
+
+    /// Sends a guest POST under the job's egress grant and records it in the
+    /// egress log.
+    pub fn egress_post(
+        &mut self,
+        url: String,
+        headers: &[(String, String)],
+        body: &[u8],
+    ) -> Result<egress::EgressResponse, egress::EgressError> {
+        let Some(params) = self.egress.as_ref() else {
+            return Err(egress::EgressError::HostNotAllowed(url));
+        };
+
+        let requested_at = time::OffsetDateTime::now_utc();
+        let outcome = egress::post(params, &url, headers, body);
+        self.record_egress(url, body.len(), requested_at, &outcome);
+        outcome
+    }
+
+    /// Refuses a guest POST whose body is over the grant's `max_body_bytes`
+    /// without reading the body out of guest memory.
+    pub fn egress_too_large(&mut self, url: String, body_len: usize) -> i32 {
+        let outcome = Err(egress::EgressError::RequestTooLarge(body_len));
+        self.record_egress(url, body_len, time::OffsetDateTime::now_utc(), &outcome);
+        egress::EgressError::RequestTooLarge(body_len).guest_code()
+    }
+
+    fn record_egress(
+        &mut self,
+        url: String,
+        request_bytes: usize,
+        requested_at: time::OffsetDateTime,
+        outcome: &Result<egress::EgressResponse, egress::EgressError>,
+    ) {
+        let elapsed = time::OffsetDateTime::now_utc() - requested_at;
+
+        let code = match outcome {
+            Ok(response) => i32::from(response.status),
+            Err(e) => e.guest_code(),
+        };
+        println!(
+            "Network egress by tenant: {} for job: {} to {} -> {}",
+            self.tenant_id, self.job_id, url, code
+        );
+        self.logs.push(format!("http_post {} -> {}", url, code));
+        self.egress_log.push(EgressRecord {
+            url,
+            requested_at,
+            request_bytes,
+            status: outcome.as_ref().ok().map(|r| r.status),
+            response_bytes: outcome.as_ref().map_or(0, |r| r.body.len()),
+            error: outcome.as_ref().err().map(|e| e.to_string()),
+            elapsed_ms: elapsed.whole_milliseconds() as u64,
+        });
+    }
+}
+
+impl ResourceLimiter for SandboxContext {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        if desired > self.max_memory {
+            self.memory_exceeded = true;
+            return Ok(false);
+        }
+        self.memory_used = self.memory_used.max(desired);
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        Ok(true)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
 pub enum SandboxError {
+    #[error("Module not found: {0}")]
     ModuleNotFound(String),
+    #[error("Module load failed: {0}")]
     ModeleLoadFailed(String),
+    #[error("Execution failed: {0}")]
     ExecutionFailed(String),
+    #[error("Execution timed out")]
     Timeout,
+    #[error("Job was cancelled")]
+    Cancelled,
+    #[error("Memory limit exceeded")]
     OutOfMemory,
+    #[error("Capability violation: {0}")]
     CapabilityViolation(String),
+    #[error("Trap occured: {0}")]
     TrapOccured(String),
 }
 
@@ -50,8 +199,8 @@ impl SandboxExecutor {
             config.consume_fuel(true);
         }
 
-        // Note: epoch_interruption requires calling store.set_epoch_deadline()
-        // and periodically incrementing the engine epoch. We use fuel + tokio timeout instead.
+        // Guests check for cancellation and the time limit on every epoch tick
+        config.epoch_interruption(true);
 
         config.max_wasm_stack(2 * 1024 * 1024); // 2MB stack limit
 
@@ -68,35 +217,131 @@ impl SandboxExecutor {
             SandboxError::ModeleLoadFailed(format!("Engine creation failed: {}", e))
         })?;
 
+        // Ticks until the executor, and with it the engine, is dropped
+        let ticked = engine.weak();
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(EPOCH_TICK);
+                match ticked.upgrade() {
+                    Some(engine) => engine.increment_epoch(),
+                    None => break,
+                }
+            }
+        });
+
         Ok(SandboxExecutor {
             engine,
             config: sandbox_config,
+            modules_dir: PathBuf::from("modules"),
         })
     }
 
-    pub fn default() -> Result<Self, SandboxError> {
+    pub fn with_default_config() -> Result<Self, SandboxError> {
         let default_config = SandboxConfig {
             max_memory_bytes: 64 * 1024 * 1024, // 64MB
             max_execution_time: Duration::seconds(30),
             module_cache_size: 10,
             enable_fuel: true,
+            max_device_memory_bytes: 256 * 1024 * 1024, // 256MB
         };
 
         Self::new(default_config)
     }
 
-    pub async fn execute(&self, job: &Job) -> Result<ExecutionResult, SandboxError> {
+    /// Looks modules up in `dir` instead of `modules/`.
+    pub fn with_modules_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.modules_dir = dir.into();
+        self
+    }
+
+    /// `{modules_dir}/{module_id}.{extension}`
+    pub fn module_file(&self, module_id: &str, extension: &str) -> PathBuf {
+        self.modules_dir
+            .join(format!("{}.{}", module_id, extension))
+    }
+
+    pub fn max_execution_time(&self) -> Duration {
+        self.config.max_execution_time
+    }
+
+    /// Compiles and runs a trivial module on the job engine.
+    pub fn self_test(&self) -> Result<(), SandboxError> {
+        const PROBE: &str = r#"(module (func (export "run") (result i32) i32.const 1))"#;
+
+        let module = Module::new(&self.engine, PROBE)
+            .map_err(|e| SandboxError::ModeleLoadFailed(e.to_string()))?;
+        let mut store = Store::new(&self.engine, ());
+        store.set_epoch_deadline(PROBE_TICKS);
+        if self.config.enable_fuel {
+            store
+                .set_fuel(10_000)
+                .map_err(|e| SandboxError::ExecutionFailed(format!("Fuel setup failed: {}", e)))?;
+        }
+
+        let run = Instance::new(&mut store, &module, &[])
+            .and_then(|instance| instance.get_typed_func::<(), i32>(&mut store, "run"))
+            .map_err(|e| SandboxError::ExecutionFailed(e.to_string()))?;
+        match run.call(&mut store, ()) {
+            Ok(1) => Ok(()),
+            Ok(value) => Err(SandboxError::ExecutionFailed(format!(
+                "Probe returned {} instead of 1",
+                value
+            ))),
+            Err(e) => Err(SandboxError::TrapOccured(e.to_string())),
+        }
+    }
+
+    pub async fn execute(
+        &self,
+        job: &Job,
+        capabilities: &[ResolvedCapability],
+    ) -> Result<ExecutionResult, SandboxError> {
         let start_time = time::OffsetDateTime::now_utc();
 
-        let module = self.load_module(&job.module_id)?;
+        let guest = self.load_guest(&job.module_id)?;
 
         let context = SandboxContext {
             job_id: job.job_id,
             tenant_id: job.tenant_id.clone(),
             max_memory: self.config.max_memory_bytes,
+            memory_used: 0,
+            memory_exceeded: false,
+            log_bytes_remaining: capabilities
+                .iter()
+                .find_map(|c| match &c.params {
+                    CapabilityParams::Logging(params) => Some(params.max_bytes),
+                    _ => None,
+                })
+                .unwrap_or(0),
+            egress: capabilities.iter().find_map(|c| match &c.params {
+                CapabilityParams::NetworkEgress(params) => Some(params.clone()),
+                _ => None,
+            }),
+            egress_log: Vec::new(),
+            logs: Vec::new(),
+            wasi: WasiSetup::for_job(job, capabilities)?,
+            device: VirtualDevice::new(self.device_memory_limit(job)),
+            tensors: TensorIo::new(job.tensors.clone()),
         };
 
         let mut store = Store::new(&self.engine, context);
+        store.limiter(|ctx| ctx);
+
+        // Checked on every epoch tick, so a cancelled or overrunning guest
+        // stops in wasm code. Host calls it is in finish first.
+        let cancel = job.cancel.clone();
+        let deadline = std::time::Instant::now()
+            + std::time::Duration::from_micros(
+                self.config.max_execution_time.whole_microseconds() as u64
+            );
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(move |_| {
+            if cancel.is_cancelled() || std::time::Instant::now() >= deadline {
+                Ok(UpdateDeadline::Interrupt)
+            } else {
+                Ok(UpdateDeadline::Continue(1))
+            }
+        });
 
         if self.config.enable_fuel {
             store
@@ -104,57 +349,171 @@ impl SandboxExecutor {
                 .map_err(|e| SandboxError::ExecutionFailed(format!("Fuel setup failed: {}", e)))?;
         }
 
-        let linker = self.build_linker(&job.capabilities)?;
-
-        let instance = linker
-            .instantiate(&mut store, &module)
-            .map_err(|e| SandboxError::ExecutionFailed(e.to_string()))?;
-
-        // Simple function that takes no input, returns a result
-        let run_func = instance
-            .get_typed_func::<(), i32>(&mut store, "run")
-            .map_err(|e| {
-                SandboxError::ExecutionFailed(format!("Function 'run' not found {}", e))
-            })?;
-
-        // Execute in blocking thread with timeout
-        let timeout_duration = self.config.max_execution_time;
+        let entry = match guest {
+            Guest::Core(module) => self.prepare_core(&mut store, &module, capabilities)?,
+            Guest::Component(component) => {
+                component::check_imports(&self.engine, &component, capabilities)?;
+                let linker = component::build_linker(&self.engine, capabilities)?;
+                let bindings = component::Guest::instantiate(&mut store, &component, &linker)
+                    .map_err(|e| SandboxError::ExecutionFailed(e.to_string()))?;
+                Entry::Component(bindings, component::job_input(job))
+            }
+        };
 
+        // Execute in blocking thread; the epoch callback enforces the timeout.
+        // The task isn't abandoned early, so the job's slot stays reserved for
+        // as long as the guest runs.
+        let cancel = job.cancel.clone();
         let execution_handle = tokio::task::spawn_blocking(move || {
-            let result = run_func
-                .call(&mut store, ())
-                .map_err(|e| SandboxError::ExecutionFailed(e.to_string()))?;
+            let called = match entry {
+                Entry::Run(run_func) => run_func.call(&mut store, ()).map(Outcome::Returned),
+                Entry::Command(start) => match start.call(&mut store, ()) {
+                    Ok(()) => Ok(Outcome::Exited(0)),
+                    Err(e) => match e.downcast_ref::<wasmtime_wasi::I32Exit>() {
+                        Some(exit) => Ok(Outcome::Exited(exit.0)),
+                        None => Err(e),
+                    },
+                },
+                Entry::Component(bindings, input) => bindings
+                    .call_run(&mut store, &input)
+                    .map(|r| Outcome::Output(r.map(|output| output.data))),
+            };
+
+            let outcome = called.map_err(|e| {
+                if e.downcast_ref::<Trap>() == Some(&Trap::Interrupt) {
+                    if cancel.is_cancelled() {
+                        SandboxError::Cancelled
+                    } else {
+                        SandboxError::Timeout
+                    }
+                } else if store.data().memory_exceeded {
+                    SandboxError::OutOfMemory
+                } else if let Some(trap) = e.downcast_ref::<wasmtime::Trap>() {
+                    SandboxError::TrapOccured(trap.to_string())
+                } else {
+                    SandboxError::ExecutionFailed(e.to_string())
+                }
+            })?;
 
-            Ok::<_, SandboxError>(result)
+            let mut ctx = store.into_data();
+
+            // Dropping the WASI setup also removes the scratch directory
+            let (stdout, stderr) = match ctx.wasi.take() {
+                Some(wasi) => (wasi.stdout.contents(), wasi.stderr.contents()),
+                None => Default::default(),
+            };
+
+            let output = match outcome {
+                Outcome::Returned(result) => {
+                    ctx.logs.extend(prefixed_lines("stdout", &stdout));
+                    result.to_string().into_bytes()
+                }
+                Outcome::Exited(0) => stdout.to_vec(),
+                Outcome::Exited(code) => {
+                    return Err(SandboxError::ExecutionFailed(format!(
+                        "Guest exited with code {}",
+                        code
+                    )));
+                }
+                Outcome::Output(Ok(data)) => {
+                    ctx.logs.extend(prefixed_lines("stdout", &stdout));
+                    data
+                }
+                Outcome::Output(Err(message)) => {
+                    return Err(SandboxError::ExecutionFailed(format!(
+                        "Guest returned an error: {}",
+                        message
+                    )));
+                }
+            };
+            ctx.logs.extend(prefixed_lines("stderr", &stderr));
+
+            Ok::<_, SandboxError>((output, ctx))
         });
 
-        let result = tokio::time::timeout(
-            std::time::Duration::from_micros(timeout_duration.whole_microseconds() as u64),
-            execution_handle,
-        )
-        .await
-        .map_err(|_| SandboxError::Timeout)?
-        .map_err(|e| SandboxError::ExecutionFailed(format!("Task join failed: {}", e)))??;
+        let (output, ctx) = execution_handle
+            .await
+            .map_err(|e| SandboxError::ExecutionFailed(format!("Task join failed: {}", e)))??;
 
         let end_time = time::OffsetDateTime::now_utc();
         let execution_time = end_time - start_time;
 
         Ok(ExecutionResult {
-            output: result.to_string().into_bytes(),
+            output,
             execution_time,
-            memory_used: 0,
+            memory_used: ctx.memory_used,
+            device_memory_peak: ctx.device.memory_peak(),
+            egress_log: ctx.egress_log,
+            logs: ctx.logs,
+            tensors: ctx.tensors.outputs,
         })
     }
 
-    fn load_module(&self, module_id: &str) -> Result<Module, SandboxError> {
+    /// Device memory is charged against the job's slot: the memory of its
+    /// partition(s), capped by what the emulator may take from the host.
+    fn device_memory_limit(&self, job: &Job) -> usize {
+        let slot_bytes = job
+            .resources
+            .times(job.gang_size)
+            .map_or(usize::MAX, |total| {
+                (total.memory_gb as usize).saturating_mul(1024 * 1024 * 1024)
+            });
+        slot_bytes.min(self.config.max_device_memory_bytes)
+    }
+
+    /// Links and instantiates a core module and picks its entry point.
+    fn prepare_core(
+        &self,
+        store: &mut Store<SandboxContext>,
+        module: &Module,
+        capabilities: &[ResolvedCapability],
+    ) -> Result<Entry, SandboxError> {
+        let linker = self.build_linker(capabilities)?;
+
+        // Anything the module imports that the linker doesn't provide was not granted
+        for import in module.imports() {
+            if linker
+                .get(&mut *store, import.module(), import.name())
+                .is_none()
+            {
+                return Err(SandboxError::CapabilityViolation(format!(
+                    "Module imports {}::{} which is not granted",
+                    import.module(),
+                    import.name()
+                )));
+            }
+        }
+
+        let instance = linker
+            .instantiate(&mut *store, module)
+            .map_err(|e| SandboxError::ExecutionFailed(e.to_string()))?;
+        tensor::stage_inputs(store, &instance)?;
+
+        // WASI command modules export `_start` and report through stdout and
+        // their exit code; everything else exports `run() -> i32`
+        match instance.get_typed_func::<(), ()>(&mut *store, "_start") {
+            Ok(start) if store.data().wasi.is_some() => Ok(Entry::Command(start)),
+            _ => Ok(Entry::Run(
+                instance
+                    .get_typed_func::<(), i32>(&mut *store, "run")
+                    .map_err(|e| {
+                        SandboxError::ExecutionFailed(format!("Function 'run' not found {}", e))
+                    })?,
+            )),
+        }
+    }
+
+    /// Loads `modules/{id}.wasm`, which may hold a core module or a component
+    /// targeting the `gpu-sandbox:guest` world.
+    fn load_guest(&self, module_id: &str) -> Result<Guest, SandboxError> {
         // Construct path to WASM module
-        let module_path = format!("modules/{}.wasm", module_id);
+        let module_path = self.module_file(module_id, "wasm");
 
         // Check if file exists
-        if !std::path::Path::new(&module_path).exists() {
+        if !module_path.exists() {
             return Err(SandboxError::ModuleNotFound(format!(
                 "Module file not found: {}",
-                module_path
+                module_path.display()
             )));
         }
 
@@ -162,100 +521,240 @@ impl SandboxExecutor {
         let wasm_bytes = std::fs::read(&module_path).map_err(|e| {
             SandboxError::ModeleLoadFailed(format!(
                 "Failed to read module file {}: {}",
-                module_path, e
+                module_path.display(),
+                e
             ))
         })?;
 
-        // Compile the module
-        Module::from_binary(&self.engine, &wasm_bytes).map_err(|e| {
+        let compile_failed = |e: wasmtime::Error| {
             SandboxError::ModeleLoadFailed(format!("Failed to compile module {}: {}", module_id, e))
-        })
+        };
+
+        // Compile the module or component
+        if component::is_component(&wasm_bytes) {
+            Component::from_binary(&self.engine, &wasm_bytes)
+                .map(Guest::Component)
+                .map_err(compile_failed)
+        } else {
+            Module::from_binary(&self.engine, &wasm_bytes)
+                .map(Guest::Core)
+                .map_err(compile_failed)
+        }
     }
 
     fn build_linker(
         &self,
-        capabilities: &[String],
+        capabilities: &[ResolvedCapability],
     ) -> Result<Linker<SandboxContext>, SandboxError> {
         let mut linker = Linker::new(&self.engine);
 
-        // Capability: "gpu.compute" - allows GPU computation
-        if capabilities.contains(&"gpu.compute".to_string()) {
-            linker
-                .func_wrap(
-                    "env",
-                    "gpu_compute",
-                    |caller: wasmtime::Caller<'_, SandboxContext>, operation: i32| -> i32 {
-                        let ctx = caller.data();
-                        println!(
-                            "GPU compute called by tenant: {} for job: {}",
-                            ctx.tenant_id, ctx.job_id
-                        );
-
-                        // Mock GPU computation
-                        // Real implementation would submit to GPU queue
-                        operation * 2
-                    },
-                )
-                .map_err(|e| {
-                    SandboxError::ExecutionFailed(format!("Failed to link gpu_compute: {}", e))
-                })?;
-        }
-
-        // Capability: "logging" - allows the WASM module to log messages
-        if capabilities.contains(&"logging".to_string()) {
-            linker
-                .func_wrap(
-                    "env",
-                    "log_message",
-                    |mut caller: wasmtime::Caller<'_, SandboxContext>,
-                     msg_ptr: i32,
-                     msg_len: i32| {
-                        // Read message from WASM memory
-                        if let Some(memory) =
-                            caller.get_export("memory").and_then(|e| e.into_memory())
-                        {
-                            let mut buffer = vec![0u8; msg_len as usize];
-                            if memory
-                                .read(&mut caller, msg_ptr as usize, &mut buffer)
-                                .is_ok()
-                            {
-                                let msg = String::from_utf8_lossy(&buffer);
-                                let ctx = caller.data();
-                                println!("[WASM Log | Job {}] {}", ctx.job_id, msg);
-                            }
-                        }
-                    },
-                )
-                .map_err(|e| {
-                    SandboxError::ExecutionFailed(format!("Failed to link log_message: {}", e))
-                })?;
-        }
-
-        // Capability: "network.egress" - allows outbound network calls
-        if capabilities.contains(&"network.egress".to_string()) {
-            linker
-                .func_wrap(
-                    "env",
-                    "http_post",
-                    |caller: wasmtime::Caller<'_, SandboxContext>,
-                     _url_ptr: i32,
-                     _data_ptr: i32|
-                     -> i32 {
-                        let ctx = caller.data();
-                        println!(
-                            "Network egress requested by tenant: {} for job: {}",
-                            ctx.tenant_id, ctx.job_id
-                        );
-
-                        // Mock network call - real implementation would validate and make HTTP request
-                        200 // Mock HTTP status
-                    },
-                )
-                .map_err(|e| {
-                    SandboxError::ExecutionFailed(format!("Failed to link http_post: {}", e))
-                })?;
+        // Tensors are the job's own data, so every module may read them
+        tensor::add_to_linker(&mut linker)?;
+
+        for capability in capabilities {
+            match &capability.params {
+                // Capability: "gpu.compute" - allows GPU computation
+                CapabilityParams::GpuCompute => {
+                    linker
+                        .func_wrap(
+                            "env",
+                            "gpu_compute",
+                            |caller: wasmtime::Caller<'_, SandboxContext>, operation: i32| -> i32 {
+                                caller.data().gpu_compute(operation)
+                            },
+                        )
+                        .map_err(|e| {
+                            SandboxError::ExecutionFailed(format!(
+                                "Failed to link gpu_compute: {}",
+                                e
+                            ))
+                        })?;
+
+                    // Buffer-based device API, emulated on the CPU
+                    vgpu::add_to_linker(&mut linker)?;
+                }
+
+                // Capability: "logging" - allows the WASM module to log messages,
+                // up to `max_bytes` per job
+                CapabilityParams::Logging(_) => {
+                    linker
+                        .func_wrap(
+                            "env",
+                            "log_message",
+                            |mut caller: wasmtime::Caller<'_, SandboxContext>,
+                             msg_ptr: i32,
+                             msg_len: i32| {
+                                let msg_len =
+                                    (msg_len as usize).min(caller.data().log_bytes_remaining);
+                                if msg_len == 0 {
+                                    return;
+                                }
+
+                                // Read message from WASM memory
+                                if let Some(memory) =
+                                    caller.get_export("memory").and_then(|e| e.into_memory())
+                                {
+                                    let mut buffer = vec![0u8; msg_len];
+                                    if memory
+                                        .read(&mut caller, msg_ptr as usize, &mut buffer)
+                                        .is_ok()
+                                    {
+                                        let msg = String::from_utf8_lossy(&buffer);
+                                        caller.data_mut().write_log(&msg);
+                                    }
+                                }
+                            },
+                        )
+                        .map_err(|e| {
+                            SandboxError::ExecutionFailed(format!(
+                                "Failed to link log_message: {}",
+                                e
+                            ))
+                        })?;
+                }
+
+                // Capability: "network.egress" - allows outbound HTTP POSTs to
+                // allowlisted hosts. The response is written to the guest as a
+                // little-endian u32 body length followed by the (truncated) body.
+                CapabilityParams::NetworkEgress(_) => {
+                    linker
+                        .func_wrap(
+                            "env",
+                            "http_post",
+                            |mut caller: wasmtime::Caller<'_, SandboxContext>,
+                             url_ptr: i32,
+                             url_len: i32,
+                             headers_ptr: i32,
+                             headers_len: i32,
+                             body_ptr: i32,
+                             body_len: i32,
+                             resp_ptr: i32,
+                             resp_cap: i32|
+                             -> i32 {
+                                let Some(memory) =
+                                    caller.get_export("memory").and_then(|e| e.into_memory())
+                                else {
+                                    return -1;
+                                };
+
+                                // The guest picks the lengths, so check them against
+                                // its memory before allocating anything for them
+                                let read = |caller: &mut wasmtime::Caller<'_, SandboxContext>,
+                                            ptr: i32,
+                                            len: i32| {
+                                    let ptr = ptr as u32 as usize;
+                                    let len = usize::try_from(len).ok()?;
+                                    if ptr.checked_add(len)? > memory.data_size(&*caller) {
+                                        return None;
+                                    }
+                                    let mut buffer = vec![0u8; len];
+                                    memory.read(caller, ptr, &mut buffer).ok().map(|_| buffer)
+                                };
+
+                                let (Some(url), Some(headers)) = (
+                                    read(&mut caller, url_ptr, url_len),
+                                    read(&mut caller, headers_ptr, headers_len),
+                                ) else {
+                                    return -1;
+                                };
+                                let url = String::from_utf8_lossy(&url).into_owned();
+
+                                let max_body_bytes = caller
+                                    .data()
+                                    .egress
+                                    .as_ref()
+                                    .map_or(0, |p| p.max_body_bytes);
+                                if body_len as u32 as usize > max_body_bytes {
+                                    return caller
+                                        .data_mut()
+                                        .egress_too_large(url, body_len as u32 as usize);
+                                }
+                                let Some(body) = read(&mut caller, body_ptr, body_len) else {
+                                    return -1;
+                                };
+                                let headers =
+                                    egress::parse_headers(&String::from_utf8_lossy(&headers));
+
+                                let outcome = caller.data_mut().egress_post(url, &headers, &body);
+                                let (code, response_body) = match &outcome {
+                                    Ok(response) => {
+                                        (i32::from(response.status), &response.body[..])
+                                    }
+                                    Err(e) => (e.guest_code(), &[][..]),
+                                };
+
+                                if resp_cap >= 4 {
+                                    let resp_ptr = resp_ptr as u32 as usize;
+                                    let room = resp_cap as u32 as usize - 4;
+                                    let body = &response_body[..response_body.len().min(room)];
+                                    let len = (body.len() as u32).to_le_bytes();
+                                    if memory.write(&mut caller, resp_ptr, &len).is_err()
+                                        || memory.write(&mut caller, resp_ptr + 4, body).is_err()
+                                    {
+                                        return -1;
+                                    }
+                                }
+
+                                code
+                            },
+                        )
+                        .map_err(|e| {
+                            SandboxError::ExecutionFailed(format!(
+                                "Failed to link http_post: {}",
+                                e
+                            ))
+                        })?;
+                }
+
+                // Capability: "wasi" - links wasi_snapshot_preview1 with captured
+                // stdio. "wasi.fs", "wasi.clocks" and "wasi.random" only widen
+                // what that context exposes, so they link nothing themselves.
+                CapabilityParams::Wasi(_) => wasi::add_to_linker(&mut linker, capabilities)?,
+                CapabilityParams::WasiFs
+                | CapabilityParams::WasiClocks
+                | CapabilityParams::WasiRandom => {}
+            }
         }
 
         Ok(linker)
     }
 }
+
+/// Checks that `bytes` compile as a core module or a component, and returns
+/// whether it is a component. Used on upload.
+pub fn check_module(bytes: &[u8]) -> Result<bool, SandboxError> {
+    let engine = Engine::default();
+    let invalid = |e: wasmtime::Error| SandboxError::ModeleLoadFailed(e.to_string());
+
+    if component::is_component(bytes) {
+        Component::from_binary(&engine, bytes).map_err(invalid)?;
+        Ok(true)
+    } else {
+        Module::validate(&engine, bytes).map_err(invalid)?;
+        Ok(false)
+    }
+}
+
+enum Guest {
+    Core(Module),
+    Component(Component),
+}
+
+enum Entry {
+    Run(TypedFunc<(), i32>),
+    Command(TypedFunc<(), ()>),
+    Component(component::Guest, JobInput),
+}
+
+enum Outcome {
+    Returned(i32),
+    Exited(i32),
+    Output(Result<Vec<u8>, String>),
+}
+
+fn prefixed_lines(stream: &str, bytes: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(bytes)
+        .lines()
+        .map(|line| format!("[{}] {}", stream, line))
+        .collect()
+}
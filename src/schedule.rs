@@ -0,0 +1,587 @@
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime, Time};
+use uuid::Uuid;
+
+use crate::api;
+use crate::dispatcher;
+use crate::domain::{JobStatus, SubmitJobRequest};
+use crate::state::AppState;
+
+/// How often the scheduler looks for due schedules.
+const TICK: Duration = Duration::from_millis(250);
+
+/// Runs kept in a schedule's history, newest last.
+pub const MAX_HISTORY: usize = 100;
+
+/// How far ahead a cron expression is searched for its next time.
+const CRON_HORIZON_DAYS: i64 = 5 * 366;
+
+/// Longest `every_secs`: the same five years.
+pub const MAX_EVERY_SECS: u64 = CRON_HORIZON_DAYS as u64 * 24 * 60 * 60;
+
+#[derive(Deserialize)]
+pub struct SubmitScheduleRequest {
+    /// Five fields, in UTC: minute hour day-of-month month day-of-week
+    #[serde(default)]
+    pub cron: Option<String>,
+    /// Fixed time between runs; exactly one of this and `cron` is required
+    #[serde(default)]
+    pub every_secs: Option<u64>,
+    #[serde(default)]
+    pub overlap: OverlapPolicy,
+    /// Submitted like a `POST /jobs` body at every run
+    pub job: SubmitJobRequest,
+}
+
+/// What a run does while the job of the previous run is queued or running.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapPolicy {
+    #[default]
+    Skip,
+    Queue,
+    CancelPrevious,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Schedule {
+    pub schedule_id: Uuid,
+    pub tenant_id: String,
+    pub module_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub every_secs: Option<u64>,
+    pub overlap: OverlapPolicy,
+    pub paused: bool,
+    pub created_at: OffsetDateTime,
+    /// None while paused
+    pub next_run: Option<OffsetDateTime>,
+    pub runs: VecDeque<ScheduleRun>,
+    #[serde(skip)]
+    trigger: Trigger,
+    #[serde(skip)]
+    template: SubmitJobRequest,
+    // Job of the last submitted run
+    #[serde(skip)]
+    last_job: Option<Uuid>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ScheduleRun {
+    pub scheduled_for: OffsetDateTime,
+    pub outcome: RunOutcome,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    Submitted {
+        job_id: Uuid,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cancelled_previous: Option<Uuid>,
+    },
+    /// The previous run's job was still queued or running
+    Skipped { previous: Uuid },
+    /// Turned down by the same checks as `POST /jobs`
+    Rejected { error: String, message: String },
+}
+
+#[derive(Serialize)]
+pub struct ScheduleListResponse {
+    pub schedules: Vec<Schedule>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("Give exactly one of cron and every_secs")]
+    Trigger,
+    #[error("every_secs must be between 1 and {}", MAX_EVERY_SECS)]
+    Interval,
+    #[error("Invalid cron expression {0:?}: {1}")]
+    Cron(String, String),
+    #[error("Cron expression {0:?} never fires")]
+    NeverFires(String),
+}
+
+#[derive(Clone)]
+enum Trigger {
+    Cron(Cron),
+    Every(time::Duration),
+}
+
+impl Trigger {
+    /// When to run next after the run due at `last`. Runs missed while the
+    /// controller was busy or the schedule paused are dropped, not caught up.
+    fn next(&self, last: OffsetDateTime, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        match self {
+            Trigger::Cron(cron) => cron.next_after(last.max(now)),
+            Trigger::Every(every) => {
+                let next = last.checked_add(*every)?;
+                if next > now {
+                    Some(next)
+                } else {
+                    now.checked_add(*every)
+                }
+            }
+        }
+    }
+}
+
+impl Schedule {
+    /// Checks the trigger of `req` and builds an active schedule. The job
+    /// template is checked by the caller.
+    pub fn new(req: SubmitScheduleRequest) -> Result<Self, ScheduleError> {
+        let trigger = match (&req.cron, req.every_secs) {
+            (Some(expr), None) => {
+                let cron = expr
+                    .parse()
+                    .map_err(|e: CronError| ScheduleError::Cron(expr.clone(), e.to_string()))?;
+                Trigger::Cron(cron)
+            }
+            (None, Some(secs)) if secs == 0 || secs > MAX_EVERY_SECS => {
+                return Err(ScheduleError::Interval);
+            }
+            (None, Some(secs)) => Trigger::Every(time::Duration::seconds(secs as i64)),
+            _ => return Err(ScheduleError::Trigger),
+        };
+
+        let now = OffsetDateTime::now_utc();
+        let Some(next_run) = trigger.next(now, now) else {
+            return Err(ScheduleError::NeverFires(req.cron.unwrap_or_default()));
+        };
+
+        Ok(Schedule {
+            schedule_id: Uuid::new_v4(),
+            tenant_id: req.job.tenant_id.clone(),
+            module_id: req.job.module_id.clone(),
+            cron: req.cron,
+            every_secs: req.every_secs,
+            overlap: req.overlap,
+            paused: false,
+            created_at: now,
+            next_run: Some(next_run),
+            runs: VecDeque::new(),
+            trigger,
+            template: req.job,
+            last_job: None,
+        })
+    }
+
+    pub fn template(&self) -> &SubmitJobRequest {
+        &self.template
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.next_run = None;
+    }
+
+    /// Picks up from now; runs that fell in the pause don't happen.
+    pub fn resume(&mut self) {
+        if self.paused {
+            let now = OffsetDateTime::now_utc();
+            self.paused = false;
+            self.next_run = self.trigger.next(now, now);
+        }
+    }
+
+    /// Takes the run due by `now`, if there is one, and moves `next_run` past
+    /// it. A pause, resume or delete after the claim applies from the next run.
+    fn claim(&mut self, now: OffsetDateTime) -> Option<Due> {
+        let scheduled_for = self.next_run.filter(|at| *at <= now)?;
+        self.next_run = self.trigger.next(scheduled_for, now);
+        Some(Due {
+            scheduled_for,
+            overlap: self.overlap,
+            previous: self.last_job,
+            template: self.template.clone(),
+        })
+    }
+
+    fn record(&mut self, run: ScheduleRun) {
+        if let RunOutcome::Submitted { job_id, .. } = run.outcome {
+            self.last_job = Some(job_id);
+        }
+        self.runs.push_back(run);
+        if self.runs.len() > MAX_HISTORY {
+            self.runs.pop_front();
+        }
+    }
+}
+
+/// A claimed run, taken out of the schedule table so the submission doesn't
+/// hold its lock.
+struct Due {
+    scheduled_for: OffsetDateTime,
+    overlap: OverlapPolicy,
+    previous: Option<Uuid>,
+    template: SubmitJobRequest,
+}
+
+/// Submits the template of every due schedule, until shutdown.
+pub async fn run_scheduler(state: AppState) {
+    let mut ticker = tokio::time::interval(TICK);
+    loop {
+        ticker.tick().await;
+        if state.is_shutting_down() {
+            break;
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let due: Vec<Uuid> = state
+            .schedules
+            .read()
+            .await
+            .values()
+            .filter(|schedule| schedule.next_run.is_some_and(|at| at <= now))
+            .map(|schedule| schedule.schedule_id)
+            .collect();
+
+        // Claimed one at a time, so a schedule paused or deleted while the
+        // ones before it are submitted doesn't run
+        for schedule_id in due {
+            let claimed = state
+                .schedules
+                .write()
+                .await
+                .get_mut(&schedule_id)
+                .and_then(|schedule| schedule.claim(now));
+            let Some(due) = claimed else {
+                continue;
+            };
+            let scheduled_for = due.scheduled_for;
+            let outcome = run(&state, due).await;
+            match state.schedules.write().await.get_mut(&schedule_id) {
+                Some(schedule) => schedule.record(ScheduleRun {
+                    scheduled_for,
+                    outcome,
+                }),
+                // Deleted during the submission; its job goes with it
+                None => {
+                    if let RunOutcome::Submitted { job_id, .. } = outcome {
+                        dispatcher::cancel(&state, job_id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn run(state: &AppState, due: Due) -> RunOutcome {
+    let active = due.previous.filter(|job_id| {
+        state
+            .jobs
+            .read(*job_id, |job| {
+                matches!(job.status, JobStatus::Queued | JobStatus::Running)
+            })
+            .unwrap_or(false)
+    });
+
+    let cancelled_previous = match (due.overlap, active) {
+        (OverlapPolicy::Skip, Some(previous)) => return RunOutcome::Skipped { previous },
+        (OverlapPolicy::CancelPrevious, Some(previous)) => {
+            matches!(dispatcher::cancel(state, previous), Some(Ok(()))).then_some(previous)
+        }
+        _ => None,
+    };
+
+    match api::submit(state, due.template).await {
+        Ok(job_id) => RunOutcome::Submitted {
+            job_id,
+            cancelled_previous,
+        },
+        Err((_, error)) => RunOutcome::Rejected {
+            error: error.error,
+            message: error.message,
+        },
+    }
+}
+
+/// A parsed five-field cron expression. Fields take `*`, numbers, ranges
+/// `a-b`, steps `*/n` or `a-b/n`, and comma-separated lists of those.
+/// Day of week runs from 0 (Sunday) to 6; 7 is Sunday too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // With both day fields restricted, either may match, as in cron(8)
+    any_day: bool,
+    any_weekday: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CronError {
+    #[error("expected 5 fields, got {0}")]
+    FieldCount(usize),
+    #[error("invalid field {0:?}")]
+    Field(String),
+    #[error("{value} is out of range {min}-{max}")]
+    OutOfRange { value: u32, min: u32, max: u32 },
+}
+
+impl FromStr for Cron {
+    type Err = CronError;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(CronError::FieldCount(fields.len()));
+        };
+
+        let mut weekday_bits = field(weekdays, 0, 7)?;
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits = (weekday_bits & !(1 << 7)) | 1;
+        }
+
+        Ok(Cron {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+        })
+    }
+}
+
+/// Parses one field into a bit set of the values it matches.
+fn field(spec: &str, min: u32, max: u32) -> Result<u64, CronError> {
+    let invalid = || CronError::Field(spec.to_string());
+    let number = |s: &str| -> Result<u32, CronError> {
+        let value: u32 = s.parse().map_err(|_| invalid())?;
+        if value < min || value > max {
+            return Err(CronError::OutOfRange { value, min, max });
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0;
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (number(from)?, number(to)?),
+            // `a/n` runs from a to the end of the range
+            None if part.contains('/') => (number(range)?, max),
+            None => {
+                let value = number(range)?;
+                (value, value)
+            }
+        };
+        if from > to {
+            return Err(invalid());
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl Cron {
+    fn day_matches(&self, date: Date) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().number_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The first matching minute after `after`, if there is one in the next
+    /// five years.
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let after = after.replace_second(0).ok()?.replace_nanosecond(0).ok()?;
+        let mut at = after + time::Duration::MINUTE;
+        let horizon = at + time::Duration::days(CRON_HORIZON_DAYS);
+
+        while at < horizon {
+            if self.months & (1 << u8::from(at.month())) == 0 {
+                let (year, month) = match at.month() {
+                    time::Month::December => (at.year() + 1, time::Month::January),
+                    month => (at.year(), month.next()),
+                };
+                let first = Date::from_calendar_date(year, month, 1).ok()?;
+                at = at.replace_date(first).replace_time(Time::MIDNIGHT);
+            } else if !self.day_matches(at.date()) {
+                at = at.replace_time(Time::MIDNIGHT) + time::Duration::DAY;
+            } else if self.hours & (1 << at.hour()) == 0 {
+                at = at.replace_minute(0).ok()? + time::Duration::HOUR;
+            } else if self.minutes & (1 << at.minute()) == 0 {
+                at += time::Duration::MINUTE;
+            } else {
+                return Some(at);
+            }
+        }
+        None
+    }
+}
+
+/// Schedules of `tenant_id`, or all of them.
+pub fn list(schedules: &HashMap<Uuid, Schedule>, tenant_id: Option<&str>) -> Vec<Schedule> {
+    let mut listed: Vec<Schedule> = schedules
+        .values()
+        .filter(|s| tenant_id.is_none_or(|t| t == s.tenant_id))
+        .cloned()
+        .collect();
+    listed.sort_by_key(|s| s.created_at);
+    listed
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Month;
+
+    use super::*;
+
+    fn utc(year: i32, month: Month, day: u8, hour: u8, minute: u8) -> OffsetDateTime {
+        Date::from_calendar_date(year, month, day)
+            .unwrap()
+            .with_hms(hour, minute, 0)
+            .unwrap()
+            .assume_utc()
+    }
+
+    fn next(expr: &str, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        expr.parse::<Cron>().unwrap().next_after(after)
+    }
+
+    #[test]
+    fn cron_finds_the_next_matching_minute() {
+        let at = utc(2026, Month::March, 14, 10, 17) + time::Duration::seconds(30);
+
+        assert_eq!(
+            next("* * * * *", at),
+            Some(utc(2026, Month::March, 14, 10, 18))
+        );
+        assert_eq!(
+            next("*/15 * * * *", at),
+            Some(utc(2026, Month::March, 14, 10, 30))
+        );
+        assert_eq!(
+            next("0 9-17/4 * * *", at),
+            Some(utc(2026, Month::March, 14, 13, 0))
+        );
+        assert_eq!(
+            next("30 2 1 * *", at),
+            Some(utc(2026, Month::April, 1, 2, 30))
+        );
+        assert_eq!(
+            next("0 0 * 1,6 *", at),
+            Some(utc(2026, Month::June, 1, 0, 0))
+        );
+        // 2026-03-14 is a Saturday
+        assert_eq!(
+            next("0 8 * * 1-5", at),
+            Some(utc(2026, Month::March, 16, 8, 0))
+        );
+        assert_eq!(
+            next("0 8 * * 7", at),
+            Some(utc(2026, Month::March, 15, 8, 0))
+        );
+        // Either day field may match once both are restricted
+        assert_eq!(
+            next("0 0 20 * 0", at),
+            Some(utc(2026, Month::March, 15, 0, 0))
+        );
+        assert_eq!(
+            next("0 0 29 2 *", at),
+            Some(utc(2028, Month::February, 29, 0, 0))
+        );
+        assert_eq!(next("0 0 31 2 *", at), None);
+    }
+
+    #[test]
+    fn cron_rejects_malformed_expressions() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(expr.parse::<Cron>().is_err(), "{}", expr);
+        }
+    }
+
+    fn every(secs: u64) -> Result<Schedule, ScheduleError> {
+        let job = serde_json::from_value(serde_json::json!({
+            "tenant_id": "tenant1",
+            "module_id": "simple-compute",
+            "payload": {},
+            "capabilities": [],
+        }))
+        .unwrap();
+        Schedule::new(SubmitScheduleRequest {
+            cron: None,
+            every_secs: Some(secs),
+            overlap: OverlapPolicy::Skip,
+            job,
+        })
+    }
+
+    #[test]
+    fn every_secs_is_bounded() {
+        for secs in [0, MAX_EVERY_SECS + 1, 1 << 63, u64::MAX] {
+            assert!(
+                matches!(every(secs), Err(ScheduleError::Interval)),
+                "{}",
+                secs
+            );
+        }
+
+        let schedule = every(MAX_EVERY_SECS).unwrap();
+        let wait = schedule.next_run.unwrap() - schedule.created_at;
+        assert_eq!(wait.whole_seconds() as u64, MAX_EVERY_SECS);
+    }
+
+    #[test]
+    fn a_claimed_run_is_taken_once_and_leaves_pause_and_resume_alone() {
+        let mut schedule = every(60).unwrap();
+        let due_at = schedule.next_run.unwrap();
+        assert!(schedule.claim(due_at - time::Duration::SECOND).is_none());
+
+        let due = schedule.claim(due_at).unwrap();
+        assert_eq!(due.scheduled_for, due_at);
+        assert_eq!(schedule.next_run, Some(due_at + time::Duration::MINUTE));
+        assert!(schedule.claim(due_at).is_none());
+
+        // Paused before the next run is due, then resumed before the claimed
+        // one is recorded
+        schedule.pause();
+        assert!(schedule.claim(due_at + time::Duration::HOUR).is_none());
+        schedule.resume();
+        let resumed = schedule.next_run;
+        schedule.record(ScheduleRun {
+            scheduled_for: due.scheduled_for,
+            outcome: RunOutcome::Skipped {
+                previous: Uuid::new_v4(),
+            },
+        });
+        assert_eq!(schedule.next_run, resumed);
+        assert_eq!(schedule.runs.len(), 1);
+    }
+
+    #[test]
+    fn intervals_past_the_end_of_time_never_fire() {
+        let trigger = Trigger::Every(time::Duration::seconds(MAX_EVERY_SECS as i64));
+        let last = Date::MAX.midnight().assume_utc();
+        assert_eq!(trigger.next(last, last), None);
+    }
+}
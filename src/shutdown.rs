@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::fs;
+
+use crate::domain::{Job, JobStatus};
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// How long running jobs may take to finish before they are cancelled
+    pub grace_secs: u64,
+    /// Where jobs still queued at shutdown are written
+    pub queue_file: String,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            grace_secs: 30,
+            queue_file: "queued_jobs.json".to_string(),
+        }
+    }
+}
+
+impl ShutdownConfig {
+    pub fn grace_period(&self) -> Duration {
+        Duration::from_secs(self.grace_secs)
+    }
+}
+
+/// Resolves on the first SIGINT or SIGTERM.
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Writes the jobs that never started to `path` so they can be resubmitted,
+/// and lists them on stdout. Nothing is written if the queue is empty.
+pub async fn persist_queued(state: &AppState, path: &str) -> std::io::Result<()> {
+    let mut queued: Vec<Job> = state
+        .jobs
+        .collect(|job| matches!(job.status, JobStatus::Queued).then(|| job.clone()));
+    if queued.is_empty() {
+        println!("Shutdown: no queued jobs");
+        return Ok(());
+    }
+    queued.sort_by_key(|job| job.submitted_at);
+
+    for job in &queued {
+        println!(
+            "Shutdown: job {} ({}, {}) was still queued",
+            job.job_id, job.tenant_id, job.module_id
+        );
+    }
+
+    let contents = serde_json::to_vec_pretty(&queued)?;
+    fs::write(path, contents).await?;
+    println!("Shutdown: wrote {} queued job(s) to {}", queued.len(), path);
+
+    Ok(())
+}
@@ -1,44 +1,119 @@
-use std::collections::{HashMap, VecDeque};
-use std::sync::Arc;
-use time::OffsetDateTime;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::RwLock;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::watch;
 use uuid::Uuid;
 
+use crate::backend::BackendRegistry;
+use crate::capability::CapabilityRegistry;
 use crate::config::Config;
+use crate::deadline::{DispatchPolicy, RuntimeEstimates};
 use crate::domain::Job;
 use crate::gpu_manager::GpuManager;
-use crate::tenant::Tenant;
-
-pub struct InnerState {
-    pub jobs: HashMap<Uuid, Job>,
-    pub queue: Sender<Job>,
-}
-
-impl InnerState {
-    fn new(sender: Sender<Job>) -> Self {
-        Self {
-            jobs: HashMap::new(),
-            queue: sender,
-        }
-    }
-}
+use crate::health::DispatcherHealth;
+use crate::job_store::JobStore;
+use crate::model_registry::ModelRegistry;
+use crate::quota::{QuotaDefaults, QuotaError, ScopeQuota, quota_chain};
+use crate::rate_limit::RateLimiter;
+use crate::schedule::Schedule;
+use crate::stats::QueueHistory;
+use crate::tenant::{Organization, Tenant};
+use crate::workflow::Workflow;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub inner: Arc<RwLock<InnerState>>,
-    pub gpu_manager: Arc<RwLock<GpuManager>>,
+    pub jobs: Arc<JobStore>,
+    // Hands accepted jobs to the dispatcher, which owns the pending queue
+    pub queue: Sender<Job>,
+    pub gpu_manager: Arc<Mutex<GpuManager>>,
     pub tenants: Arc<RwLock<HashMap<String, Tenant>>>,
-    pub tenant_usage: Arc<RwLock<HashMap<String, VecDeque<OffsetDateTime>>>>,
+    pub organizations: Arc<RwLock<HashMap<String, Organization>>>,
+    pub quota_defaults: Arc<QuotaDefaults>,
+    pub capabilities: Arc<CapabilityRegistry>,
+    pub backends: Arc<BackendRegistry>,
+    pub models: Arc<ModelRegistry>,
+    pub admin_token: Option<Arc<str>>,
+    pub modules_dir: Arc<Path>,
+    pub queue_history: Arc<Mutex<QueueHistory>>,
+    pub dispatcher_health: Arc<DispatcherHealth>,
+    pub max_queue_depth: usize,
+    pub max_batch_size: usize,
+    // true once a shutdown signal arrived
+    pub shutdown: Arc<watch::Sender<bool>>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub workflows: Arc<RwLock<HashMap<Uuid, Workflow>>>,
+    pub schedules: Arc<RwLock<HashMap<Uuid, Schedule>>>,
+    pub dispatch_policy: DispatchPolicy,
+    pub runtime_estimates: Arc<RuntimeEstimates>,
 }
 
 impl AppState {
-    pub fn new(sender: Sender<Job>, config: &Config, tenants: HashMap<String, Tenant>) -> Self {
+    pub fn new(
+        sender: Sender<Job>,
+        config: &Config,
+        tenants: HashMap<String, Tenant>,
+        organizations: HashMap<String, Organization>,
+        capabilities: CapabilityRegistry,
+        backends: BackendRegistry,
+        models: Arc<ModelRegistry>,
+    ) -> Self {
         Self {
-            inner: Arc::new(RwLock::new(InnerState::new(sender))),
-            gpu_manager: Arc::new(RwLock::new(GpuManager::new(config))),
+            jobs: Arc::new(JobStore::new(config.queue_length)),
+            queue: sender,
+            gpu_manager: Arc::new(Mutex::new(GpuManager::new(config))),
             tenants: Arc::new(RwLock::new(tenants)),
-            tenant_usage: Arc::new(RwLock::new(HashMap::new())),
+            organizations: Arc::new(RwLock::new(organizations)),
+            quota_defaults: Arc::new(config.quota_defaults.clone()),
+            capabilities: Arc::new(capabilities),
+            backends: Arc::new(backends),
+            models,
+            admin_token: config.admin_token.as_deref().map(Arc::from),
+            modules_dir: Arc::from(config.modules_dir.as_path()),
+            queue_history: Arc::new(Mutex::new(QueueHistory::default())),
+            dispatcher_health: Arc::new(DispatcherHealth::new(Duration::from_secs(
+                config.health.dispatcher_stall_secs.unwrap_or(10),
+            ))),
+            max_queue_depth: config.health.max_queue_depth.unwrap_or(config.queue_length),
+            max_batch_size: config.max_batch_size,
+            shutdown: Arc::new(watch::Sender::new(false)),
+            rate_limiter: Arc::new(RateLimiter::default()),
+            workflows: Arc::new(RwLock::new(HashMap::new())),
+            schedules: Arc::new(RwLock::new(HashMap::new())),
+            dispatch_policy: config.dispatch.policy,
+            runtime_estimates: Arc::new(RuntimeEstimates::new(Duration::from_secs(
+                config.dispatch.default_runtime_secs,
+            ))),
         }
     }
+
+    /// Stops new submissions and tells the dispatcher to drain.
+    pub fn begin_shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    pub async fn quota_chain(
+        &self,
+        tenant: &Tenant,
+        project_id: Option<&str>,
+    ) -> Result<Vec<ScopeQuota>, QuotaError> {
+        let org = match &tenant.org_id {
+            Some(org_id) => {
+                let organizations = self.organizations.read().await;
+                let Some(org) = organizations.get(org_id).cloned() else {
+                    return Err(QuotaError::UnknownOrganization(org_id.clone()));
+                };
+                Some(org)
+            }
+            None => None,
+        };
+
+        quota_chain(&self.quota_defaults, org.as_ref(), tenant, project_id)
+    }
 }
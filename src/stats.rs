@@ -0,0 +1,64 @@
+use std::collections::VecDeque;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::state::AppState;
+
+/// How often `run_sampler` records the queue depth.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Samples kept, one hour at `SAMPLE_INTERVAL`.
+const MAX_SAMPLES: usize = 720;
+
+#[derive(Clone, Serialize)]
+pub struct QueueSample {
+    pub at: OffsetDateTime,
+    pub queued: usize,
+    pub running: usize,
+}
+
+/// Recent queue depth, oldest sample first. Served by `GET /stats/queue`.
+#[derive(Default)]
+pub struct QueueHistory {
+    samples: VecDeque<QueueSample>,
+}
+
+impl QueueHistory {
+    pub fn push(&mut self, sample: QueueSample) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn samples(&self) -> Vec<QueueSample> {
+        self.samples.iter().cloned().collect()
+    }
+}
+
+#[derive(Serialize)]
+pub struct QueueStatsResponse {
+    pub interval_secs: u64,
+    pub samples: Vec<QueueSample>,
+}
+
+/// Records how many jobs are queued and running every `SAMPLE_INTERVAL`.
+pub async fn run_sampler(state: AppState) {
+    let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+    loop {
+        ticker.tick().await;
+
+        state
+            .queue_history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(QueueSample {
+                at: OffsetDateTime::now_utc(),
+                queued: state.jobs.queued(),
+                running: state.jobs.running(),
+            });
+    }
+}
@@ -1,39 +1,99 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tokio::fs;
 
+use crate::backend::DEFAULT_BACKEND;
+use crate::capability::CapabilityGrant;
+use crate::quota::{QuotaError, QuotaLimits};
+
 #[derive(Deserialize, Clone)]
 pub struct Tenant {
     pub tenant_id: String,
-    pub allowed_capabilities: Vec<String>,
-    pub gpu_slot_limit: usize,
-    pub rate_limit: usize, // #jobs / minute
+    #[serde(default)]
+    pub org_id: Option<String>,
+    pub allowed_capabilities: Vec<CapabilityGrant>,
+    #[serde(default = "default_backends")]
+    pub allowed_backends: Vec<String>,
+    #[serde(flatten)]
+    pub limits: QuotaLimits,
+    #[serde(default)]
+    pub projects: Vec<Project>,
     pub status: TenantStatus,
 }
 
-#[derive(Deserialize, Clone)]
+fn default_backends() -> Vec<String> {
+    vec![DEFAULT_BACKEND.to_string()]
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum TenantStatus {
     Active,
     Suspended,
 }
 
+/// Groups tenants under a shared budget.
+#[derive(Deserialize, Clone)]
+pub struct Organization {
+    pub org_id: String,
+    #[serde(flatten)]
+    pub limits: QuotaLimits,
+}
+
+/// Optional sub-budget inside a tenant, chosen per job.
+#[derive(Deserialize, Clone)]
+pub struct Project {
+    pub project_id: String,
+    #[serde(flatten)]
+    pub limits: QuotaLimits,
+}
+
+/// Tenants and organizations by id, as loaded from `tenants.json`.
+pub type TenantSet = (HashMap<String, Tenant>, HashMap<String, Organization>);
+
 #[derive(Deserialize)]
 pub struct TenantFile {
+    #[serde(default)]
+    organizations: Vec<Organization>,
     tenants: Vec<Tenant>,
 }
 
 impl Tenant {
-    pub async fn load_all(
-        path: &str,
-    ) -> Result<HashMap<String, Tenant>, Box<dyn std::error::Error>> {
+    pub fn grant(&self, capability: &str) -> Option<&CapabilityGrant> {
+        self.allowed_capabilities
+            .iter()
+            .find(|g| g.name() == capability)
+    }
+
+    pub async fn load_all(path: &str) -> Result<TenantSet, Box<dyn std::error::Error>> {
         let contents: String = fs::read_to_string(path).await?;
-        let file: TenantFile = serde_json::from_str(&contents)?;
+        Self::parse_all(&contents)
+    }
+
+    /// Parses a `tenants.json` document.
+    pub fn parse_all(contents: &str) -> Result<TenantSet, Box<dyn std::error::Error>> {
+        let file: TenantFile = serde_json::from_str(contents)?;
+
+        let org_map: HashMap<String, Organization> = file
+            .organizations
+            .into_iter()
+            .map(|o| (o.org_id.clone(), o))
+            .collect();
+
+        if let Some(org_id) = file
+            .tenants
+            .iter()
+            .filter_map(|t| t.org_id.as_ref())
+            .find(|org_id| !org_map.contains_key(*org_id))
+        {
+            return Err(QuotaError::UnknownOrganization(org_id.clone()).into());
+        }
+
         let tenant_map = file
             .tenants
             .into_iter()
             .map(|t| (t.tenant_id.clone(), t))
             .collect();
-        Ok(tenant_map)
+        Ok((tenant_map, org_map))
     }
 }
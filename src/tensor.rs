@@ -0,0 +1,641 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use wasmtime::{Caller, Instance, Linker, Memory, Store};
+
+use crate::sandbox::{SandboxContext, SandboxError};
+
+/// Largest rank the core-module tensor ABI can describe.
+pub const MAX_RANK: usize = 8;
+
+/// Bytes `env.tensor_info` writes: data pointer, byte length, dtype, rank and
+/// `MAX_RANK` dims, all little-endian `u32`.
+const INFO_BYTES: usize = 4 * (4 + MAX_RANK);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DType {
+    F32,
+    F64,
+    I32,
+    I64,
+    U8,
+}
+
+/// How tensor data is written out in job results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TensorEncoding {
+    /// `data`: a JSON array of numbers
+    #[default]
+    Json,
+    /// `base64`: the raw little-endian bytes, base64-encoded
+    Base64,
+}
+
+/// A dense row-major tensor with little-endian elements. On the wire it is
+/// `{"dtype", "shape", "data": [...]}` or `{"dtype", "shape", "base64": "..."}`.
+/// `data` may be flat or nested; either way it must hold exactly the number
+/// of elements `shape` implies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "WireTensor", into = "WireTensor")]
+pub struct Tensor {
+    dtype: DType,
+    shape: Vec<usize>,
+    bytes: Vec<u8>,
+    encoding: TensorEncoding,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireTensor {
+    dtype: DType,
+    shape: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base64: Option<String>,
+}
+
+/// One declared input of a module: `null` dims match any size, and a missing
+/// `shape` matches any rank.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TensorSpec {
+    pub dtype: DType,
+    #[serde(default)]
+    pub shape: Option<Vec<Option<usize>>>,
+}
+
+/// The named input tensors a module declares. For WASM modules it lives next
+/// to the module as `modules/{id}.signature.json`; ONNX models carry their own.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Signature {
+    pub inputs: BTreeMap<String, TensorSpec>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TensorError {
+    #[error("Invalid tensor data: {0}")]
+    InvalidData(String),
+    #[error("Tensors don't match the module signature: {0}")]
+    SignatureMismatch(String),
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+}
+
+/// An element type a `Tensor` can hold.
+pub trait Element: Copy {
+    const DTYPE: DType;
+    fn from_le(bytes: &[u8]) -> Self;
+    fn extend_le(self, out: &mut Vec<u8>);
+    fn from_json(number: &serde_json::Number) -> Option<Self>;
+    fn to_json(self) -> serde_json::Value;
+}
+
+macro_rules! element {
+    ($ty:ty, $dtype:ident, |$n:ident| $from_json:expr) => {
+        impl Element for $ty {
+            const DTYPE: DType = DType::$dtype;
+
+            fn from_le(bytes: &[u8]) -> Self {
+                <$ty>::from_le_bytes(bytes.try_into().unwrap())
+            }
+
+            fn extend_le(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn from_json($n: &serde_json::Number) -> Option<Self> {
+                $from_json
+            }
+
+            fn to_json(self) -> serde_json::Value {
+                serde_json::Value::from(self)
+            }
+        }
+    };
+}
+
+element!(f32, F32, |n| n.as_f64().map(|v| v as f32));
+element!(f64, F64, |n| n.as_f64());
+element!(i32, I32, |n| n.as_i64().and_then(|v| i32::try_from(v).ok()));
+element!(i64, I64, |n| n.as_i64());
+element!(u8, U8, |n| n.as_u64().and_then(|v| u8::try_from(v).ok()));
+
+impl DType {
+    pub fn size(self) -> usize {
+        match self {
+            DType::U8 => 1,
+            DType::F32 | DType::I32 => 4,
+            DType::F64 | DType::I64 => 8,
+        }
+    }
+
+    /// The dtype's number in the core-module ABI.
+    pub fn code(self) -> u32 {
+        match self {
+            DType::F32 => 0,
+            DType::F64 => 1,
+            DType::I32 => 2,
+            DType::I64 => 3,
+            DType::U8 => 4,
+        }
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        [DType::F32, DType::F64, DType::I32, DType::I64, DType::U8]
+            .into_iter()
+            .find(|d| d.code() == code)
+    }
+}
+
+impl fmt::Display for DType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DType::F32 => "f32",
+            DType::F64 => "f64",
+            DType::I32 => "i32",
+            DType::I64 => "i64",
+            DType::U8 => "u8",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Tensor {
+    /// Wraps little-endian `bytes`, which must fill `shape` exactly.
+    pub fn new(dtype: DType, shape: Vec<usize>, bytes: Vec<u8>) -> Result<Self, TensorError> {
+        let expected = element_count(&shape)?
+            .checked_mul(dtype.size())
+            .ok_or_else(|| TensorError::InvalidData(format!("shape {:?} is too large", shape)))?;
+        if bytes.len() != expected {
+            return Err(TensorError::InvalidData(format!(
+                "shape {:?} of {} needs {} bytes, got {}",
+                shape,
+                dtype,
+                expected,
+                bytes.len()
+            )));
+        }
+        Ok(Tensor {
+            dtype,
+            shape,
+            bytes,
+            encoding: TensorEncoding::default(),
+        })
+    }
+
+    pub fn from_values<T: Element>(
+        shape: Vec<usize>,
+        values: impl IntoIterator<Item = T>,
+    ) -> Result<Self, TensorError> {
+        let mut bytes = Vec::new();
+        values.into_iter().for_each(|v| v.extend_le(&mut bytes));
+        Tensor::new(T::DTYPE, shape, bytes)
+    }
+
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The elements as `T`, which must match the tensor's dtype.
+    pub fn values<T: Element>(&self) -> Result<Vec<T>, TensorError> {
+        if T::DTYPE != self.dtype {
+            return Err(TensorError::InvalidData(format!(
+                "tensor holds {}, not {}",
+                self.dtype,
+                T::DTYPE
+            )));
+        }
+        Ok(self
+            .bytes
+            .chunks_exact(self.dtype.size())
+            .map(T::from_le)
+            .collect())
+    }
+
+    pub fn set_encoding(&mut self, encoding: TensorEncoding) {
+        self.encoding = encoding;
+    }
+}
+
+fn element_count(shape: &[usize]) -> Result<usize, TensorError> {
+    shape
+        .iter()
+        .try_fold(1usize, |n, d| n.checked_mul(*d))
+        .ok_or_else(|| TensorError::InvalidData(format!("shape {:?} is too large", shape)))
+}
+
+/// Flattens (possibly nested) JSON arrays of numbers into `T`s.
+fn json_values<T: Element>(value: &serde_json::Value, out: &mut Vec<u8>) -> Result<(), String> {
+    match value {
+        serde_json::Value::Array(items) => items.iter().try_for_each(|v| json_values::<T>(v, out)),
+        serde_json::Value::Number(n) => match T::from_json(n) {
+            Some(v) => {
+                v.extend_le(out);
+                Ok(())
+            }
+            None => Err(format!("{} is not a valid {}", n, T::DTYPE)),
+        },
+        other => Err(format!("expected a number, got {}", other)),
+    }
+}
+
+fn to_json<T: Element>(bytes: &[u8]) -> serde_json::Value {
+    bytes
+        .chunks_exact(T::DTYPE.size())
+        .map(|c| T::from_le(c).to_json())
+        .collect()
+}
+
+impl TryFrom<WireTensor> for Tensor {
+    type Error = TensorError;
+
+    fn try_from(wire: WireTensor) -> Result<Self, Self::Error> {
+        let (bytes, encoding) = match (wire.data, wire.base64) {
+            (Some(data), None) => {
+                let mut bytes = Vec::new();
+                match wire.dtype {
+                    DType::F32 => json_values::<f32>(&data, &mut bytes),
+                    DType::F64 => json_values::<f64>(&data, &mut bytes),
+                    DType::I32 => json_values::<i32>(&data, &mut bytes),
+                    DType::I64 => json_values::<i64>(&data, &mut bytes),
+                    DType::U8 => json_values::<u8>(&data, &mut bytes),
+                }
+                .map_err(TensorError::InvalidData)?;
+                (bytes, TensorEncoding::Json)
+            }
+            (None, Some(encoded)) => {
+                let bytes = BASE64
+                    .decode(encoded)
+                    .map_err(|e| TensorError::InvalidData(e.to_string()))?;
+                (bytes, TensorEncoding::Base64)
+            }
+            _ => {
+                return Err(TensorError::InvalidData(
+                    "give exactly one of data or base64".to_string(),
+                ));
+            }
+        };
+
+        let mut tensor = Tensor::new(wire.dtype, wire.shape, bytes)?;
+        tensor.encoding = encoding;
+        Ok(tensor)
+    }
+}
+
+impl From<Tensor> for WireTensor {
+    fn from(tensor: Tensor) -> Self {
+        let (data, base64) = match tensor.encoding {
+            TensorEncoding::Json => {
+                let data = match tensor.dtype {
+                    DType::F32 => to_json::<f32>(&tensor.bytes),
+                    DType::F64 => to_json::<f64>(&tensor.bytes),
+                    DType::I32 => to_json::<i32>(&tensor.bytes),
+                    DType::I64 => to_json::<i64>(&tensor.bytes),
+                    DType::U8 => to_json::<u8>(&tensor.bytes),
+                };
+                (Some(data), None)
+            }
+            TensorEncoding::Base64 => (None, Some(BASE64.encode(&tensor.bytes))),
+        };
+        WireTensor {
+            dtype: tensor.dtype,
+            shape: tensor.shape,
+            data,
+            base64,
+        }
+    }
+}
+
+impl TensorSpec {
+    fn check(&self, name: &str, tensor: &Tensor) -> Result<(), TensorError> {
+        if tensor.dtype != self.dtype {
+            return Err(TensorError::SignatureMismatch(format!(
+                "{} must be {}, got {}",
+                name, self.dtype, tensor.dtype
+            )));
+        }
+        let Some(shape) = &self.shape else {
+            return Ok(());
+        };
+        let matches = shape.len() == tensor.shape.len()
+            && shape
+                .iter()
+                .zip(&tensor.shape)
+                .all(|(want, got)| want.is_none_or(|w| w == *got));
+        if !matches {
+            let shape: Vec<String> = shape
+                .iter()
+                .map(|d| d.map_or("?".to_string(), |d| d.to_string()))
+                .collect();
+            return Err(TensorError::SignatureMismatch(format!(
+                "{} must have shape [{}], got {:?}",
+                name,
+                shape.join(", "),
+                tensor.shape
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Signature {
+    /// Reads a signature file. A module without one takes any tensors.
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, TensorError> {
+        let contents = match std::fs::read_to_string(path.as_ref()) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(TensorError::InvalidSignature(e.to_string())),
+        };
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| TensorError::InvalidSignature(e.to_string()))
+    }
+
+    /// Checks that `tensors` are exactly the declared inputs, with matching
+    /// dtypes and shapes.
+    pub fn check_inputs(&self, tensors: &BTreeMap<String, Tensor>) -> Result<(), TensorError> {
+        if let Some(name) = tensors.keys().find(|n| !self.inputs.contains_key(*n)) {
+            return Err(TensorError::SignatureMismatch(format!(
+                "the module takes no input called {}",
+                name
+            )));
+        }
+        for (name, spec) in &self.inputs {
+            match tensors.get(name) {
+                Some(tensor) => spec.check(name, tensor)?,
+                None => {
+                    return Err(TensorError::SignatureMismatch(format!(
+                        "missing input {}",
+                        name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A job's tensors inside the sandbox: inputs, where each was staged in the
+/// guest's linear memory, and what the guest has output so far.
+#[derive(Default)]
+pub struct TensorIo {
+    pub inputs: BTreeMap<String, Tensor>,
+    staged: HashMap<String, u32>,
+    pub outputs: BTreeMap<String, Tensor>,
+}
+
+impl TensorIo {
+    pub fn new(inputs: BTreeMap<String, Tensor>) -> Self {
+        TensorIo {
+            inputs,
+            ..Default::default()
+        }
+    }
+}
+
+/// Copies the job's input tensors into a core module's linear memory once,
+/// before `run`, through its exported `tensor_alloc(bytes) -> ptr`. The guest
+/// then reads them in place.
+pub fn stage_inputs(
+    store: &mut Store<SandboxContext>,
+    instance: &Instance,
+) -> Result<(), SandboxError> {
+    if store.data().tensors.inputs.is_empty() {
+        return Ok(());
+    }
+    let failed = |msg: String| SandboxError::ExecutionFailed(format!("Tensor staging: {}", msg));
+
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&mut *store, "tensor_alloc")
+        .map_err(|_| failed("the job has tensors but the module exports no tensor_alloc".into()))?;
+    let memory = instance
+        .get_memory(&mut *store, "memory")
+        .ok_or_else(|| failed("the module exports no memory".into()))?;
+
+    let inputs: Vec<(String, Tensor)> = store
+        .data()
+        .tensors
+        .inputs
+        .iter()
+        .map(|(n, t)| (n.clone(), t.clone()))
+        .collect();
+    for (name, tensor) in inputs {
+        if tensor.shape.len() > MAX_RANK {
+            return Err(failed(format!("{} has rank above {}", name, MAX_RANK)));
+        }
+        let len = i32::try_from(tensor.bytes.len())
+            .map_err(|_| failed(format!("{} is too large", name)))?;
+        let ptr = alloc
+            .call(&mut *store, len)
+            .map_err(|e| failed(e.to_string()))?;
+        if ptr < 0 || !(ptr as usize).is_multiple_of(tensor.dtype.size()) {
+            return Err(failed(format!(
+                "tensor_alloc returned {} for {}, not an aligned pointer",
+                ptr, name
+            )));
+        }
+        memory
+            .write(&mut *store, ptr as usize, &tensor.bytes)
+            .map_err(|_| failed(format!("tensor_alloc returned {} out of range", ptr)))?;
+        store.data_mut().tensors.staged.insert(name, ptr as u32);
+    }
+    Ok(())
+}
+
+fn guest_memory(caller: &mut Caller<'_, SandboxContext>) -> Option<Memory> {
+    caller.get_export("memory").and_then(|e| e.into_memory())
+}
+
+fn read_guest(
+    caller: &mut Caller<'_, SandboxContext>,
+    memory: Memory,
+    ptr: i32,
+    len: i32,
+) -> Option<Vec<u8>> {
+    let mut buffer = vec![0u8; usize::try_from(len).ok()?];
+    memory
+        .read(&mut *caller, ptr as u32 as usize, &mut buffer)
+        .ok()?;
+    Some(buffer)
+}
+
+/// Adds `env.tensor_info` and `env.tensor_output` for core modules. They
+/// return `0` on success, `-1` for an unknown input, `-2` for a bad guest
+/// pointer and `-3` for an invalid output tensor.
+pub fn add_to_linker(linker: &mut Linker<SandboxContext>) -> Result<(), SandboxError> {
+    let link_failed = |e: wasmtime::Error| {
+        SandboxError::ExecutionFailed(format!("Failed to link tensor functions: {}", e))
+    };
+
+    // Writes the staged input's descriptor to `info_ptr`
+    linker
+        .func_wrap(
+            "env",
+            "tensor_info",
+            |mut caller: Caller<'_, SandboxContext>,
+             name_ptr: i32,
+             name_len: i32,
+             info_ptr: i32|
+             -> i32 {
+                let Some(memory) = guest_memory(&mut caller) else {
+                    return -2;
+                };
+                let Some(name) = read_guest(&mut caller, memory, name_ptr, name_len) else {
+                    return -2;
+                };
+                let name = String::from_utf8_lossy(&name);
+
+                let tensors = &caller.data().tensors;
+                let (Some(tensor), Some(ptr)) =
+                    (tensors.inputs.get(&*name), tensors.staged.get(&*name))
+                else {
+                    return -1;
+                };
+
+                let mut info = Vec::with_capacity(INFO_BYTES);
+                info.extend_from_slice(&ptr.to_le_bytes());
+                info.extend_from_slice(&(tensor.bytes.len() as u32).to_le_bytes());
+                info.extend_from_slice(&tensor.dtype.code().to_le_bytes());
+                info.extend_from_slice(&(tensor.shape.len() as u32).to_le_bytes());
+                for i in 0..MAX_RANK {
+                    let dim = tensor.shape.get(i).copied().unwrap_or(0) as u32;
+                    info.extend_from_slice(&dim.to_le_bytes());
+                }
+
+                match memory.write(&mut caller, info_ptr as u32 as usize, &info) {
+                    Ok(()) => 0,
+                    Err(_) => -2,
+                }
+            },
+        )
+        .map_err(link_failed)?;
+
+    // Copies an output tensor out of guest memory; `shape` is `rank` u32s
+    linker
+        .func_wrap(
+            "env",
+            "tensor_output",
+            |mut caller: Caller<'_, SandboxContext>,
+             name_ptr: i32,
+             name_len: i32,
+             dtype: i32,
+             shape_ptr: i32,
+             rank: i32,
+             data_ptr: i32,
+             data_len: i32|
+             -> i32 {
+                let Some(memory) = guest_memory(&mut caller) else {
+                    return -2;
+                };
+                if !(0..=MAX_RANK as i32).contains(&rank) {
+                    return -3;
+                }
+                let (Some(name), Some(shape), Some(data)) = (
+                    read_guest(&mut caller, memory, name_ptr, name_len),
+                    read_guest(&mut caller, memory, shape_ptr, rank * 4),
+                    read_guest(&mut caller, memory, data_ptr, data_len),
+                ) else {
+                    return -2;
+                };
+                let Some(dtype) = DType::from_code(dtype as u32) else {
+                    return -3;
+                };
+                let shape = shape
+                    .chunks_exact(4)
+                    .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize)
+                    .collect();
+
+                match Tensor::new(dtype, shape, data) {
+                    Ok(tensor) => {
+                        let name = String::from_utf8_lossy(&name).into_owned();
+                        caller.data_mut().tensors.outputs.insert(name, tensor);
+                        0
+                    }
+                    Err(_) => -3,
+                }
+            },
+        )
+        .map_err(link_failed)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: serde_json::Value) -> Result<Tensor, serde_json::Error> {
+        serde_json::from_value(json)
+    }
+
+    #[test]
+    fn json_and_base64_decode_to_the_same_bytes() {
+        let from_json = parse(serde_json::json!({
+            "dtype": "f32", "shape": [2, 2], "data": [[1.0, 2.0], [3.0, 4.0]]
+        }))
+        .unwrap();
+        let encoded = BASE64.encode(from_json.bytes());
+        let from_base64 = parse(serde_json::json!({
+            "dtype": "f32", "shape": [2, 2], "base64": encoded
+        }))
+        .unwrap();
+
+        assert_eq!(from_json.bytes(), from_base64.bytes());
+        assert_eq!(from_base64.values::<f32>().unwrap(), [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(
+            serde_json::to_value(&from_base64).unwrap()["base64"],
+            encoded.as_str()
+        );
+    }
+
+    #[test]
+    fn data_must_fit_shape_and_dtype() {
+        let short = parse(serde_json::json!({"dtype": "i32", "shape": [3], "data": [1, 2]}));
+        assert!(short.is_err());
+        let overflow = parse(serde_json::json!({"dtype": "u8", "shape": [1], "data": [256]}));
+        assert!(overflow.is_err());
+        let both = parse(serde_json::json!({
+            "dtype": "u8", "shape": [1], "data": [1], "base64": "AQ=="
+        }));
+        assert!(both.is_err());
+    }
+
+    #[test]
+    fn signature_checks_names_dtypes_and_dims() {
+        let signature: Signature = serde_json::from_value(serde_json::json!({
+            "inputs": {"x": {"dtype": "f32", "shape": [null, 2]}}
+        }))
+        .unwrap();
+        let inputs = |name: &str, tensor: Tensor| BTreeMap::from([(name.to_string(), tensor)]);
+
+        let ok = Tensor::from_values(vec![3, 2], [0.0f32; 6]).unwrap();
+        signature.check_inputs(&inputs("x", ok.clone())).unwrap();
+
+        let wrong_dim = Tensor::from_values(vec![3, 3], [0.0f32; 9]).unwrap();
+        let wrong_dtype = Tensor::from_values(vec![1, 2], [0i64; 2]).unwrap();
+        for bad in [
+            inputs("x", wrong_dim),
+            inputs("x", wrong_dtype),
+            inputs("y", ok),
+            BTreeMap::new(),
+        ] {
+            assert!(matches!(
+                signature.check_inputs(&bad),
+                Err(TensorError::SignatureMismatch(_))
+            ));
+        }
+    }
+}
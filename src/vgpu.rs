@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+
+use wasmtime::{Caller, Linker, Memory};
+
+use crate::sandbox::{SandboxContext, SandboxError};
+
+/// Bytes per element; device buffers hold `f32`.
+const ELEMENT_BYTES: usize = 4;
+
+/// A virtual device for one job, emulated on the CPU. Buffers live in host
+/// memory and are charged against `memory_limit`, derived from the job's slot.
+pub struct VirtualDevice {
+    memory_limit: usize,
+    memory_used: usize,
+    memory_peak: usize,
+    next_handle: u32,
+    buffers: HashMap<u32, Vec<f32>>,
+}
+
+/// Kernels the CPU backend can run. Buffers and scalars are passed
+/// positionally; see `Kernel::launch` for each signature.
+#[derive(Debug, Clone, Copy)]
+pub enum Kernel {
+    VectorAdd,
+    Saxpy,
+    Matmul,
+    ReduceSum,
+    ReduceMax,
+    Softmax,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VgpuError {
+    #[error("Device memory exhausted: {requested} bytes requested, {available} available")]
+    OutOfMemory { requested: usize, available: usize },
+    #[error("Unknown buffer handle {0}")]
+    InvalidHandle(u32),
+    #[error("Unknown kernel {0}")]
+    UnknownKernel(String),
+    #[error("Invalid arguments for {0:?}: {1}")]
+    InvalidArguments(Kernel, String),
+    #[error("Buffer {handle} holds {len} elements, access needs {needed}")]
+    OutOfBounds {
+        handle: u32,
+        len: usize,
+        needed: usize,
+    },
+    #[error("Guest memory access out of range")]
+    GuestMemory,
+}
+
+impl VgpuError {
+    /// Negative status handed back to core-module guests.
+    pub fn guest_code(&self) -> i32 {
+        match self {
+            VgpuError::OutOfMemory { .. } => -1,
+            VgpuError::InvalidHandle(_) => -2,
+            VgpuError::UnknownKernel(_) => -3,
+            VgpuError::InvalidArguments(..) => -4,
+            VgpuError::OutOfBounds { .. } => -5,
+            VgpuError::GuestMemory => -6,
+        }
+    }
+}
+
+impl Kernel {
+    pub fn parse(name: &str) -> Result<Self, VgpuError> {
+        match name {
+            "vadd" => Ok(Kernel::VectorAdd),
+            "saxpy" => Ok(Kernel::Saxpy),
+            "matmul" => Ok(Kernel::Matmul),
+            "reduce_sum" => Ok(Kernel::ReduceSum),
+            "reduce_max" => Ok(Kernel::ReduceMax),
+            "softmax" => Ok(Kernel::Softmax),
+            _ => Err(VgpuError::UnknownKernel(name.to_string())),
+        }
+    }
+
+    fn expect(self, buffers: &[u32], scalars: &[f32], b: usize, s: usize) -> Result<(), VgpuError> {
+        if buffers.len() != b || scalars.len() != s {
+            return Err(VgpuError::InvalidArguments(
+                self,
+                format!(
+                    "expected {} buffers and {} scalars, got {} and {}",
+                    b,
+                    s,
+                    buffers.len(),
+                    scalars.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl VirtualDevice {
+    pub fn new(memory_limit: usize) -> Self {
+        VirtualDevice {
+            memory_limit,
+            memory_used: 0,
+            memory_peak: 0,
+            next_handle: 1,
+            buffers: HashMap::new(),
+        }
+    }
+
+    pub fn memory_peak(&self) -> usize {
+        self.memory_peak
+    }
+
+    /// Allocates a zeroed buffer of `elements` floats.
+    pub fn alloc(&mut self, elements: usize) -> Result<u32, VgpuError> {
+        let bytes = elements.saturating_mul(ELEMENT_BYTES);
+        let available = self.memory_limit - self.memory_used;
+        if bytes > available {
+            return Err(VgpuError::OutOfMemory {
+                requested: bytes,
+                available,
+            });
+        }
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.buffers.insert(handle, vec![0.0; elements]);
+        self.memory_used += bytes;
+        self.memory_peak = self.memory_peak.max(self.memory_used);
+        Ok(handle)
+    }
+
+    pub fn free(&mut self, handle: u32) -> Result<(), VgpuError> {
+        let buffer = self
+            .buffers
+            .remove(&handle)
+            .ok_or(VgpuError::InvalidHandle(handle))?;
+        self.memory_used -= buffer.len() * ELEMENT_BYTES;
+        Ok(())
+    }
+
+    /// Copies host data into the start of a buffer.
+    pub fn write(&mut self, handle: u32, data: &[f32]) -> Result<(), VgpuError> {
+        let buffer = self
+            .buffers
+            .get_mut(&handle)
+            .ok_or(VgpuError::InvalidHandle(handle))?;
+        if data.len() > buffer.len() {
+            return Err(VgpuError::OutOfBounds {
+                handle,
+                len: buffer.len(),
+                needed: data.len(),
+            });
+        }
+        buffer[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Returns the first `elements` floats of a buffer.
+    pub fn read(&self, handle: u32, elements: usize) -> Result<&[f32], VgpuError> {
+        let buffer = self.buffer(handle)?;
+        buffer.get(..elements).ok_or(VgpuError::OutOfBounds {
+            handle,
+            len: buffer.len(),
+            needed: elements,
+        })
+    }
+
+    fn buffer(&self, handle: u32) -> Result<&Vec<f32>, VgpuError> {
+        self.buffers
+            .get(&handle)
+            .ok_or(VgpuError::InvalidHandle(handle))
+    }
+
+    /// Runs `kernel` synchronously. Signatures:
+    ///
+    /// - `vadd`: buffers `[a, b, out]`, `out = a + b`
+    /// - `saxpy`: buffers `[x, y]`, scalars `[alpha]`, `y = alpha * x + y`
+    /// - `matmul`: buffers `[a, b, out]`, scalars `[m, k, n]`, row-major
+    ///   `out (m x n) = a (m x k) * b (k x n)`
+    /// - `reduce_sum` / `reduce_max`: buffers `[input, out]`, result in `out[0]`
+    /// - `softmax`: buffers `[input, out]`
+    pub fn launch(
+        &mut self,
+        kernel: Kernel,
+        buffers: &[u32],
+        scalars: &[f32],
+    ) -> Result<(), VgpuError> {
+        match kernel {
+            Kernel::VectorAdd => {
+                kernel.expect(buffers, scalars, 3, 0)?;
+                let (a, b) = (self.buffer(buffers[0])?, self.buffer(buffers[1])?);
+                if a.len() != b.len() {
+                    return Err(VgpuError::InvalidArguments(
+                        kernel,
+                        format!("inputs differ in length, {} vs {}", a.len(), b.len()),
+                    ));
+                }
+                let sum: Vec<f32> = a.iter().zip(b).map(|(x, y)| x + y).collect();
+                self.output(buffers[2], sum.len())?.copy_from_slice(&sum);
+            }
+            Kernel::Saxpy => {
+                kernel.expect(buffers, scalars, 2, 1)?;
+                let alpha = scalars[0];
+                let x = self.buffer(buffers[0])?.clone();
+                let y = self.output(buffers[1], x.len())?;
+                for (y, x) in y.iter_mut().zip(&x) {
+                    *y += alpha * x;
+                }
+            }
+            Kernel::Matmul => {
+                kernel.expect(buffers, scalars, 3, 3)?;
+                let dims: Vec<usize> = scalars
+                    .iter()
+                    .map(|d| {
+                        (d.fract() == 0.0 && *d >= 1.0)
+                            .then_some(*d as usize)
+                            .ok_or_else(|| {
+                                VgpuError::InvalidArguments(
+                                    kernel,
+                                    "dimensions must be positive integers".to_string(),
+                                )
+                            })
+                    })
+                    .collect::<Result<_, _>>()?;
+                let (m, k, n) = (dims[0], dims[1], dims[2]);
+                let elements = |x: usize, y: usize| {
+                    x.checked_mul(y).ok_or_else(|| {
+                        VgpuError::InvalidArguments(kernel, "dimensions overflow".to_string())
+                    })
+                };
+                let (a_len, b_len, out_len) = (elements(m, k)?, elements(k, n)?, elements(m, n)?);
+
+                // The product is only as large as a buffer the job already holds
+                self.output(buffers[2], out_len)?;
+                let a = self.read(buffers[0], a_len)?;
+                let b = self.read(buffers[1], b_len)?;
+                let mut product = vec![0.0; out_len];
+                for row in 0..m {
+                    for inner in 0..k {
+                        let lhs = a[row * k + inner];
+                        for col in 0..n {
+                            product[row * n + col] += lhs * b[inner * n + col];
+                        }
+                    }
+                }
+                self.output(buffers[2], out_len)?.copy_from_slice(&product);
+            }
+            Kernel::ReduceSum | Kernel::ReduceMax => {
+                kernel.expect(buffers, scalars, 2, 0)?;
+                let input = self.buffer(buffers[0])?;
+                let value = match kernel {
+                    Kernel::ReduceSum => input.iter().sum(),
+                    _ => input.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+                };
+                self.output(buffers[1], 1)?[0] = value;
+            }
+            Kernel::Softmax => {
+                kernel.expect(buffers, scalars, 2, 0)?;
+                let input = self.buffer(buffers[0])?;
+                let max = input.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                let exps: Vec<f32> = input.iter().map(|x| (x - max).exp()).collect();
+                let total: f32 = exps.iter().sum();
+                let out = self.output(buffers[1], exps.len())?;
+                for (out, e) in out.iter_mut().zip(&exps) {
+                    *out = e / total;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The first `elements` floats of a buffer, for writing.
+    fn output(&mut self, handle: u32, elements: usize) -> Result<&mut [f32], VgpuError> {
+        let buffer = self
+            .buffers
+            .get_mut(&handle)
+            .ok_or(VgpuError::InvalidHandle(handle))?;
+        let len = buffer.len();
+        buffer.get_mut(..elements).ok_or(VgpuError::OutOfBounds {
+            handle,
+            len,
+            needed: elements,
+        })
+    }
+}
+
+fn guest_memory(caller: &mut Caller<'_, SandboxContext>) -> Result<Memory, VgpuError> {
+    caller
+        .get_export("memory")
+        .and_then(|e| e.into_memory())
+        .ok_or(VgpuError::GuestMemory)
+}
+
+/// Reads `count` little-endian 32-bit values from guest memory.
+fn read_words(
+    caller: &mut Caller<'_, SandboxContext>,
+    ptr: i32,
+    count: i32,
+) -> Result<Vec<[u8; 4]>, VgpuError> {
+    let memory = guest_memory(caller)?;
+    let mut bytes = vec![0u8; count.max(0) as usize * ELEMENT_BYTES];
+    memory
+        .read(&mut *caller, ptr as u32 as usize, &mut bytes)
+        .map_err(|_| VgpuError::GuestMemory)?;
+    Ok(bytes
+        .chunks_exact(ELEMENT_BYTES)
+        .map(|c| [c[0], c[1], c[2], c[3]])
+        .collect())
+}
+
+fn status(result: Result<(), VgpuError>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => e.guest_code(),
+    }
+}
+
+/// Adds the `env.gpu_*` device functions for core modules. All return `0`
+/// (or a handle) on success and a negative `VgpuError::guest_code` otherwise.
+pub fn add_to_linker(linker: &mut Linker<SandboxContext>) -> Result<(), SandboxError> {
+    let link_failed = |e: wasmtime::Error| {
+        SandboxError::ExecutionFailed(format!("Failed to link device functions: {}", e))
+    };
+
+    linker
+        .func_wrap(
+            "env",
+            "gpu_alloc",
+            |mut caller: Caller<'_, SandboxContext>, elements: i32| -> i32 {
+                match caller.data_mut().device.alloc(elements.max(0) as usize) {
+                    Ok(handle) => handle as i32,
+                    Err(e) => e.guest_code(),
+                }
+            },
+        )
+        .map_err(link_failed)?;
+
+    linker
+        .func_wrap(
+            "env",
+            "gpu_free",
+            |mut caller: Caller<'_, SandboxContext>, handle: i32| -> i32 {
+                status(caller.data_mut().device.free(handle as u32))
+            },
+        )
+        .map_err(link_failed)?;
+
+    // Guest -> device: copies `elements` floats from `ptr`
+    linker
+        .func_wrap(
+            "env",
+            "gpu_write",
+            |mut caller: Caller<'_, SandboxContext>, handle: i32, ptr: i32, elements: i32| -> i32 {
+                status(read_words(&mut caller, ptr, elements).and_then(|words| {
+                    let data: Vec<f32> = words.into_iter().map(f32::from_le_bytes).collect();
+                    caller.data_mut().device.write(handle as u32, &data)
+                }))
+            },
+        )
+        .map_err(link_failed)?;
+
+    // Device -> guest: copies the first `elements` floats to `ptr`
+    linker
+        .func_wrap(
+            "env",
+            "gpu_read",
+            |mut caller: Caller<'_, SandboxContext>, handle: i32, ptr: i32, elements: i32| -> i32 {
+                let bytes: Vec<u8> = match caller
+                    .data()
+                    .device
+                    .read(handle as u32, elements.max(0) as usize)
+                {
+                    Ok(data) => data.iter().flat_map(|x| x.to_le_bytes()).collect(),
+                    Err(e) => return e.guest_code(),
+                };
+                status(guest_memory(&mut caller).and_then(|memory| {
+                    memory
+                        .write(&mut caller, ptr as u32 as usize, &bytes)
+                        .map_err(|_| VgpuError::GuestMemory)
+                }))
+            },
+        )
+        .map_err(link_failed)?;
+
+    // Buffers are a u32 handle array, scalars an f32 array
+    linker
+        .func_wrap(
+            "env",
+            "gpu_launch",
+            |mut caller: Caller<'_, SandboxContext>,
+             kernel_ptr: i32,
+             kernel_len: i32,
+             buffers_ptr: i32,
+             buffers_len: i32,
+             scalars_ptr: i32,
+             scalars_len: i32|
+             -> i32 {
+                let launched = (|| {
+                    let memory = guest_memory(&mut caller)?;
+                    let mut name = vec![0u8; kernel_len.max(0) as usize];
+                    memory
+                        .read(&caller, kernel_ptr as u32 as usize, &mut name)
+                        .map_err(|_| VgpuError::GuestMemory)?;
+                    let kernel = Kernel::parse(&String::from_utf8_lossy(&name))?;
+
+                    let buffers: Vec<u32> = read_words(&mut caller, buffers_ptr, buffers_len)?
+                        .into_iter()
+                        .map(u32::from_le_bytes)
+                        .collect();
+                    let scalars: Vec<f32> = read_words(&mut caller, scalars_ptr, scalars_len)?
+                        .into_iter()
+                        .map(f32::from_le_bytes)
+                        .collect();
+
+                    caller.data_mut().device.launch(kernel, &buffers, &scalars)
+                })();
+                status(launched)
+            },
+        )
+        .map_err(link_failed)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device_with(buffers: &[&[f32]]) -> (VirtualDevice, Vec<u32>) {
+        let mut device = VirtualDevice::new(1024);
+        let handles = buffers
+            .iter()
+            .map(|data| {
+                let handle = device.alloc(data.len()).unwrap();
+                device.write(handle, data).unwrap();
+                handle
+            })
+            .collect();
+        (device, handles)
+    }
+
+    #[test]
+    fn vector_kernels() {
+        let (mut device, h) = device_with(&[&[1.0, 2.0, 3.0], &[10.0, 20.0, 30.0], &[0.0; 3]]);
+
+        device.launch(Kernel::VectorAdd, &h, &[]).unwrap();
+        assert_eq!(device.read(h[2], 3).unwrap(), [11.0, 22.0, 33.0]);
+
+        device.launch(Kernel::Saxpy, &h[..2], &[2.0]).unwrap();
+        assert_eq!(device.read(h[1], 3).unwrap(), [12.0, 24.0, 36.0]);
+    }
+
+    #[test]
+    fn matmul_is_row_major() {
+        let (mut device, h) = device_with(&[
+            &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0],
+            &[7.0, 8.0, 9.0, 10.0, 11.0, 12.0],
+            &[0.0; 4],
+        ]);
+
+        device.launch(Kernel::Matmul, &h, &[2.0, 3.0, 2.0]).unwrap();
+        assert_eq!(device.read(h[2], 4).unwrap(), [58.0, 64.0, 139.0, 154.0]);
+
+        let err = device.launch(Kernel::Matmul, &h, &[3.0, 3.0, 2.0]);
+        assert!(matches!(err, Err(VgpuError::OutOfBounds { .. })));
+    }
+
+    #[test]
+    fn matmul_checks_sizes_before_computing() {
+        let (mut device, h) = device_with(&[&[1.0; 100], &[1.0; 100], &[0.0; 4]]);
+
+        // Both inputs are in bounds, but the 100 x 100 product isn't
+        let err = device.launch(Kernel::Matmul, &h, &[100.0, 1.0, 100.0]);
+        assert!(matches!(
+            err,
+            Err(VgpuError::OutOfBounds {
+                len: 4,
+                needed: 10_000,
+                ..
+            })
+        ));
+
+        let huge = 2f32.powi(40);
+        let err = device.launch(Kernel::Matmul, &h, &[huge, huge, 1.0]);
+        assert!(matches!(err, Err(VgpuError::InvalidArguments(..))));
+        assert_eq!(device.read(h[2], 4).unwrap(), [0.0; 4]);
+    }
+
+    #[test]
+    fn reductions_and_softmax() {
+        let (mut device, h) = device_with(&[&[1.0, 3.0, 2.0], &[0.0; 3]]);
+
+        device.launch(Kernel::ReduceSum, &h, &[]).unwrap();
+        assert_eq!(device.read(h[1], 1).unwrap(), [6.0]);
+        device.launch(Kernel::ReduceMax, &h, &[]).unwrap();
+        assert_eq!(device.read(h[1], 1).unwrap(), [3.0]);
+
+        device.launch(Kernel::Softmax, &h, &[]).unwrap();
+        let out = device.read(h[1], 3).unwrap();
+        assert!((out.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!(out[1] > out[2] && out[2] > out[0]);
+    }
+
+    #[test]
+    fn allocations_are_charged_against_the_limit() {
+        let mut device = VirtualDevice::new(64);
+        let a = device.alloc(12).unwrap();
+        assert!(matches!(
+            device.alloc(8),
+            Err(VgpuError::OutOfMemory {
+                requested: 32,
+                available: 16
+            })
+        ));
+
+        device.free(a).unwrap();
+        device.alloc(16).unwrap();
+        assert_eq!(device.memory_peak(), 64);
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use tempfile::TempDir;
+use wasmtime::Linker;
+use wasmtime_wasi::p1::{self, WasiP1Ctx};
+use wasmtime_wasi::p2::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder, WasiCtxView, WasiView};
+
+use crate::capability::{CapabilityParams, ResolvedCapability};
+use crate::domain::Job;
+use crate::sandbox::{SandboxContext, SandboxError};
+
+/// Where the per-job scratch directory shows up inside the guest.
+pub const SCRATCH_GUEST_PATH: &str = "/scratch";
+
+/// errno returned by clock and random calls the job wasn't granted.
+const ERRNO_NOTCAPABLE: i32 = 76;
+
+/// Guest args and env, taken from `payload.wasi`. The host's own args and env
+/// are never inherited.
+#[derive(Default, Deserialize)]
+struct WasiPayload {
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+/// The WASI state for one job. Dropping it removes the scratch directory.
+pub struct WasiSetup {
+    pub ctx: WasiP1Ctx,
+    pub stdout: MemoryOutputPipe,
+    pub stderr: MemoryOutputPipe,
+    _scratch: Option<TempDir>,
+}
+
+impl WasiSetup {
+    /// Builds the WASI context if the job was granted `wasi`, otherwise `None`.
+    pub fn for_job(
+        job: &Job,
+        capabilities: &[ResolvedCapability],
+    ) -> Result<Option<WasiSetup>, SandboxError> {
+        let Some(params) = capabilities.iter().find_map(|c| match &c.params {
+            CapabilityParams::Wasi(params) => Some(params),
+            _ => None,
+        }) else {
+            return Ok(None);
+        };
+
+        let payload: WasiPayload = match job.payload.get("wasi") {
+            Some(value) => serde_json::from_value(value.clone()).map_err(|e| {
+                SandboxError::ExecutionFailed(format!("Invalid payload.wasi: {}", e))
+            })?,
+            None => WasiPayload::default(),
+        };
+
+        let stdout = MemoryOutputPipe::new(params.max_output_bytes);
+        let stderr = MemoryOutputPipe::new(params.max_output_bytes);
+
+        let mut builder = WasiCtxBuilder::new();
+        builder
+            .stdin(MemoryInputPipe::new(job.input.clone()))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone())
+            .arg(&job.module_id)
+            .args(&payload.args)
+            .envs(&payload.env.into_iter().collect::<Vec<_>>());
+
+        let scratch = if capabilities
+            .iter()
+            .any(|c| matches!(c.params, CapabilityParams::WasiFs))
+        {
+            let dir = tempfile::Builder::new()
+                .prefix(&format!("gpu-sandbox-{}-", job.job_id))
+                .tempdir()
+                .map_err(|e| {
+                    SandboxError::ExecutionFailed(format!("Scratch dir setup failed: {}", e))
+                })?;
+            builder
+                .preopened_dir(
+                    dir.path(),
+                    SCRATCH_GUEST_PATH,
+                    DirPerms::all(),
+                    FilePerms::all(),
+                )
+                .map_err(|e| {
+                    SandboxError::ExecutionFailed(format!("Scratch dir setup failed: {}", e))
+                })?;
+            Some(dir)
+        } else {
+            None
+        };
+
+        Ok(Some(WasiSetup {
+            ctx: builder.build_p1(),
+            stdout,
+            stderr,
+            _scratch: scratch,
+        }))
+    }
+}
+
+/// WASI 0.2 for components, backed by the same context as preview1.
+impl WasiView for SandboxContext {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        self.wasi
+            .as_mut()
+            .expect("WASI linked without a WASI context")
+            .ctx
+            .ctx()
+    }
+}
+
+/// Adds `wasi_snapshot_preview1` to the linker, then shadows the clock and
+/// random imports with `ENOTCAPABLE` stubs unless `wasi.clocks` or
+/// `wasi.random` was granted.
+pub fn add_to_linker(
+    linker: &mut Linker<SandboxContext>,
+    capabilities: &[ResolvedCapability],
+) -> Result<(), SandboxError> {
+    let link_failed =
+        |e: wasmtime::Error| SandboxError::ExecutionFailed(format!("Failed to link WASI: {}", e));
+
+    p1::add_to_linker_sync(linker, |ctx: &mut SandboxContext| {
+        ctx.wasi
+            .as_mut()
+            .map(|w| &mut w.ctx)
+            .expect("WASI linked without a WASI context")
+    })
+    .map_err(link_failed)?;
+
+    linker.allow_shadowing(true);
+
+    if !capabilities
+        .iter()
+        .any(|c| matches!(c.params, CapabilityParams::WasiClocks))
+    {
+        linker
+            .func_wrap(
+                "wasi_snapshot_preview1",
+                "clock_time_get",
+                |_id: i32, _precision: i64, _out: i32| -> i32 { ERRNO_NOTCAPABLE },
+            )
+            .map_err(link_failed)?;
+        linker
+            .func_wrap(
+                "wasi_snapshot_preview1",
+                "clock_res_get",
+                |_id: i32, _out: i32| -> i32 { ERRNO_NOTCAPABLE },
+            )
+            .map_err(link_failed)?;
+    }
+
+    if !capabilities
+        .iter()
+        .any(|c| matches!(c.params, CapabilityParams::WasiRandom))
+    {
+        linker
+            .func_wrap(
+                "wasi_snapshot_preview1",
+                "random_get",
+                |_buf: i32, _len: i32| -> i32 { ERRNO_NOTCAPABLE },
+            )
+            .map_err(link_failed)?;
+    }
+
+    linker.allow_shadowing(false);
+
+    Ok(())
+}
@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::domain::{Job, JobStatus, SubmitJobRequest};
+use crate::job_store::JobStore;
+
+/// A DAG of jobs submitted together. Steps name the steps they run `after`.
+#[derive(Deserialize)]
+pub struct SubmitWorkflowRequest {
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Deserialize)]
+pub struct WorkflowStep {
+    pub name: String,
+    #[serde(default)]
+    pub after: Vec<String>,
+    #[serde(flatten)]
+    pub job: SubmitJobRequest,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Workflow {
+    pub workflow_id: Uuid,
+    pub tenant_id: String,
+    pub submitted_at: OffsetDateTime,
+    /// Step name -> job
+    pub steps: BTreeMap<String, Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct SubmitWorkflowResponse {
+    pub workflow_id: Uuid,
+    pub steps: BTreeMap<String, Uuid>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkflowStatus {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize)]
+pub struct WorkflowStepStatus {
+    pub job_id: Uuid,
+    pub status: JobStatus,
+}
+
+#[derive(Serialize)]
+pub struct WorkflowStatusResponse {
+    pub workflow_id: Uuid,
+    pub tenant_id: String,
+    pub status: WorkflowStatus,
+    pub submitted_at: OffsetDateTime,
+    pub steps: BTreeMap<String, WorkflowStepStatus>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WorkflowError {
+    #[error("A workflow needs at least one step")]
+    Empty,
+    #[error("Step name {0:?} is empty or used twice")]
+    DuplicateStep(String),
+    #[error("Step {step} runs after unknown step {after}")]
+    UnknownStep { step: String, after: String },
+    #[error("Steps {0:?} depend on each other in a cycle")]
+    Cycle(Vec<String>),
+    #[error("All steps must belong to the same tenant")]
+    MixedTenants,
+}
+
+/// Checks the step graph and returns the step indices with every step after
+/// the steps it runs after.
+pub fn order(steps: &[WorkflowStep]) -> Result<Vec<usize>, WorkflowError> {
+    let Some(first) = steps.first() else {
+        return Err(WorkflowError::Empty);
+    };
+    if steps.iter().any(|s| s.job.tenant_id != first.job.tenant_id) {
+        return Err(WorkflowError::MixedTenants);
+    }
+
+    let mut index = HashMap::new();
+    for (i, step) in steps.iter().enumerate() {
+        if step.name.is_empty() || index.insert(step.name.as_str(), i).is_some() {
+            return Err(WorkflowError::DuplicateStep(step.name.clone()));
+        }
+    }
+
+    // Kahn's algorithm, keeping submission order among ready steps
+    let mut unmet = vec![0; steps.len()];
+    let mut children = vec![Vec::new(); steps.len()];
+    for (i, step) in steps.iter().enumerate() {
+        for after in &step.after {
+            let Some(&parent) = index.get(after.as_str()) else {
+                return Err(WorkflowError::UnknownStep {
+                    step: step.name.clone(),
+                    after: after.clone(),
+                });
+            };
+            unmet[i] += 1;
+            children[parent].push(i);
+        }
+    }
+
+    let mut ready: VecDeque<usize> = (0..steps.len()).filter(|i| unmet[*i] == 0).collect();
+    let mut ordered = Vec::with_capacity(steps.len());
+    while let Some(i) = ready.pop_front() {
+        ordered.push(i);
+        for &child in &children[i] {
+            unmet[child] -= 1;
+            if unmet[child] == 0 {
+                ready.push_back(child);
+            }
+        }
+    }
+
+    if ordered.len() < steps.len() {
+        let cycle = (0..steps.len())
+            .filter(|i| unmet[*i] > 0)
+            .map(|i| steps[i].name.clone())
+            .collect();
+        return Err(WorkflowError::Cycle(cycle));
+    }
+    Ok(ordered)
+}
+
+/// The status of a workflow, from the status of its jobs. It is queued until
+/// a job starts, and settles on finished, failed or cancelled once no job is
+/// left to run. An expired job counts as failed.
+pub fn aggregate_status<'a>(statuses: impl IntoIterator<Item = &'a JobStatus>) -> WorkflowStatus {
+    let (mut queued, mut running, mut settled, mut failed, mut cancelled) = (0, 0, 0, 0, 0);
+    for status in statuses {
+        match status {
+            JobStatus::Queued => queued += 1,
+            JobStatus::Running => running += 1,
+            JobStatus::Finished(_) => settled += 1,
+            JobStatus::Failed(_) | JobStatus::Expired => {
+                settled += 1;
+                failed += 1;
+            }
+            JobStatus::Cancelled => {
+                settled += 1;
+                cancelled += 1;
+            }
+        }
+    }
+
+    if running > 0 || (queued > 0 && settled > 0) {
+        WorkflowStatus::Running
+    } else if queued > 0 {
+        WorkflowStatus::Queued
+    } else if failed > 0 {
+        WorkflowStatus::Failed
+    } else if cancelled > 0 {
+        WorkflowStatus::Cancelled
+    } else {
+        WorkflowStatus::Finished
+    }
+}
+
+/// Where a job stands with respect to its parents.
+pub enum Parents {
+    /// Some parent is queued or running
+    Pending,
+    /// Every parent finished successfully
+    Finished,
+    /// This parent failed, was cancelled or is gone
+    Failed(Uuid),
+}
+
+pub fn parents(jobs: &JobStore, job: &Job) -> Parents {
+    let mut pending = false;
+    for &parent in &job.depends_on {
+        match jobs.read(parent, |p| p.status.name()) {
+            Some("finished") => {}
+            Some("queued" | "running") => pending = true,
+            _ => return Parents::Failed(parent),
+        }
+    }
+    if pending {
+        Parents::Pending
+    } else {
+        Parents::Finished
+    }
+}
+
+/// Adds the output tensors of a job's (finished) parents to its inputs, and
+/// their base64-encoded outputs to `payload.parent_outputs` keyed by job id. Tensors the job
+/// was submitted with win over passed ones; two parents passing the same
+/// tensor is an error.
+pub fn pass_outputs(jobs: &JobStore, job: &mut Job) -> Result<(), String> {
+    let mut passed_by = HashMap::new();
+    let mut outputs = serde_json::Map::new();
+
+    for &parent in &job.depends_on {
+        let Some(result) = jobs.read(parent, |p| p.result.clone()).flatten() else {
+            continue;
+        };
+        for (name, tensor) in result.tensors {
+            if let Some(other) = passed_by.insert(name.clone(), parent) {
+                return Err(format!(
+                    "Parent jobs {} and {} both output tensor {}",
+                    other, parent, name
+                ));
+            }
+            job.tensors.entry(name).or_insert(tensor);
+        }
+        outputs.insert(
+            parent.to_string(),
+            Value::String(BASE64.encode(&result.output)),
+        );
+    }
+
+    job.add_to_payload("parent_outputs", Value::Object(outputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(statuses: &[JobStatus]) -> &'static str {
+        match aggregate_status(statuses) {
+            WorkflowStatus::Queued => "queued",
+            WorkflowStatus::Running => "running",
+            WorkflowStatus::Finished => "finished",
+            WorkflowStatus::Failed => "failed",
+            WorkflowStatus::Cancelled => "cancelled",
+        }
+    }
+
+    #[test]
+    fn status_settles_once_no_job_is_left() {
+        let finished = || JobStatus::Finished(String::new());
+        let failed = || JobStatus::Failed(String::new());
+
+        assert_eq!(status(&[JobStatus::Queued, JobStatus::Queued]), "queued");
+        assert_eq!(status(&[finished(), JobStatus::Queued]), "running");
+        assert_eq!(status(&[failed(), JobStatus::Running]), "running");
+        assert_eq!(status(&[finished(), finished()]), "finished");
+        assert_eq!(status(&[failed(), JobStatus::Cancelled]), "failed");
+        assert_eq!(status(&[finished(), JobStatus::Cancelled]), "cancelled");
+    }
+}
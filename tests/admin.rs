@@ -0,0 +1,73 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+
+use common::{Harness, config, error_code, tenant};
+
+const TOKEN: &str = "s3cret";
+
+fn harness() -> Harness {
+    Harness::start(
+        &format!(
+            "admin_token = \"{}\"\n{}",
+            TOKEN,
+            config(8, &[4], "127.0.0.1:1")
+        ),
+        json!({"tenants": [tenant("alice", &[], 4)]}),
+    )
+}
+
+#[tokio::test]
+async fn model_upload_needs_admin_token() {
+    let harness = harness();
+
+    for token in [None, Some("wrong")] {
+        let (status, body) = harness
+            .upload("/models/tiny-dense", b"not a model".to_vec(), token)
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", body);
+        assert_eq!(error_code(&body), "unauthorized");
+    }
+
+    let (status, versions) = harness.get("/models/tiny-dense").await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", versions);
+
+    // With the token the body is checked, not refused
+    let (status, body) = harness
+        .upload("/models/tiny-dense", b"not a model".to_vec(), Some(TOKEN))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+}
+
+#[tokio::test]
+async fn module_upload_needs_admin_token() {
+    let harness = harness();
+    let wasm =
+        wat::parse_str(r#"(module (func (export "run") (result i32) i32.const 7))"#).unwrap();
+
+    for token in [None, Some("wrong"), Some("s3cre")] {
+        let (status, body) = harness.upload("/modules/seven", wasm.clone(), token).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", body);
+    }
+    assert!(!harness.state.modules_dir.join("seven.wasm").exists());
+
+    let (status, body) = harness.upload("/modules/seven", wasm, Some(TOKEN)).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+    assert_eq!(body["module_id"], "seven");
+    harness.run("alice", "seven", &[]).await;
+}
+
+#[tokio::test]
+async fn uploads_are_refused_without_configured_token() {
+    let harness = Harness::start(
+        &config(8, &[4], "127.0.0.1:1"),
+        json!({"tenants": [tenant("alice", &[], 4)]}),
+    );
+    let wasm =
+        wat::parse_str(r#"(module (func (export "run") (result i32) i32.const 7))"#).unwrap();
+
+    let (status, body) = harness.upload("/modules/seven", wasm, Some(TOKEN)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+    assert_eq!(error_code(&body), "admin_disabled");
+}
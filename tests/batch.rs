@@ -0,0 +1,137 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use serde_json::{Value, json};
+
+use common::{Harness, config, error_code, status_name, tenant};
+
+fn spec(tenant_id: &str) -> Value {
+    json!({
+        "tenant_id": tenant_id,
+        "module_id": "simple-compute",
+        "payload": {},
+        "capabilities": [],
+    })
+}
+
+async fn submit_batch(harness: &Harness, mode: &str, jobs: Vec<Value>) -> (StatusCode, Value) {
+    harness
+        .request(
+            Method::POST,
+            "/jobs/batch",
+            Some(json!({"mode": mode, "jobs": jobs})),
+        )
+        .await
+}
+
+async fn listed(harness: &Harness) -> usize {
+    let (_, body) = harness.get("/jobs/list").await;
+    body["jobs"].as_array().unwrap().len()
+}
+
+fn harness(queue_length: usize, tenants: Vec<Value>) -> Harness {
+    let harness = Harness::start(
+        &config(queue_length, &[4], "127.0.0.1:1"),
+        json!({ "tenants": tenants }),
+    );
+    harness.install_example("simple-compute");
+    harness
+}
+
+#[tokio::test]
+async fn best_effort_queues_what_it_can() {
+    let harness = harness(8, vec![tenant("alice", &[], 1)]);
+
+    let (status, body) = submit_batch(
+        &harness,
+        "best_effort",
+        vec![spec("alice"), spec("nobody"), spec("alice")],
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    assert_eq!(
+        (body["accepted"].as_u64(), body["rejected"].as_u64()),
+        (Some(2), Some(1))
+    );
+    let items = body["jobs"].as_array().unwrap();
+    assert_eq!(items[1]["status"], 404);
+    assert_eq!(error_code(&items[1]), "unknown_tenant");
+
+    for item in [&items[0], &items[2]] {
+        assert_eq!(item["status"], 202);
+        let job = harness.wait_for(item["job_id"].as_str().unwrap()).await;
+        assert_eq!(status_name(&job), "finished");
+    }
+}
+
+#[tokio::test]
+async fn all_or_nothing_rejects_the_whole_batch() {
+    let harness = harness(8, vec![tenant("alice", &[], 1)]);
+
+    let (status, body) = submit_batch(
+        &harness,
+        "all_or_nothing",
+        vec![spec("alice"), spec("nobody"), spec("alice")],
+    )
+    .await;
+
+    // Answers like the rejected job would have
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+    assert_eq!(body["accepted"], 0);
+    let codes: Vec<&str> = body["jobs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(error_code)
+        .collect();
+    assert_eq!(codes, ["batch_aborted", "unknown_tenant", "batch_aborted"]);
+    assert_eq!(listed(&harness).await, 0);
+
+    let (status, body) = submit_batch(&harness, "all_or_nothing", vec![spec("alice"); 3]).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    assert_eq!(body["accepted"], 3);
+}
+
+#[tokio::test]
+async fn all_or_nothing_counts_the_batch_against_the_rate_limit() {
+    let mut limited = tenant("alice", &[], 1);
+    limited["rate_limit"] = json!(3);
+    let harness = harness(8, vec![limited]);
+
+    let (status, body) = submit_batch(&harness, "all_or_nothing", vec![spec("alice"); 4]).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "{}", body);
+    assert_eq!(error_code(&body["jobs"][3]), "rate_limit_exceeded");
+    assert_eq!(listed(&harness).await, 0);
+
+    // The three that fit were given back
+    let (status, body) = submit_batch(&harness, "all_or_nothing", vec![spec("alice"); 3]).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    let (status, _) = harness.submit("alice", "simple-compute", &[]).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn all_or_nothing_needs_queue_space_for_every_job() {
+    let harness = harness(2, vec![tenant("alice", &[], 1)]);
+
+    let (status, body) = submit_batch(&harness, "all_or_nothing", vec![spec("alice"); 3]).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+    assert_eq!(error_code(&body["jobs"][2]), "queue_full");
+    assert_eq!(listed(&harness).await, 0);
+
+    let (status, body) = submit_batch(&harness, "best_effort", vec![spec("alice"); 3]).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    assert!(body["accepted"].as_u64().unwrap() >= 2, "{}", body);
+}
+
+#[tokio::test]
+async fn batch_size_is_bounded() {
+    let harness = harness(8, vec![tenant("alice", &[], 1)]);
+
+    for jobs in [Vec::new(), vec![spec("alice"); 101]] {
+        let (status, body) = submit_batch(&harness, "best_effort", jobs).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error_code(&body), "invalid_batch");
+    }
+}
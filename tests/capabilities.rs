@@ -0,0 +1,261 @@
+mod common;
+
+use serde_json::json;
+
+use common::{Gate, Harness, config, egress_module, output, status_name, tenant};
+
+const ALL: &[&str] = &[
+    "gpu.compute",
+    "logging",
+    "network.egress",
+    "wasi",
+    "wasi.fs",
+    "wasi.clocks",
+    "wasi.random",
+];
+
+fn harness(allowed_host: &str) -> Harness {
+    Harness::start(
+        &config(8, &[4], allowed_host),
+        json!({"tenants": [tenant("alice", ALL, 4)]}),
+    )
+}
+
+#[tokio::test]
+async fn plain_module_needs_no_capabilities() {
+    let harness = harness("127.0.0.1:1");
+    harness.install_example("simple-compute");
+
+    let job = harness.run("alice", "simple-compute", &[]).await;
+    assert_eq!(output(&job), "60");
+}
+
+#[tokio::test]
+async fn gpu_compute() {
+    let harness = harness("127.0.0.1:1");
+    harness.install_example("gpu-compute");
+
+    let job = harness.run("alice", "gpu-compute", &["gpu.compute"]).await;
+    assert_eq!(output(&job), "42");
+}
+
+#[tokio::test]
+async fn imports_without_the_capability_fail() {
+    let harness = harness("127.0.0.1:1");
+    harness.install_example("gpu-compute");
+
+    let job_id = harness.accepted("alice", "gpu-compute", &[]).await;
+    let job = harness.wait_for(&job_id).await;
+
+    assert_eq!(status_name(&job), "failed");
+    let reason = job["status"]["failed"].as_str().unwrap();
+    assert!(reason.contains("Capability violation"), "{}", reason);
+}
+
+#[tokio::test]
+async fn logging() {
+    let harness = harness("127.0.0.1:1");
+    harness.install_example("logging-test");
+
+    let job = harness.run("alice", "logging-test", &["logging"]).await;
+    assert_eq!(output(&job), "100");
+
+    let job_id = job["job_id"].as_str().unwrap();
+    let (_, logs) = harness.get(&format!("/jobs/{}/logs", job_id)).await;
+    assert_eq!(logs["logs"], json!(["Hello from WASM!"]));
+}
+
+#[tokio::test]
+async fn network_egress() {
+    let gate = Gate::opened();
+    let harness = harness(&gate.host());
+    harness.install("egress", &egress_module(&gate.url()));
+
+    let job = harness.run("alice", "egress", &["network.egress"]).await;
+
+    assert_eq!(output(&job), "200");
+    assert_eq!(gate.bodies(), vec!["Hello from WASM!"]);
+    assert_eq!(job["result"]["egress_log"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn network_egress_outside_the_allowlist() {
+    let gate = Gate::opened();
+    let harness = harness("127.0.0.1:1");
+    harness.install("egress", &egress_module(&gate.url()));
+
+    let job = harness.run("alice", "egress", &["network.egress"]).await;
+
+    // -2: host not allowlisted
+    assert_eq!(output(&job), "-2");
+    assert!(gate.bodies().is_empty());
+}
+
+#[tokio::test]
+async fn network_egress_refuses_oversized_bodies_before_reading_them() {
+    let gate = Gate::opened();
+    let harness = harness(&gate.host());
+    // Far past both the grant's max_body_bytes and the guest's one page
+    harness.install(
+        "egress-huge",
+        &egress_module(&gate.url()).replace(
+            "i32.const 16         ;; body_len",
+            "i32.const 2147483647 ;; body_len",
+        ),
+    );
+    // Within max_body_bytes but running off the end of guest memory
+    harness.install(
+        "egress-overrun",
+        &egress_module(&gate.url()).replace(
+            "i32.const 192        ;; body_ptr",
+            "i32.const 65530      ;; body_ptr",
+        ),
+    );
+
+    let job = harness
+        .run("alice", "egress-huge", &["network.egress"])
+        .await;
+    // -3: request body too large
+    assert_eq!(output(&job), "-3");
+    let log = job["result"]["egress_log"].as_array().unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0]["request_bytes"], 2147483647);
+
+    let job = harness
+        .run("alice", "egress-overrun", &["network.egress"])
+        .await;
+    assert_eq!(output(&job), "-1");
+
+    assert!(gate.bodies().is_empty());
+}
+
+#[tokio::test]
+async fn network_egress_response_outside_guest_memory() {
+    let gate = Gate::opened();
+    let harness = harness(&gate.host());
+    // Negative, so past the end of any 32-bit memory
+    harness.install(
+        "egress-bad-resp",
+        &egress_module(&gate.url()).replace(
+            "i32.const 256        ;; resp_ptr",
+            "i32.const -8         ;; resp_ptr",
+        ),
+    );
+
+    let job = harness
+        .run("alice", "egress-bad-resp", &["network.egress"])
+        .await;
+    assert_eq!(output(&job), "-1");
+    assert_eq!(gate.bodies(), vec!["Hello from WASM!"]);
+}
+
+#[tokio::test]
+async fn virtual_device() {
+    let harness = harness("127.0.0.1:1");
+    harness.install_example("vgpu-test");
+
+    let job = harness.run("alice", "vgpu-test", &["gpu.compute"]).await;
+    assert_eq!(output(&job), "110");
+    assert!(job["result"]["device_memory_peak"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn wasi_with_and_without_scratch_dir() {
+    let harness = harness("127.0.0.1:1");
+    harness.install_example("wasi-hello");
+
+    let job = harness.run("alice", "wasi-hello", &["wasi"]).await;
+    assert_eq!(output(&job), "Hello from WASI!\n");
+    assert_eq!(job["result"]["logs"], json!(["[stderr] no scratch dir"]));
+
+    let job = harness
+        .run("alice", "wasi-hello", &["wasi", "wasi.fs"])
+        .await;
+    assert_eq!(job["result"]["logs"], json!(["[stderr] scratch ok"]));
+}
+
+#[tokio::test]
+async fn wasip2_components_get_wasi_under_the_wasi_capabilities() {
+    let harness = harness("127.0.0.1:1");
+    harness.install("wasip2-args", include_str!("fixtures/wasip2-args.wat"));
+    // Also imports the monotonic clock, without using it
+    harness.install(
+        "wasip2-clock",
+        &include_str!("fixtures/wasip2-args.wat").replacen(
+            "  (import \"wasi:cli",
+            "  (import \"wasi:clocks/monotonic-clock@0.2.0\" (instance\n    \
+             (export \"now\" (func (result u64)))))\n  (import \"wasi:cli",
+            1,
+        ),
+    );
+    let args = json!({"payload": {"wasi": {"args": ["from-payload"]}}});
+
+    let (_, body) = harness
+        .submit_with("alice", "wasip2-args", &["wasi"], args.clone())
+        .await;
+    let job = harness.wait_for(body["job_id"].as_str().unwrap()).await;
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    assert_eq!(output(&job), "from-payload");
+
+    // Without `wasi`, and clocks only with `wasi.clocks`
+    for (module_id, capabilities, missing) in [
+        ("wasip2-args", &[][..], "wasi"),
+        ("wasip2-clock", &["wasi"][..], "wasi.clocks"),
+    ] {
+        let job_id = harness.accepted("alice", module_id, capabilities).await;
+        let job = harness.wait_for(&job_id).await;
+        assert_eq!(status_name(&job), "failed");
+        let reason = job["status"]["failed"].as_str().unwrap();
+        assert!(
+            reason.contains(&format!("needs {}, not granted", missing)),
+            "{}",
+            reason
+        );
+    }
+    let job = harness
+        .run("alice", "wasip2-clock", &["wasi", "wasi.clocks"])
+        .await;
+    assert_eq!(output(&job), "");
+}
+
+#[tokio::test]
+async fn tensors() {
+    let harness = harness("127.0.0.1:1");
+    harness.install_example("tensor-scale");
+
+    let (_, body) = harness
+        .submit_with(
+            "alice",
+            "tensor-scale",
+            &[],
+            json!({"tensors": {"x": {"dtype": "f32", "shape": [1, 4], "data": [1, 2, 3, 4]}}}),
+        )
+        .await;
+    let job = harness.wait_for(body["job_id"].as_str().unwrap()).await;
+
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    assert_eq!(
+        job["result"]["tensors"]["y"],
+        json!({"dtype": "f32", "shape": [1, 4], "data": [2.0, 4.0, 6.0, 8.0]})
+    );
+}
+
+#[tokio::test]
+async fn component_logging_and_compute() {
+    let harness = harness("127.0.0.1:1");
+    harness.install_example("component-hello");
+
+    let (_, body) = harness
+        .submit_with(
+            "alice",
+            "component-hello",
+            &["logging", "gpu.compute"],
+            json!({"payload": {"greeting": "hi"}}),
+        )
+        .await;
+    let job = harness.wait_for(body["job_id"].as_str().unwrap()).await;
+
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    assert_eq!(output(&job), r#"{"greeting":"hi"}"#);
+    assert!(!job["result"]["logs"].as_array().unwrap().is_empty());
+}
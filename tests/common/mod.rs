@@ -0,0 +1,457 @@
+//! In-process harness: a controller built from an injected config and tenant
+//! set, driven through its `Router` without a listener.
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode, header};
+use gpu_sandbox_controller::Controller;
+use gpu_sandbox_controller::config::Config;
+use gpu_sandbox_controller::state::AppState;
+use gpu_sandbox_controller::tenant::Tenant;
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+/// How long `wait_for` polls a job before giving up.
+const JOB_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A config with every capability, `queue_length` and one device per entry
+/// of `devices` (compute slices; 10 GB each). Egress may reach
+/// `allowed_host`.
+pub fn config(queue_length: usize, devices: &[u32], allowed_host: &str) -> String {
+    let mut config = format!(
+        r#"
+queue_length = {queue_length}
+default_profile = "1g.10gb"
+
+[shutdown]
+grace_secs = 1
+
+[profiles."1g.10gb"]
+compute_slices = 1
+memory_gb = 10
+
+[profiles."2g.20gb"]
+compute_slices = 2
+memory_gb = 20
+
+[capabilities."gpu.compute"]
+description = "GPU compute"
+version = "0.1.0"
+
+[capabilities.logging]
+description = "Guest logging"
+version = "0.1.0"
+params = {{ max_bytes = 4096 }}
+
+[capabilities."network.egress"]
+description = "Outbound HTTP"
+version = "0.1.0"
+params = {{ allowed_hosts = ["{allowed_host}"], timeout_ms = 10000 }}
+
+[capabilities.wasi]
+description = "WASI preview1"
+version = "0.1.0"
+
+[capabilities."wasi.fs"]
+description = "Scratch directory"
+version = "0.1.0"
+
+[capabilities."wasi.clocks"]
+description = "Clocks"
+version = "0.1.0"
+
+[capabilities."wasi.random"]
+description = "Random bytes"
+version = "0.1.0"
+"#
+    );
+
+    for (i, slices) in devices.iter().enumerate() {
+        config.push_str(&format!(
+            "\n[[devices]]\ndevice_id = \"gpu{}\"\ncompute_slices = {}\nmemory_gb = {}\n",
+            i,
+            slices,
+            slices * 10
+        ));
+    }
+    config
+}
+
+/// A tenant granted `capabilities`, limited to `slices` compute slices.
+pub fn tenant(tenant_id: &str, capabilities: &[&str], slices: u32) -> Value {
+    json!({
+        "tenant_id": tenant_id,
+        "allowed_capabilities": capabilities,
+        "gpu_limit": {"compute_slices": slices, "memory_gb": slices * 10},
+        "status": "active",
+    })
+}
+
+pub struct Harness {
+    pub state: AppState,
+    router: Router,
+    modules_dir: PathBuf,
+    _dir: TempDir,
+}
+
+impl Harness {
+    /// Starts a controller from `config` (TOML) and `tenants` (the
+    /// `tenants.json` document). Modules and models live in a temporary
+    /// directory.
+    pub fn start(config: &str, tenants: Value) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let modules_dir = dir.path().join("modules");
+        std::fs::create_dir(&modules_dir).unwrap();
+
+        // Top-level keys have to come before the config's first table
+        let config = format!(
+            "modules_dir = {:?}\nmodels_dir = {:?}\n{}",
+            modules_dir,
+            dir.path().join("models"),
+            config
+        );
+        let config = Config::parse(&config).unwrap();
+        let (tenants, organizations) = Tenant::parse_all(&tenants.to_string()).unwrap();
+
+        let controller = Controller::start(&config, tenants, organizations).unwrap();
+        Harness {
+            state: controller.state,
+            router: controller.router,
+            modules_dir,
+            _dir: dir,
+        }
+    }
+
+    /// Compiles `wat` and stores it as module `module_id`.
+    pub fn install(&self, module_id: &str, wat: &str) {
+        let wasm = wat::parse_str(wat).unwrap();
+        std::fs::write(self.modules_dir.join(format!("{}.wasm", module_id)), wasm).unwrap();
+    }
+
+    /// Compiles `modules/{module_id}.wat` from the repository.
+    pub fn install_example(&self, module_id: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("modules")
+            .join(format!("{}.wat", module_id));
+        self.install(module_id, &std::fs::read_to_string(path).unwrap());
+    }
+
+    /// Installs `tensor-scale` (doubles "x" into "y") and `tensor-rescale`
+    /// (doubles "y" into "z"), both with signatures.
+    pub fn install_tensor_modules(&self) {
+        let wat = std::fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("modules/tensor-scale.wat"),
+        )
+        .unwrap();
+        self.install("tensor-scale", &wat);
+        let rescale = wat
+            .replace(r#"(data (i32.const 0) "x")"#, r#"(data (i32.const 0) "y")"#)
+            .replace(r#"(data (i32.const 8) "y")"#, r#"(data (i32.const 8) "z")"#);
+        self.install("tensor-rescale", &rescale);
+
+        for (module_id, input) in [("tensor-scale", "x"), ("tensor-rescale", "y")] {
+            self.write_module_file(
+                &format!("{}.signature.json", module_id),
+                &json!({"inputs": {input: {"dtype": "f32", "shape": [null, 4]}}}).to_string(),
+            );
+        }
+    }
+
+    pub fn write_module_file(&self, name: &str, contents: &str) {
+        std::fs::write(self.modules_dir.join(name), contents).unwrap();
+    }
+
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let builder = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => builder.body(Body::empty()),
+        }
+        .unwrap();
+        self.send(request).await
+    }
+
+    async fn send(&self, request: Request<Body>) -> (StatusCode, Value) {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+        (status, body)
+    }
+
+    /// POSTs `body` as raw bytes, with `token` as the admin bearer token.
+    pub async fn upload(
+        &self,
+        uri: &str,
+        body: Vec<u8>,
+        token: Option<&str>,
+    ) -> (StatusCode, Value) {
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/octet-stream");
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        self.send(builder.body(Body::from(body)).unwrap()).await
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        self.request(Method::GET, uri, None).await
+    }
+
+    /// Submits `module_id` for `tenant_id`; `extra` is merged into the request.
+    pub async fn submit_with(
+        &self,
+        tenant_id: &str,
+        module_id: &str,
+        capabilities: &[&str],
+        extra: Value,
+    ) -> (StatusCode, Value) {
+        let mut body = json!({
+            "tenant_id": tenant_id,
+            "module_id": module_id,
+            "payload": {},
+            "capabilities": capabilities,
+        });
+        if let (Some(body), Value::Object(extra)) = (body.as_object_mut(), extra) {
+            body.extend(extra);
+        }
+        self.request(Method::POST, "/jobs", Some(body)).await
+    }
+
+    pub async fn submit(
+        &self,
+        tenant_id: &str,
+        module_id: &str,
+        capabilities: &[&str],
+    ) -> (StatusCode, Value) {
+        self.submit_with(tenant_id, module_id, capabilities, json!({}))
+            .await
+    }
+
+    /// Submits a job that must be accepted and returns its id.
+    pub async fn accepted(
+        &self,
+        tenant_id: &str,
+        module_id: &str,
+        capabilities: &[&str],
+    ) -> String {
+        let (status, body) = self.submit(tenant_id, module_id, capabilities).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+        body["job_id"].as_str().unwrap().to_string()
+    }
+
+    pub async fn job(&self, job_id: &str) -> Value {
+        let (status, job) = self.get(&format!("/jobs/{}", job_id)).await;
+        assert_eq!(status, StatusCode::OK, "{}", job);
+        job
+    }
+
+    /// Polls `/readyz` until the controller reports ready.
+    pub async fn wait_ready(&self) {
+        let deadline = tokio::time::Instant::now() + JOB_TIMEOUT;
+        loop {
+            let (status, body) = self.get("/readyz").await;
+            if status == StatusCode::OK {
+                return;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "never became ready: {}",
+                body
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    /// Polls until the job has status `wanted`.
+    pub async fn wait_for_status(&self, job_id: &str, wanted: &str) -> Value {
+        let deadline = tokio::time::Instant::now() + JOB_TIMEOUT;
+        loop {
+            let job = self.job(job_id).await;
+            if status_name(&job) == wanted {
+                return job;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "job {} never reached {}: {}",
+                job_id,
+                wanted,
+                job["status"]
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    /// Polls until the job finished, failed or was cancelled.
+    pub async fn wait_for(&self, job_id: &str) -> Value {
+        let deadline = tokio::time::Instant::now() + JOB_TIMEOUT;
+        loop {
+            let job = self.job(job_id).await;
+            if !matches!(status_name(&job), "queued" | "running") {
+                return job;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "job {} still {}",
+                job_id,
+                status_name(&job)
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    /// Runs a job to completion and returns it; it must finish successfully.
+    pub async fn run(&self, tenant_id: &str, module_id: &str, capabilities: &[&str]) -> Value {
+        let job_id = self.accepted(tenant_id, module_id, capabilities).await;
+        let job = self.wait_for(&job_id).await;
+        assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+        job
+    }
+}
+
+/// `queued`, `running`, `finished`, `failed` or `cancelled`.
+pub fn status_name(job: &Value) -> &str {
+    match &job["status"] {
+        Value::String(name) => name,
+        Value::Object(status) => status.keys().next().unwrap(),
+        other => panic!("unexpected status {}", other),
+    }
+}
+
+/// `result.output` as text.
+pub fn output(job: &Value) -> String {
+    let bytes: Vec<u8> = serde_json::from_value(job["result"]["output"].clone()).unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+pub fn error_code(body: &Value) -> &str {
+    body["error"].as_str().unwrap_or_default()
+}
+
+/// A module that POSTs "Hello from WASM!" to `url` and returns the status.
+pub fn egress_module(url: &str) -> String {
+    include_str!("../fixtures/egress.wat")
+        .replace("{url_len}", &url.len().to_string())
+        .replace("{url}", url)
+}
+
+/// An HTTP server that holds every request until `open` is called, so egress
+/// jobs stay running as long as a test needs them to.
+pub struct Gate {
+    port: u16,
+    shared: Arc<GateState>,
+}
+
+#[derive(Default)]
+struct GateState {
+    open: Mutex<bool>,
+    opened: Condvar,
+    bodies: Mutex<Vec<String>>,
+}
+
+impl Gate {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let shared = Arc::new(GateState::default());
+
+        let accepting = shared.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = accepting.clone();
+                std::thread::spawn(move || serve(stream, &shared));
+            }
+        });
+
+        Gate { port, shared }
+    }
+
+    /// Starts with requests answered right away.
+    pub fn opened() -> Self {
+        let gate = Self::start();
+        gate.open();
+        gate
+    }
+
+    pub fn host(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}/ingest", self.host())
+    }
+
+    pub fn open(&self) {
+        *self.shared.open.lock().unwrap() = true;
+        self.shared.opened.notify_all();
+    }
+
+    /// Request bodies received so far.
+    pub fn bodies(&self) -> Vec<String> {
+        self.shared.bodies.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Gate {
+    fn drop(&mut self) {
+        self.open();
+    }
+}
+
+fn serve(stream: TcpStream, shared: &GateState) {
+    let mut reader = BufReader::new(stream);
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = vec![0; content_length];
+    if reader.read_exact(&mut body).is_err() {
+        return;
+    }
+    shared
+        .bodies
+        .lock()
+        .unwrap()
+        .push(String::from_utf8_lossy(&body).into_owned());
+
+    let mut open = shared.open.lock().unwrap();
+    while !*open {
+        open = shared.opened.wait(open).unwrap();
+    }
+    drop(open);
+
+    let _ = reader
+        .into_inner()
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok");
+}
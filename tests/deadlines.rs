@@ -0,0 +1,187 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+use time::{Duration, OffsetDateTime};
+
+use common::{Gate, Harness, config, egress_module, error_code, status_name, tenant};
+
+/// One slice in total, so a running upload holds everything back.
+fn harness(gate: &Gate, dispatch: &str) -> Harness {
+    let harness = Harness::start(
+        &format!(
+            "{}\n[dispatch]\n{}\n",
+            config(8, &[1], &gate.host()),
+            dispatch
+        ),
+        json!({"tenants": [tenant("alice", &["network.egress"], 1)]}),
+    );
+    harness.install_example("simple-compute");
+    harness.install("upload", &egress_module(&gate.url()));
+    harness
+}
+
+/// Time `millis` from now, as the API takes it.
+fn from_now(millis: i64) -> Value {
+    serde_json::to_value(OffsetDateTime::now_utc() + Duration::milliseconds(millis)).unwrap()
+}
+
+fn timestamp(value: &Value) -> OffsetDateTime {
+    serde_json::from_value(value.clone()).unwrap()
+}
+
+async fn block(harness: &Harness) -> String {
+    let (status, body) = harness.submit("alice", "upload", &["network.egress"]).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    let job_id = body["job_id"].as_str().unwrap().to_string();
+    harness.wait_for_status(&job_id, "running").await;
+    job_id
+}
+
+async fn submit(harness: &Harness, extra: Value) -> String {
+    let (status, body) = harness
+        .submit_with("alice", "simple-compute", &[], extra)
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    body["job_id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn queued_jobs_expire_at_their_deadline() {
+    let gate = Gate::start();
+    let harness = harness(&gate, "default_runtime_secs = 0");
+    let blocker = block(&harness).await;
+
+    let job_id = submit(&harness, json!({"deadline": from_now(1000)})).await;
+    let job = harness.wait_for(&job_id).await;
+    assert_eq!(status_name(&job), "expired");
+    assert!(job["started_at"].is_null());
+    assert!(!job["finished_at"].is_null());
+
+    gate.open();
+    let blocker = harness.wait_for(&blocker).await;
+    assert_eq!(status_name(&blocker), "finished");
+}
+
+#[tokio::test]
+async fn jobs_wait_for_not_before() {
+    let gate = Gate::opened();
+    let harness = harness(&gate, "default_runtime_secs = 0");
+
+    let not_before = from_now(1000);
+    let job_id = submit(&harness, json!({"not_before": not_before})).await;
+    assert_eq!(status_name(&harness.job(&job_id).await), "queued");
+
+    let job = harness.wait_for(&job_id).await;
+    assert_eq!(status_name(&job), "finished");
+    assert!(timestamp(&job["started_at"]) >= timestamp(&not_before));
+}
+
+#[tokio::test]
+async fn edf_dispatches_the_earliest_deadline_first() {
+    let gate = Gate::start();
+    let harness = harness(&gate, "policy = \"edf\"\ndefault_runtime_secs = 0");
+    let blocker = block(&harness).await;
+
+    let late = submit(&harness, json!({"deadline": from_now(60_000)})).await;
+    let none = submit(&harness, json!({})).await;
+    let early = submit(&harness, json!({"deadline": from_now(30_000)})).await;
+
+    gate.open();
+    harness.wait_for(&blocker).await;
+    let mut started = Vec::new();
+    for job_id in [&early, &late, &none] {
+        let job = harness.wait_for(job_id).await;
+        assert_eq!(status_name(&job), "finished");
+        started.push(timestamp(&job["started_at"]));
+    }
+    assert!(started.is_sorted(), "{:?}", started);
+}
+
+#[tokio::test]
+async fn infeasible_deadlines_are_rejected() {
+    let gate = Gate::start();
+    let harness = harness(&gate, "default_runtime_secs = 30");
+    let blocker = block(&harness).await;
+
+    // 30s left of the upload, then 30s of its own
+    let (status, body) = harness
+        .submit_with(
+            "alice",
+            "simple-compute",
+            &[],
+            json!({"deadline": from_now(10_000)}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert_eq!(error_code(&body), "infeasible_deadline");
+    submit(&harness, json!({"deadline": from_now(120_000)})).await;
+
+    // Once simple-compute has run, its estimate is what it took
+    gate.open();
+    harness.wait_for(&blocker).await;
+    harness.wait_for(&submit(&harness, json!({})).await).await;
+    submit(&harness, json!({"deadline": from_now(10_000)})).await;
+
+    for times in [
+        json!({"deadline": from_now(-1000)}),
+        json!({"not_before": from_now(20_000), "deadline": from_now(10_000)}),
+    ] {
+        let (status, body) = harness
+            .submit_with("alice", "simple-compute", &[], times)
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(error_code(&body), "invalid_deadline");
+    }
+}
+
+#[tokio::test]
+async fn deadlines_past_the_end_of_time_are_infeasible() {
+    let gate = Gate::start();
+    let harness = harness(&gate, &format!("default_runtime_secs = {}", i64::MAX));
+    let blocker = block(&harness).await;
+
+    // The estimate runs past the last representable date, ~6000 years away
+    let (status, body) = harness
+        .submit_with(
+            "alice",
+            "simple-compute",
+            &[],
+            json!({"deadline": from_now(200_000_000_000_000)}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert_eq!(error_code(&body), "infeasible_deadline");
+
+    gate.open();
+    harness.wait_for(&blocker).await;
+}
+
+#[tokio::test]
+async fn children_wait_for_their_parents_estimated_finish() {
+    let gate = Gate::opened();
+    let harness = harness(&gate, "default_runtime_secs = 1");
+
+    // The queue is short, but the parent can't start for 30s
+    let parent = submit(&harness, json!({"not_before": from_now(30_000)})).await;
+    let child = |deadline: i64| json!({"depends_on": [parent], "deadline": from_now(deadline)});
+
+    let (status, body) = harness
+        .submit_with("alice", "simple-compute", &[], child(10_000))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert_eq!(error_code(&body), "infeasible_deadline");
+
+    let child = submit(&harness, child(60_000)).await;
+    // Grandchildren wait for the whole chain
+    let (status, body) = harness
+        .submit_with(
+            "alice",
+            "simple-compute",
+            &[],
+            json!({"depends_on": [child], "deadline": from_now(20_000)}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert_eq!(error_code(&body), "infeasible_deadline");
+}
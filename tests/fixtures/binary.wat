@@ -0,0 +1,13 @@
+;; WASI command that writes the bytes ff 00 c3 28, which aren't UTF-8.
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (data (i32.const 64) "\ff\00\c3\28")
+
+  ;; iovec at 0 pointing at the 4 bytes at 64; the byte count goes to 16
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 64))
+    (i32.store (i32.const 4) (i32.const 4))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 16)))))
@@ -0,0 +1,24 @@
+;; POSTs "Hello from WASM!" to {url} and returns the HTTP status (or a
+;; negative egress error). The harness fills in the URL before compiling.
+(module
+  (import "env" "http_post"
+    (func $http_post (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  (data (i32.const 0) "{url}")
+  (data (i32.const 128) "Content-Type: text/plain")
+  (data (i32.const 192) "Hello from WASM!")
+
+  (func (export "run") (result i32)
+    i32.const 0          ;; url_ptr
+    i32.const {url_len}  ;; url_len
+    i32.const 128        ;; headers_ptr
+    i32.const 24         ;; headers_len
+    i32.const 192        ;; body_ptr
+    i32.const 16         ;; body_len
+    i32.const 256        ;; resp_ptr
+    i32.const 1024       ;; resp_cap
+    call $http_post
+  )
+)
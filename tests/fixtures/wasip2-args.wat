@@ -0,0 +1,59 @@
+;; wasip2 component that returns its first argument after the program name,
+;; read through wasi:cli/environment.
+(component
+  (import "wasi:cli/environment@0.2.0" (instance $environment
+    (export "get-arguments" (func (result (list string))))))
+
+  (core module $Libc
+    (memory (export "memory") 1)
+    (global $heap (mut i32) (i32.const 1024))
+    (func (export "realloc") (param i32 i32) (param $align i32) (param $size i32) (result i32)
+      (local $ptr i32)
+      (local.set $ptr
+        (i32.and
+          (i32.add (global.get $heap) (i32.sub (local.get $align) (i32.const 1)))
+          (i32.sub (i32.const 0) (local.get $align))))
+      (global.set $heap (i32.add (local.get $ptr) (local.get $size)))
+      (local.get $ptr)))
+  (core instance $libc (instantiate $Libc))
+  (alias core export $libc "memory" (core memory $memory))
+  (alias core export $libc "realloc" (core func $realloc))
+
+  (core func $get-arguments (canon lower (func $environment "get-arguments")
+    (memory $memory) (realloc $realloc)))
+
+  (core module $Main
+    (import "libc" "memory" (memory 1))
+    (import "host" "get-arguments" (func $get-arguments (param i32)))
+
+    ;; The argument list goes to 96 as (ptr, len) of (ptr, len) strings; the
+    ;; result is written at 80 as discriminant + (ptr, len)
+    (func (export "run") (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+      (call $get-arguments (i32.const 96))
+      (i32.store8 (i32.const 80) (i32.const 0))
+      (if (i32.lt_u (i32.load (i32.const 100)) (i32.const 2))
+        (then
+          (i32.store (i32.const 84) (i32.const 0))
+          (i32.store (i32.const 88) (i32.const 0)))
+        (else
+          (i32.store (i32.const 84) (i32.load (i32.add (i32.load (i32.const 96)) (i32.const 8))))
+          (i32.store (i32.const 88) (i32.load (i32.add (i32.load (i32.const 96)) (i32.const 12))))))
+      (i32.const 80)))
+
+  (core instance $main (instantiate $Main
+    (with "libc" (instance $libc))
+    (with "host" (instance (export "get-arguments" (func $get-arguments))))))
+
+  (type $job-input' (record
+    (field "job-id" string)
+    (field "tenant-id" string)
+    (field "module-id" string)
+    (field "payload" string)))
+  (type $job-output' (record (field "data" (list u8))))
+  (export $job-input "job-input" (type $job-input'))
+  (export $job-output "job-output" (type $job-output'))
+
+  (func (export "run") (param "input" $job-input) (result (result $job-output (error string)))
+    (canon lift (core func $main "run")
+      (memory $memory) (realloc $realloc)))
+)
@@ -0,0 +1,176 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use common::{Harness, config, error_code, output, status_name, tenant};
+
+fn harness() -> Harness {
+    let harness = Harness::start(
+        &config(8, &[4], "127.0.0.1:1"),
+        json!({"tenants": [tenant("alice", &["wasi", "logging", "gpu.compute"], 1)]}),
+    );
+    harness.install_example("simple-compute");
+    harness.install_example("component-hello");
+    harness.install("echo", include_str!("fixtures/echo.wat"));
+    harness.install("binary", include_str!("fixtures/binary.wat"));
+    harness.install(
+        "trap",
+        r#"(module (func (export "run") (result i32) unreachable))"#,
+    );
+    harness
+}
+
+async fn run_pipeline(harness: &Harness, stages: &[&str], extra: Value) -> Value {
+    let mut request = json!({ "stages": &stages[1..] });
+    if let (Some(request), Value::Object(extra)) = (request.as_object_mut(), extra) {
+        request.extend(extra);
+    }
+    let (status, body) = harness
+        .submit_with(
+            "alice",
+            stages[0],
+            &["wasi", "logging", "gpu.compute"],
+            request,
+        )
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    harness.wait_for(body["job_id"].as_str().unwrap()).await
+}
+
+fn stage_statuses(job: &Value) -> Vec<String> {
+    job["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stage| status_name(stage).to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn each_stage_reads_the_output_of_the_one_before() {
+    let harness = harness();
+
+    // simple-compute returns 60, which echo copies from stdin
+    let job = run_pipeline(&harness, &["simple-compute", "echo", "echo"], json!({})).await;
+
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    assert_eq!(output(&job), "60");
+    assert_eq!(stage_statuses(&job), ["finished"; 3]);
+    for (stage, module_id) in
+        job["stages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .zip(["simple-compute", "echo", "echo"])
+    {
+        assert_eq!(stage["module_id"], module_id);
+        assert!(!stage["started_at"].is_null() && !stage["duration"].is_null());
+    }
+    // All stages ran on the job's one placement
+    assert_eq!(job["placements"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn output_tensors_are_the_next_stages_inputs() {
+    let harness = harness();
+    harness.install_tensor_modules();
+
+    let x = json!({"x": {"dtype": "f32", "shape": [1, 4], "data": [1, 2, 3, 4]}});
+    let job = run_pipeline(
+        &harness,
+        &["tensor-scale", "tensor-rescale"],
+        json!({"tensors": x}),
+    )
+    .await;
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    assert_eq!(
+        job["result"]["tensors"]["z"],
+        json!({"dtype": "f32", "shape": [1, 4], "data": [4.0, 8.0, 12.0, 16.0]})
+    );
+
+    // tensor-scale takes "x", not the "y" it outputs
+    let job = run_pipeline(
+        &harness,
+        &["tensor-scale", "tensor-scale"],
+        json!({"tensors": x}),
+    )
+    .await;
+    assert_eq!(status_name(&job), "failed");
+    assert_eq!(stage_statuses(&job), ["finished", "failed"]);
+}
+
+#[tokio::test]
+async fn a_failed_stage_fails_the_job_and_skips_the_rest() {
+    let harness = harness();
+
+    let job = run_pipeline(&harness, &["simple-compute", "trap", "echo"], json!({})).await;
+
+    assert_eq!(status_name(&job), "failed");
+    let reason = job["status"]["failed"].as_str().unwrap();
+    assert!(reason.contains("Stage 1 (trap) failed"), "{}", reason);
+    assert_eq!(stage_statuses(&job), ["finished", "failed", "cancelled"]);
+    assert!(job["stages"][2]["started_at"].is_null());
+}
+
+#[tokio::test]
+async fn stage_count_is_bounded() {
+    let harness = harness();
+
+    let (status, body) = harness
+        .submit_with(
+            "alice",
+            "simple-compute",
+            &[],
+            json!({"stages": vec!["echo"; 16]}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error_code(&body), "invalid_stages");
+}
+
+#[tokio::test]
+async fn binary_stage_output_is_passed_on_unchanged() {
+    let harness = harness();
+    let binary = [0xff, 0x00, 0xc3, 0x28];
+
+    let job = run_pipeline(&harness, &["binary", "echo"], json!({})).await;
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    assert_eq!(job["result"]["output"], json!(binary));
+
+    // Components read the previous output base64-encoded from the payload;
+    // component-hello echoes its payload
+    let job = run_pipeline(
+        &harness,
+        &["binary", "component-hello"],
+        json!({"payload": {"keep": 1}}),
+    )
+    .await;
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    let payload: Value = serde_json::from_str(&output(&job)).unwrap();
+    assert_eq!(payload, json!({"keep": 1, "stage_input": "/wDDKA=="}));
+}
+
+#[tokio::test]
+async fn pipelines_need_an_object_payload() {
+    let harness = harness();
+
+    for payload in [json!(5), json!("text"), json!([1])] {
+        let (status, body) = harness
+            .submit_with(
+                "alice",
+                "simple-compute",
+                &[],
+                json!({"stages": ["echo"], "payload": payload}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(error_code(&body), "invalid_payload");
+    }
+
+    // Single-module jobs take any payload
+    let (status, _) = harness
+        .submit_with("alice", "simple-compute", &[], json!({"payload": 5}))
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
@@ -0,0 +1,253 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::{Method, StatusCode};
+use serde_json::{Value, json};
+
+use common::{Gate, Harness, config, egress_module, error_code, status_name, tenant};
+
+fn harness(allowed_host: &str, tenants: Vec<Value>) -> Harness {
+    let harness = Harness::start(
+        &config(8, &[4], allowed_host),
+        json!({ "tenants": tenants }),
+    );
+    harness.install_example("simple-compute");
+    harness
+}
+
+fn template(module_id: &str, capabilities: &[&str]) -> Value {
+    json!({
+        "tenant_id": "alice",
+        "module_id": module_id,
+        "payload": {},
+        "capabilities": capabilities,
+    })
+}
+
+async fn create(harness: &Harness, schedule: Value) -> String {
+    let (status, body) = harness
+        .request(Method::POST, "/schedules", Some(schedule))
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+    body["schedule_id"].as_str().unwrap().to_string()
+}
+
+async fn schedule(harness: &Harness, schedule_id: &str) -> Value {
+    let (status, body) = harness.get(&format!("/schedules/{}", schedule_id)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body
+}
+
+/// Polls until the schedule has `count` runs and returns them.
+async fn wait_for_runs(harness: &Harness, schedule_id: &str, count: usize) -> Vec<Value> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    loop {
+        let runs = schedule(harness, schedule_id).await["runs"]
+            .as_array()
+            .unwrap()
+            .clone();
+        if runs.len() >= count {
+            return runs;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "only {} runs",
+            runs.len()
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn submitted_job(run: &Value) -> &str {
+    run["outcome"]["submitted"]["job_id"]
+        .as_str()
+        .unwrap_or_else(|| panic!("not submitted: {}", run))
+}
+
+#[tokio::test]
+async fn interval_schedules_run_until_paused() {
+    let harness = harness("127.0.0.1:1", vec![tenant("alice", &[], 1)]);
+    let id = create(
+        &harness,
+        json!({"every_secs": 1, "job": template("simple-compute", &[])}),
+    )
+    .await;
+
+    for run in wait_for_runs(&harness, &id, 2).await {
+        let job = harness.wait_for(submitted_job(&run)).await;
+        assert_eq!(status_name(&job), "finished");
+    }
+
+    let (status, paused) = harness
+        .request(Method::POST, &format!("/schedules/{}/pause", id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(paused["paused"], true);
+    assert!(paused["next_run"].is_null());
+    let runs = paused["runs"].as_array().unwrap().len();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(
+        schedule(&harness, &id).await["runs"]
+            .as_array()
+            .unwrap()
+            .len(),
+        runs
+    );
+
+    let (_, resumed) = harness
+        .request(Method::POST, &format!("/schedules/{}/resume", id), None)
+        .await;
+    assert!(!resumed["next_run"].is_null());
+    wait_for_runs(&harness, &id, runs + 1).await;
+
+    let (_, listed) = harness.get("/schedules?tenant_id=alice").await;
+    assert_eq!(listed["schedules"].as_array().unwrap().len(), 1);
+    let (status, _) = harness
+        .request(Method::DELETE, &format!("/schedules/{}", id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = harness.get(&format!("/schedules/{}", id)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error_code(&body), "schedule_not_found");
+}
+
+#[tokio::test]
+async fn skip_leaves_a_running_previous_run_alone() {
+    let gate = Gate::start();
+    let harness = harness(&gate.host(), vec![tenant("alice", &["network.egress"], 1)]);
+    harness.install("upload", &egress_module(&gate.url()));
+
+    let id = create(
+        &harness,
+        json!({
+            "every_secs": 1,
+            "overlap": "skip",
+            "job": template("upload", &["network.egress"]),
+        }),
+    )
+    .await;
+
+    let runs = wait_for_runs(&harness, &id, 2).await;
+    let first = submitted_job(&runs[0]);
+    assert_eq!(runs[1]["outcome"], json!({"skipped": {"previous": first}}));
+    harness.wait_for_status(first, "running").await;
+}
+
+#[tokio::test]
+async fn cancel_previous_stops_the_running_previous_run() {
+    let gate = Gate::start();
+    let harness = harness(&gate.host(), vec![tenant("alice", &["network.egress"], 1)]);
+    harness.install("upload", &egress_module(&gate.url()));
+
+    let id = create(
+        &harness,
+        json!({
+            "every_secs": 1,
+            "overlap": "cancel_previous",
+            "job": template("upload", &["network.egress"]),
+        }),
+    )
+    .await;
+
+    let runs = wait_for_runs(&harness, &id, 1).await;
+    let first = submitted_job(&runs[0]).to_string();
+    harness.wait_for_status(&first, "running").await;
+
+    let runs = wait_for_runs(&harness, &id, 2).await;
+    assert_eq!(runs[1]["outcome"]["submitted"]["cancelled_previous"], first);
+    assert_eq!(status_name(&harness.job(&first).await), "cancelled");
+    let (status, _) = harness
+        .request(Method::POST, &format!("/schedules/{}/pause", id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Still inside http_post, so the cancelled run keeps its slice
+    let (_, reservations) = harness.get("/gpu/reservations").await;
+    assert!(
+        reservations
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|r| r["job_id"] == first),
+        "{}",
+        reservations
+    );
+
+    // Once its guest returns, the slice goes to the next run and the
+    // cancelled run stays cancelled
+    gate.open();
+    let next = harness.wait_for(submitted_job(&runs[1])).await;
+    assert_eq!(status_name(&next), "finished", "{}", next["status"]);
+    let job = harness.job(&first).await;
+    assert_eq!(status_name(&job), "cancelled");
+    assert!(job["result"].is_null());
+}
+
+#[tokio::test]
+async fn runs_go_through_the_rate_limit() {
+    let mut limited = tenant("alice", &[], 1);
+    limited["rate_limit"] = json!(1);
+    let harness = harness("127.0.0.1:1", vec![limited]);
+
+    let id = create(
+        &harness,
+        json!({
+            "every_secs": 1,
+            "overlap": "queue",
+            "job": template("simple-compute", &[]),
+        }),
+    )
+    .await;
+
+    let runs = wait_for_runs(&harness, &id, 2).await;
+    submitted_job(&runs[0]);
+    assert_eq!(
+        runs[1]["outcome"]["rejected"]["error"],
+        "rate_limit_exceeded"
+    );
+}
+
+#[tokio::test]
+async fn invalid_schedules_are_rejected() {
+    let harness = harness("127.0.0.1:1", vec![tenant("alice", &[], 1)]);
+    let job = template("simple-compute", &[]);
+
+    for trigger in [
+        json!({}),
+        json!({"cron": "* * * * *", "every_secs": 60}),
+        json!({"every_secs": 0}),
+        json!({"cron": "61 * * * *"}),
+        json!({"cron": "0 0 31 2 *"}),
+    ] {
+        let mut body = trigger.clone();
+        body["job"] = job.clone();
+        let (status, body) = harness
+            .request(Method::POST, "/schedules", Some(body))
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}: {}", trigger, body);
+        assert_eq!(error_code(&body), "invalid_schedule");
+    }
+
+    // The template gets the checks of POST /jobs up front
+    let mut job = template("simple-compute", &[]);
+    job["tenant_id"] = json!("nobody");
+    let (status, body) = harness
+        .request(
+            Method::POST,
+            "/schedules",
+            Some(json!({"cron": "0 * * * *", "job": job})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error_code(&body), "unknown_tenant");
+
+    let id = create(
+        &harness,
+        json!({"cron": "0 * * * *", "job": template("simple-compute", &[])}),
+    )
+    .await;
+    let created = schedule(&harness, &id).await;
+    assert_eq!(created["cron"], "0 * * * *");
+    assert!(!created["next_run"].is_null());
+}
@@ -0,0 +1,316 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use common::{Gate, Harness, config, egress_module, error_code, status_name, tenant};
+
+#[tokio::test]
+async fn rejects_invalid_submissions() {
+    let mut suspended = tenant("suspended", &[], 1);
+    suspended["status"] = json!("suspended");
+    let harness = Harness::start(
+        &config(8, &[2], "127.0.0.1:1"),
+        json!({"tenants": [tenant("alice", &["logging"], 1), suspended]}),
+    );
+    harness.install_example("simple-compute");
+    harness.install_example("tensor-scale");
+    harness.write_module_file(
+        "tensor-scale.signature.json",
+        r#"{"inputs": {"x": {"dtype": "f32", "shape": [null, 4]}}}"#,
+    );
+
+    let cases = [
+        (
+            json!({"tenant_id": "nobody"}),
+            StatusCode::NOT_FOUND,
+            "unknown_tenant",
+        ),
+        (
+            json!({"tenant_id": "suspended"}),
+            StatusCode::UNAUTHORIZED,
+            "unauthorized_tenant",
+        ),
+        (
+            json!({"backend": "cuda"}),
+            StatusCode::BAD_REQUEST,
+            "unknown_backend",
+        ),
+        (
+            json!({"backend": "mlp"}),
+            StatusCode::FORBIDDEN,
+            "unpermitted_backend",
+        ),
+        (
+            json!({"gang_size": 0}),
+            StatusCode::BAD_REQUEST,
+            "invalid_resources",
+        ),
+        (
+            json!({"resources": {"profile": "9g.90gb"}}),
+            StatusCode::BAD_REQUEST,
+            "invalid_resources",
+        ),
+        (
+            json!({"gang_size": 3}),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "unsatisfiable_resources",
+        ),
+        (
+            json!({"gang_size": u32::MAX}),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "unsatisfiable_resources",
+        ),
+        (
+            json!({"resources": {"profile": "2g.20gb"}}),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "exceeds_quota",
+        ),
+        (
+            json!({"capabilities": ["telepathy"]}),
+            StatusCode::BAD_REQUEST,
+            "unknown_capabilities",
+        ),
+        (
+            json!({"capabilities": ["gpu.compute"]}),
+            StatusCode::FORBIDDEN,
+            "unpermitted_capabilities",
+        ),
+        (
+            json!({"project_id": "missing"}),
+            StatusCode::BAD_REQUEST,
+            "invalid_project",
+        ),
+        (
+            json!({
+                "module_id": "tensor-scale",
+                "tensors": {"x": {"dtype": "f32", "shape": [3], "data": [1, 2, 3]}},
+            }),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_tensors",
+        ),
+    ];
+
+    for (extra, expected_status, expected_error) in cases {
+        let (status, body) = harness
+            .submit_with("alice", "simple-compute", &[], extra.clone())
+            .await;
+        assert_eq!(status, expected_status, "{} -> {}", extra, body);
+        assert_eq!(error_code(&body), expected_error, "{}", extra);
+    }
+
+    let (status, body) = harness
+        .get("/jobs/00000000-0000-0000-0000-000000000000")
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error_code(&body), "job_not_found");
+}
+
+#[tokio::test]
+async fn missing_module_fails_the_job() {
+    let harness = Harness::start(
+        &config(8, &[2], "127.0.0.1:1"),
+        json!({"tenants": [tenant("alice", &[], 1)]}),
+    );
+
+    let job_id = harness.accepted("alice", "does-not-exist", &[]).await;
+    let job = harness.wait_for(&job_id).await;
+
+    assert_eq!(status_name(&job), "failed");
+    assert!(
+        job["status"]["failed"]
+            .as_str()
+            .unwrap()
+            .contains("Module not found"),
+        "{}",
+        job["status"]
+    );
+}
+
+#[tokio::test]
+async fn cancelling_a_running_job_interrupts_its_guest() {
+    let harness = Harness::start(
+        &config(8, &[1], "127.0.0.1:1"),
+        json!({"tenants": [tenant("alice", &[], 1)]}),
+    );
+    harness.install(
+        "spin",
+        r#"(module (func (export "run") (result i32) (loop $spin (br $spin)) i32.const 0))"#,
+    );
+
+    let job_id = harness.accepted("alice", "spin", &[]).await;
+    harness.wait_for_status(&job_id, "running").await;
+
+    let (status, job) = harness
+        .request(Method::POST, &format!("/jobs/{}/cancel", job_id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", job);
+    assert_eq!(status_name(&job), "cancelled");
+
+    // The slice is free once the guest has stopped
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    loop {
+        let (_, reservations) = harness.get("/gpu/reservations").await;
+        if reservations.as_array().unwrap().is_empty() {
+            break;
+        }
+        assert!(tokio::time::Instant::now() < deadline, "{}", reservations);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let job = harness.job(&job_id).await;
+    assert_eq!(status_name(&job), "cancelled");
+    assert!(job["result"].is_null());
+
+    let (status, body) = harness
+        .request(Method::POST, &format!("/jobs/{}/cancel", job_id), None)
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(error_code(&body), "job_not_cancellable");
+}
+
+#[tokio::test]
+async fn rate_limit_rejects_excess_submissions() {
+    let mut limited = tenant("alice", &[], 1);
+    limited["rate_limit"] = json!(2);
+    let harness = Harness::start(
+        &config(8, &[2], "127.0.0.1:1"),
+        json!({"tenants": [limited, tenant("bob", &[], 1)]}),
+    );
+    harness.install_example("simple-compute");
+
+    for _ in 0..2 {
+        harness.accepted("alice", "simple-compute", &[]).await;
+    }
+    let (status, body) = harness.submit("alice", "simple-compute", &[]).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(error_code(&body), "rate_limit_exceeded");
+
+    // Other tenants have their own window
+    harness.accepted("bob", "simple-compute", &[]).await;
+}
+
+#[tokio::test]
+async fn full_queue_rejects_submissions() {
+    let gate = Gate::start();
+    let harness = Harness::start(
+        &config(1, &[1], &gate.host()),
+        json!({"tenants": [tenant("alice", &["network.egress"], 1)]}),
+    );
+    harness.install("blocker", &egress_module(&gate.url()));
+    harness.install_example("simple-compute");
+
+    // Holds the only slot
+    let blocker = harness
+        .accepted("alice", "blocker", &["network.egress"])
+        .await;
+    harness.wait_for_status(&blocker, "running").await;
+
+    // queue_length counts every queued job, whether the dispatcher has
+    // picked it up or not
+    let pending = harness.accepted("alice", "simple-compute", &[]).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let (status, body) = harness.submit("alice", "simple-compute", &[]).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(error_code(&body), "queue_full");
+
+    gate.open();
+    for job_id in [blocker, pending] {
+        assert_eq!(status_name(&harness.wait_for(&job_id).await), "finished");
+    }
+}
+
+#[tokio::test]
+async fn tenant_slot_limit_keeps_jobs_queued() {
+    let gate = Gate::start();
+    let harness = Harness::start(
+        &config(8, &[4], &gate.host()),
+        json!({"tenants": [
+            tenant("alice", &["network.egress"], 1),
+            tenant("bob", &["network.egress"], 2),
+        ]}),
+    );
+    harness.install("blocker", &egress_module(&gate.url()));
+
+    let first = harness
+        .accepted("alice", "blocker", &["network.egress"])
+        .await;
+    harness.wait_for_status(&first, "running").await;
+    let second = harness
+        .accepted("alice", "blocker", &["network.egress"])
+        .await;
+
+    // Free capacity is left, but alice's single slice is taken
+    let bobs = harness
+        .accepted("bob", "blocker", &["network.egress"])
+        .await;
+    harness.wait_for_status(&bobs, "running").await;
+    assert_eq!(status_name(&harness.job(&second).await), "queued");
+
+    let (_, usage) = harness.get("/gpu").await;
+    assert_eq!(usage["devices"][0]["used"]["compute_slices"], 2);
+
+    gate.open();
+    for job_id in [first, second, bobs] {
+        assert_eq!(status_name(&harness.wait_for(&job_id).await), "finished");
+    }
+}
+
+#[tokio::test]
+async fn tenants_are_not_blocked_by_each_others_backlog() {
+    let gate = Gate::start();
+    let harness = Harness::start(
+        &config(8, &[2], &gate.host()),
+        json!({"tenants": [
+            tenant("alice", &["network.egress"], 1),
+            tenant("bob", &["network.egress"], 1),
+        ]}),
+    );
+    harness.install("blocker", &egress_module(&gate.url()));
+
+    // alice's backlog is older, but only one of it can run at a time
+    let mut alices = Vec::new();
+    for _ in 0..3 {
+        alices.push(
+            harness
+                .accepted("alice", "blocker", &["network.egress"])
+                .await,
+        );
+    }
+    harness.wait_for_status(&alices[0], "running").await;
+
+    let bobs = harness
+        .accepted("bob", "blocker", &["network.egress"])
+        .await;
+    harness.wait_for_status(&bobs, "running").await;
+    for job_id in &alices[1..] {
+        assert_eq!(status_name(&harness.job(job_id).await), "queued");
+    }
+
+    gate.open();
+    for job_id in alices.iter().chain([&bobs]) {
+        assert_eq!(status_name(&harness.wait_for(job_id).await), "finished");
+    }
+}
+
+#[tokio::test]
+async fn shutdown_rejects_submissions_and_readiness() {
+    let harness = Harness::start(
+        &config(8, &[2], "127.0.0.1:1"),
+        json!({"tenants": [tenant("alice", &[], 1)]}),
+    );
+    harness.install_example("simple-compute");
+
+    harness.wait_ready().await;
+    harness.state.begin_shutdown();
+
+    let (status, body) = harness.submit("alice", "simple-compute", &[]).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(error_code(&body), "shutting_down");
+
+    let (status, body) = harness.get("/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["checks"]["shutdown"]["ok"], false);
+}
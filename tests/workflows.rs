@@ -0,0 +1,281 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use serde_json::{Value, json};
+
+use common::{Gate, Harness, config, egress_module, error_code, status_name, tenant};
+
+/// Traps as soon as it runs.
+const TRAP: &str = r#"(module
+  (memory (export "memory") 1)
+  (func (export "run") (result i32) unreachable))"#;
+
+fn harness(allowed_host: &str) -> Harness {
+    let harness = Harness::start(
+        &config(8, &[4], allowed_host),
+        json!({ "tenants": [
+            tenant("alice", &["network.egress", "wasi"], 4),
+            tenant("bob", &[], 1),
+        ]}),
+    );
+    harness.install_example("simple-compute");
+    harness
+}
+
+fn step(name: &str, after: &[&str], module_id: &str, extra: Value) -> Value {
+    let mut step = json!({
+        "name": name,
+        "after": after,
+        "tenant_id": "alice",
+        "module_id": module_id,
+        "payload": {},
+        "capabilities": [],
+    });
+    if let (Some(step), Value::Object(extra)) = (step.as_object_mut(), extra) {
+        step.extend(extra);
+    }
+    step
+}
+
+async fn submit_workflow(harness: &Harness, steps: Vec<Value>) -> (StatusCode, Value) {
+    harness
+        .request(Method::POST, "/workflows", Some(json!({ "steps": steps })))
+        .await
+}
+
+async fn workflow_status(harness: &Harness, workflow_id: &str) -> Value {
+    let (status, body) = harness.get(&format!("/workflows/{}", workflow_id)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body
+}
+
+#[tokio::test]
+async fn steps_wait_for_the_steps_they_run_after() {
+    let gate = Gate::start();
+    let harness = harness(&gate.host());
+    harness.install("upload", &egress_module(&gate.url()));
+
+    let (status, body) = submit_workflow(
+        &harness,
+        vec![
+            step("report", &["upload"], "simple-compute", json!({})),
+            step(
+                "upload",
+                &[],
+                "upload",
+                json!({"capabilities": ["network.egress"]}),
+            ),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    let workflow_id = body["workflow_id"].as_str().unwrap();
+    let upload = body["steps"]["upload"].as_str().unwrap();
+    let report = body["steps"]["report"].as_str().unwrap();
+
+    // The upload is held by the gate, so the report can't start
+    harness.wait_for_status(upload, "running").await;
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(status_name(&harness.job(report).await), "queued");
+    let workflow = workflow_status(&harness, workflow_id).await;
+    assert_eq!(workflow["status"], "running");
+    assert_eq!(workflow["steps"]["report"]["job_id"], report);
+
+    gate.open();
+    let job = harness.wait_for(report).await;
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    assert_eq!(job["workflow_id"], workflow_id);
+    assert_eq!(job["depends_on"], json!([upload]));
+    assert_eq!(
+        workflow_status(&harness, workflow_id).await["status"],
+        "finished"
+    );
+}
+
+#[tokio::test]
+async fn failed_parents_cancel_or_fail_their_children() {
+    let harness = harness("127.0.0.1:1");
+    harness.install("trap", TRAP);
+    let parent = harness.accepted("alice", "trap", &[]).await;
+
+    let (_, cancelled) = harness
+        .submit_with(
+            "alice",
+            "simple-compute",
+            &[],
+            json!({"depends_on": [parent]}),
+        )
+        .await;
+    let (_, failed) = harness
+        .submit_with(
+            "alice",
+            "simple-compute",
+            &[],
+            json!({"depends_on": [parent], "on_parent_failure": "fail"}),
+        )
+        .await;
+
+    let job = harness
+        .wait_for(cancelled["job_id"].as_str().unwrap())
+        .await;
+    assert_eq!(status_name(&job), "cancelled");
+    let job = harness.wait_for(failed["job_id"].as_str().unwrap()).await;
+    assert_eq!(status_name(&job), "failed");
+    assert_eq!(
+        job["status"]["failed"],
+        format!("Parent job {} did not finish", parent)
+    );
+}
+
+#[tokio::test]
+async fn outputs_are_passed_to_children() {
+    let harness = harness("127.0.0.1:1");
+    harness.install_tensor_modules();
+
+    let x = json!({"x": {"dtype": "f32", "shape": [1, 4], "data": [1, 2, 3, 4]}});
+    let (status, body) = submit_workflow(
+        &harness,
+        vec![
+            step("scale", &[], "tensor-scale", json!({"tensors": x})),
+            step(
+                "rescale",
+                &["scale"],
+                "tensor-rescale",
+                json!({"pass_outputs": true}),
+            ),
+            // Gets "y", which tensor-scale doesn't take
+            step(
+                "mismatch",
+                &["scale"],
+                "tensor-scale",
+                json!({"pass_outputs": true}),
+            ),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    let scale = body["steps"]["scale"].as_str().unwrap();
+
+    let job = harness
+        .wait_for(body["steps"]["rescale"].as_str().unwrap())
+        .await;
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    assert_eq!(
+        job["result"]["tensors"]["z"],
+        json!({"dtype": "f32", "shape": [1, 4], "data": [4.0, 8.0, 12.0, 16.0]})
+    );
+    assert!(
+        job["payload"]["parent_outputs"][scale].is_string(),
+        "{}",
+        job
+    );
+
+    let job = harness
+        .wait_for(body["steps"]["mismatch"].as_str().unwrap())
+        .await;
+    assert_eq!(status_name(&job), "failed");
+    assert!(
+        job["status"]["failed"]
+            .as_str()
+            .unwrap()
+            .contains("no input called y"),
+        "{}",
+        job["status"]
+    );
+    assert_eq!(
+        workflow_status(&harness, body["workflow_id"].as_str().unwrap()).await["status"],
+        "failed"
+    );
+}
+
+#[tokio::test]
+async fn parent_output_is_passed_on_unchanged() {
+    let harness = harness("127.0.0.1:1");
+    harness.install("binary", include_str!("fixtures/binary.wat"));
+
+    let (status, body) = submit_workflow(
+        &harness,
+        vec![
+            step("binary", &[], "binary", json!({"capabilities": ["wasi"]})),
+            step(
+                "report",
+                &["binary"],
+                "simple-compute",
+                json!({"pass_outputs": true}),
+            ),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    let binary = body["steps"]["binary"].as_str().unwrap();
+
+    let job = harness
+        .wait_for(body["steps"]["report"].as_str().unwrap())
+        .await;
+    assert_eq!(status_name(&job), "finished", "{}", job["status"]);
+    // ff 00 c3 28, base64-encoded
+    assert_eq!(job["payload"]["parent_outputs"][binary], "/wDDKA==");
+}
+
+#[tokio::test]
+async fn invalid_workflows_queue_nothing() {
+    let harness = harness("127.0.0.1:1");
+    let compute = |name, after| step(name, after, "simple-compute", json!({}));
+
+    for steps in [
+        vec![],
+        vec![compute("a", &["b"]), compute("b", &["a"])],
+        vec![compute("a", &["missing"])],
+        vec![compute("a", &[]), compute("a", &[])],
+    ] {
+        let (status, body) = submit_workflow(&harness, steps).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(error_code(&body), "invalid_workflow");
+    }
+
+    // A rejected step rejects the workflow with that step's answer
+    let (status, body) = submit_workflow(
+        &harness,
+        vec![
+            compute("a", &[]),
+            step(
+                "b",
+                &["a"],
+                "simple-compute",
+                json!({"capabilities": ["telepathy"]}),
+            ),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    assert_eq!(error_code(&body), "unknown_capabilities");
+    assert!(body["message"].as_str().unwrap().starts_with("Step b: "));
+
+    let (_, body) = harness.get("/jobs/list").await;
+    assert_eq!(body["jobs"], json!([]));
+}
+
+#[tokio::test]
+async fn dependencies_must_be_known_jobs_of_the_tenant() {
+    let harness = harness("127.0.0.1:1");
+    let bobs = harness.accepted("bob", "simple-compute", &[]).await;
+
+    for parent in [uuid::Uuid::new_v4().to_string(), bobs] {
+        let (status, body) = harness
+            .submit_with(
+                "alice",
+                "simple-compute",
+                &[],
+                json!({"depends_on": [parent]}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(error_code(&body), "unknown_dependency");
+    }
+
+    let (status, body) = harness
+        .get(&format!("/workflows/{}", uuid::Uuid::new_v4()))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error_code(&body), "workflow_not_found");
+}